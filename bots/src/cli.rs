@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use realmlib::config;
+
+/* cli.rs - Command line arguments for the bots binary */
+
+#[derive(Parser, Debug)]
+#[command(name = "bots", version, about = "Runs and manages realmlib bots")]
+pub struct Cli {
+    #[command(flatten)]
+    pub settings: SettingsArgs,
+    /// Defaults to `run` with the accounts picked by the config's index and amount
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Launch bots
+    Run(RunArgs),
+    /// Validate the settings and accounts files without connecting
+    Check,
+    /// Pretty print a captured packet hex dump
+    Decode(DecodeArgs),
+    /// Show the accounts
+    List,
+    /// Show the saved bot snapshots
+    Snapshots,
+}
+
+/// Where the config comes from, plus overrides for single settings. Overrides win over the environment
+#[derive(Args, Debug)]
+pub struct SettingsArgs {
+    /// Settings file (json, toml or yaml)
+    #[arg(short, long, global = true, default_value = config::DEFAULT_SETTINGS_PATH)]
+    pub config: PathBuf,
+    /// Accounts file (json, toml or yaml)
+    #[arg(short, long, global = true, default_value = config::DEFAULT_ACCOUNTS_PATH)]
+    pub accounts: PathBuf,
+    /// Override any setting, `--set reconnect.jitter=0.1`. Can be repeated
+    #[arg(long = "set", value_name = "FIELD=VALUE", global = true, value_parser = parse_override)]
+    pub overrides: Vec<(String, String)>,
+    /// Same as `--set amount=N`
+    #[arg(long, global = true)]
+    pub amount: Option<usize>,
+    /// Same as `--set index=N`
+    #[arg(long, global = true)]
+    pub index: Option<usize>,
+    /// Same as `--set game_version=X`
+    #[arg(long, global = true)]
+    pub game_version: Option<String>,
+    /// Same as `--set logging.filter=FILTER`, like `info,realmlib::network=trace`
+    #[arg(long, value_name = "FILTER", global = true)]
+    pub log: Option<String>,
+}
+
+impl SettingsArgs {
+    pub fn paths(&self) -> config::ConfigPaths {
+        config::ConfigPaths {
+            settings: self.config.clone(),
+            accounts: self.accounts.clone(),
+        }
+    }
+
+    /// The override variables for the config loader, in the order they should be applied
+    pub fn override_vars(&self) -> Vec<(String, String)> {
+        let mut vars: Vec<(String, String)> = self
+            .overrides
+            .iter()
+            .map(|(f, v)| config::override_var(f, v))
+            .collect();
+        if let Some(a) = self.amount {
+            vars.push(config::override_var("amount", &a.to_string()));
+        }
+        if let Some(i) = self.index {
+            vars.push(config::override_var("index", &i.to_string()));
+        }
+        if let Some(v) = &self.game_version {
+            vars.push(config::override_var("game_version", v));
+        }
+        if let Some(f) = &self.log {
+            vars.push(config::override_var("logging.filter", f));
+        }
+        vars
+    }
+}
+
+fn parse_override(s: &str) -> Result<(String, String), String> {
+    match s.find('=') {
+        Some(i) if i > 0 => Ok((s[..i].trim().to_owned(), s[i + 1..].to_owned())),
+        _ => Err(format!("expected FIELD=VALUE, got {}", s)),
+    }
+}
+
+#[derive(Args, Debug, Default)]
+pub struct RunArgs {
+    /// Accounts to launch: an index (3), a range (2-5), an email or a tag (tag:farm)
+    #[arg(value_name = "ACCOUNT")]
+    pub select: Vec<String>,
+    /// Launch every account in the accounts file
+    #[arg(long, conflicts_with = "select")]
+    pub all: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DecodeArgs {
+    /// The hex dump, read from --file or stdin when left out
+    pub hex: Vec<String>,
+    /// Read the hex dump from a file
+    #[arg(short, long, conflicts_with = "hex")]
+    pub file: Option<PathBuf>,
+    /// The dump is still rc4 encrypted with the constant keys
+    #[arg(short, long)]
+    pub decrypt: bool,
+    /// The packets were sent by the client instead of the server
+    #[arg(long)]
+    pub client: bool,
+}
@@ -1,18 +1,306 @@
-use std::thread;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::panic;
+use std::process;
 use std::time;
 
+use clap::Parser;
+use tracing::{error, info};
+
 use realmlib;
+use realmlib::account;
+use realmlib::config;
+use realmlib::control;
+use realmlib::logging;
+use realmlib::manager;
+use realmlib::metrics;
+use realmlib::network::packets;
+use realmlib::network::pool;
+use realmlib::network::proxy;
+use realmlib::servers;
+use realmlib::snapshot;
+use realmlib::tiles;
+use realmlib::utils;
+
+mod cli;
 
 fn main() {
-    let config = realmlib::Config::new();
-    let index = config.index;
-    let amount = config.amount;
-    let mut clients = realmlib::accounts_to_clients(realmlib::read_accounts(), config);
-    for _ in index..amount {
-        realmlib::launch_client(clients.remove(index));
+    let args = cli::Cli::parse();
+    let code = match args.command {
+        Some(cli::Command::Run(ref run)) => run_bots(&args.settings, run),
+        Some(cli::Command::Check) => check(&args.settings),
+        Some(cli::Command::Decode(ref decode)) => decode_dump(decode),
+        Some(cli::Command::List) => list(&args.settings),
+        Some(cli::Command::Snapshots) => snapshots(&args.settings),
+        None => run_bots(&args.settings, &cli::RunArgs::default()),
+    };
+    process::exit(code);
+}
+
+/// Loads and validates both files, printing the problems if there are any
+fn load(settings: &cli::SettingsArgs) -> Option<(realmlib::Config, Vec<account::Account>)> {
+    let vars = env::vars().chain(settings.override_vars());
+    match config::load_with_env(&settings.paths(), vars) {
+        Ok(loaded) => Some(loaded),
+        Err(e) => {
+            eprintln!("{}", e);
+            None
+        }
+    }
+}
+
+fn run_bots(settings: &cli::SettingsArgs, run: &cli::RunArgs) -> i32 {
+    let (config, accounts) = match load(settings) {
+        Some(loaded) => loaded,
+        None => return 1,
+    };
+    let picked: Vec<usize> = if run.all {
+        (0..accounts.len()).collect()
+    } else if run.select.is_empty() {
+        (config.index..config.amount).collect()
+    } else {
+        match account::select_accounts(&accounts, &run.select) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        }
+    };
+    if let Err(e) = logging::init(&config.logging) {
+        eprintln!("couldn't set up logging: {}", e);
+        return 1;
+    }
+    let stop = match manager::install_signal_handler() {
+        Ok(s) => s,
+        Err(e) => {
+            error!("couldn't install the signal handler: {}", e);
+            return 1;
+        }
+    };
+    let mut bots = manager::BotManager::new(config.supervisor.clone());
+    let control_settings = config.control.clone();
+    let selected = picked.iter().map(|i| accounts[*i].clone()).collect();
+    for client in realmlib::accounts_to_clients(selected, config) {
+        bots.add(client);
+    }
+    let _control = if control_settings.enabled {
+        let api = control::Api {
+            targets: bots.control_targets(),
+            metrics: metrics::global(),
+        };
+        match control::ControlServer::start(&control_settings.bind, api) {
+            Ok(server) => {
+                info!("Control server listening on {}", control_settings.bind);
+                Some(server)
+            }
+            Err(e) => {
+                error!(
+                    "couldn't start the control server on {}: {}",
+                    control_settings.bind, e
+                );
+                return 1;
+            }
+        }
+    } else {
+        None
+    };
+    if let Err(e) = bots.start_all() {
+        error!("couldn't launch the bots: {}", e);
+        bots.shutdown();
+        return 1;
+    }
+    bots.run_until(&stop, time::Duration::from_millis(500));
+    0
+}
+
+fn check(settings: &cli::SettingsArgs) -> i32 {
+    let (config, accounts) = match load(settings) {
+        Some(loaded) => loaded,
+        None => return 1,
+    };
+    println!("{} is valid", settings.config.display());
+    println!(
+        "{} is valid, {} accounts",
+        settings.accounts.display(),
+        accounts.len()
+    );
+    let mut code = 0;
+    if !config.server_list_file.is_empty() {
+        match servers::ServerList::load(&config.server_list_file) {
+            Ok(list) => println!("{} has {} servers", config.server_list_file, list.len()),
+            Err(e) => {
+                eprintln!("couldn't load {}: {}", config.server_list_file, e);
+                code = 1;
+            }
+        }
     }
+    if !config.tiles_file.is_empty() {
+        match tiles::TileDefs::load(&config.tiles_file) {
+            Ok(defs) => println!("{} has {} tiles", config.tiles_file, defs.len()),
+            Err(e) => {
+                eprintln!("couldn't load {}: {}", config.tiles_file, e);
+                code = 1;
+            }
+        }
+    }
+    if !config.proxy_pool.file.is_empty() {
+        match pool::ProxyPool::load(config.proxy_pool.clone()) {
+            Ok(p) => println!("{} has {} proxies", config.proxy_pool.file, p.len()),
+            Err(e) => {
+                eprintln!("couldn't load {}: {}", config.proxy_pool.file, e);
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+fn list(settings: &cli::SettingsArgs) -> i32 {
+    let (config, accounts) = match load(settings) {
+        Some(loaded) => loaded,
+        None => return 1,
+    };
+    println!(
+        "{:>5}  {:<32} {:<18} {:>7}  {:<24} TAGS",
+        "INDEX", "EMAIL", "SERVER", "CHAR", "PROXY"
+    );
+    for (i, a) in accounts.iter().enumerate() {
+        let server = if a.server.is_empty() {
+            &a.server_ip
+        } else {
+            &a.server
+        };
+        //Display leaves the password out
+        let proxy = if a.use_socks {
+            proxy::Proxy::parse(&a.socks_proxy).map(|p| p.to_string())
+        } else if a.use_http {
+            proxy::Proxy::parse_or(&a.http_proxy, proxy::ProxyKind::Http).map(|p| p.to_string())
+        } else {
+            Ok(String::from("-"))
+        };
+        let marker = if i >= config.index && i < config.amount {
+            "*"
+        } else {
+            " "
+        };
+        println!(
+            "{:>4}{} {:<32} {:<18} {:>7}  {:<24} {}",
+            i,
+            marker,
+            a.email,
+            server,
+            a.char_id,
+            proxy.unwrap_or_else(|e| e),
+            a.tags.join(",")
+        );
+    }
+    println!("* launched by a plain `run`");
+    0
+}
 
-    loop {
-        thread::sleep(time::Duration::from_secs(120));
+fn snapshots(settings: &cli::SettingsArgs) -> i32 {
+    let (config, _) = match load(settings) {
+        Some(loaded) => loaded,
+        None => return 1,
+    };
+    let store = match snapshot::SnapshotStore::from_settings(&config.snapshots) {
+        Some(s) => s,
+        None => {
+            eprintln!("snapshots are disabled, snapshots.dir is empty");
+            return 1;
+        }
+    };
+    let all = match store.load_all() {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("couldn't read {}: {}", store.dir.display(), e);
+            return 1;
+        }
+    };
+    println!(
+        "{:<32} {:<12} {:>7} {:<16} {:<18} {:>6} {:>6} {:>10} SAVED",
+        "EMAIL", "IGN", "CHAR", "MAP", "SERVER", "FAME", "DEATHS", "RECONNECTS"
+    );
+    let now = snapshot::unix_now();
+    for s in all.iter() {
+        println!(
+            "{:<32} {:<12} {:>7} {:<16} {:<18} {:>6} {:>6} {:>10} {}s ago",
+            s.email,
+            s.ign,
+            s.char_id,
+            s.map,
+            s.server,
+            s.fame,
+            s.deaths,
+            s.reconnects.len(),
+            now.saturating_sub(s.saved_at)
+        );
     }
+    0
+}
+
+fn decode_dump(args: &cli::DecodeArgs) -> i32 {
+    let text = if let Some(path) = &args.file {
+        match fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("couldn't read {}: {}", path.display(), e);
+                return 1;
+            }
+        }
+    } else if !args.hex.is_empty() {
+        args.hex.join(" ")
+    } else {
+        let mut t = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut t) {
+            eprintln!("couldn't read stdin: {}", e);
+            return 1;
+        }
+        t
+    };
+    let mut packets = match utils::decode_hex(&text).and_then(|d| packets::split_packets(&d)) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    if args.decrypt {
+        packets::decrypt_packets(&mut packets, !args.client);
+    }
+    //the parsers panic on truncated packets, which describe_server_packet reports on its own
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    for (i, p) in packets.into_iter().enumerate() {
+        let id = p.data[4];
+        println!(
+            "#{} {} (id {}, {} bytes)",
+            i,
+            packets::packet_name(id).unwrap_or("UNKNOWN"),
+            id,
+            p.data.len()
+        );
+        let described = if args.client {
+            None
+        } else {
+            packets::describe_server_packet(p.clone())
+        };
+        match described {
+            Some(Ok(text)) => println!("{}", text),
+            Some(Err(e)) => println!("{}: {}", e, hex_string(&p.data[5..])),
+            None => println!("{}", hex_string(&p.data[5..])),
+        }
+    }
+    panic::set_hook(hook);
+    0
+}
+
+fn hex_string(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(" ")
 }
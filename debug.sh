@@ -1 +1 @@
-cargo build && cp ./target/debug/bots ./botz && ./botz
+cargo build && cp ./target/debug/bots ./botz && ./botz "$@"
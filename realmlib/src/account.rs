@@ -1,7 +1,9 @@
-use std::fs::File;
-use std::io::BufReader;
+use std::sync::Arc;
+use std::time;
 
 use super::client;
+use super::servers;
+use super::web;
 
 extern crate serde;
 use serde::{Deserialize, Serialize};
@@ -18,18 +20,43 @@ pub struct Account {
     pub socks_proxy: String,
     pub use_http: bool,
     pub http_proxy: String,
+    #[serde(default)]
+    pub server: String, //a server name like "USEast" or "least_populated", used instead of server_ip when set
+    #[serde(default)]
+    pub rotate_servers: bool, //move on to the next server when connecting fails
+    #[serde(default)]
+    pub tags: Vec<String>, //free form labels for picking groups of accounts, "farm", "mules"
 }
 
-trait IAccount {
-    fn get_new_char_id(&mut self);
-    fn get_url(s: &str) -> String;
+pub trait IAccount {
+    fn get_new_char_id(&mut self) -> Result<(), web::WebError>;
+    fn get_url(&self, path: &str) -> String;
 }
 
 /// This trait moves the 'Account' struct methods to the 'Client'
 impl IAccount for client::Client {
-    fn get_new_char_id(&mut self) {}
-    fn get_url<'a>(s: &'a str) -> String {
-        String::from(s)
+    /// Fetches the char list and fills in 'char_id' and 'server_ip' from it
+    fn get_new_char_id(&mut self) -> Result<(), web::WebError> {
+        let timeout = time::Duration::from_millis(self.config.connect_timeout_ms.max(1));
+        let list = web::fetch_char_list(
+            &self.config.api_base_url,
+            &self.base.email,
+            &self.base.password,
+            timeout,
+        )?;
+        if let Some(id) = list.first_char_id() {
+            self.base.char_id = id;
+        }
+        if !list.servers.is_empty() {
+            self.servers = Some(Arc::new(servers::ServerList::from_char_list(&list)));
+        }
+        self.select_server();
+        self.account_data = Some(list);
+        Ok(())
+    }
+
+    fn get_url(&self, path: &str) -> String {
+        web::api_url(&self.config.api_base_url, path)
     }
 }
 
@@ -40,9 +67,55 @@ impl Account {
     }
 }
 
-pub fn read_accounts() -> Vec<Account> {
-    let a: Vec<Account> =
-        serde_json::from_reader(BufReader::new(File::open("config/accounts.json").unwrap()))
-            .unwrap();
-    a
+/// Picks accounts by index ("3"), inclusive index range ("2-5"), email or tag ("tag:farm").
+/// Returns the indexes in the order they were asked for, without duplicates
+pub fn select_accounts(accounts: &[Account], selectors: &[String]) -> Result<Vec<usize>, String> {
+    let mut picked: Vec<usize> = Vec::new();
+    for sel in selectors {
+        let sel = sel.trim();
+        let matches: Vec<usize> = if let Some(tag) = sel.strip_prefix("tag:") {
+            accounts
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| a.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+                .map(|(i, _)| i)
+                .collect()
+        } else if let Ok(i) = sel.parse::<usize>() {
+            vec![i]
+        } else if let Some((start, end)) = parse_range(sel) {
+            (start..=end).collect()
+        } else {
+            accounts
+                .iter()
+                .position(|a| a.email.eq_ignore_ascii_case(sel))
+                .into_iter()
+                .collect()
+        };
+        if matches.is_empty() {
+            return Err(format!("{} doesn't match any account", sel));
+        }
+        if let Some(i) = matches.iter().find(|i| **i >= accounts.len()) {
+            return Err(format!(
+                "{} is out of range, there are {} accounts",
+                i,
+                accounts.len()
+            ));
+        }
+        for i in matches {
+            if !picked.contains(&i) {
+                picked.push(i);
+            }
+        }
+    }
+    Ok(picked)
+}
+
+fn parse_range(s: &str) -> Option<(usize, usize)> {
+    let mut parts = s.splitn(2, '-');
+    let start = parts.next()?.trim().parse().ok()?;
+    let end = parts.next()?.trim().parse().ok()?;
+    if start > end {
+        return None;
+    }
+    Some((start, end))
 }
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::client;
+use super::network::packets::client_packets;
+use super::network::packets::client_packets::ClientPacket;
+use super::network::packets::client_packets::ClientPackets;
+use super::network::packets::server_packets;
+use super::network::types;
+use super::network::types::Stats;
+use super::pets::Payment;
+
+/* arena.rs - The arena: enter it from the nexus, fight the waves and get back out */
+
+/// The map name the server gives the arena
+pub const ARENA_MAP: &str = "Arena";
+
+/// Arena settings, under "arena" in the settings file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ArenaSettings {
+    pub enabled: bool,       //enter the arena every time the bot is in the nexus
+    pub currency: Payment,   //what the entry is paid with, "gold" or "fame"
+    pub max_death_cost: i32, //pay up to this to keep going after an arena death, 0 never pays
+    pub shoot_range: f32,    //tiles
+    pub max_runs: u32,       //runs ``enabled`` enters on its own per session, 0 for no limit
+}
+
+impl Default for ArenaSettings {
+    fn default() -> ArenaSettings {
+        ArenaSettings {
+            enabled: false,
+            currency: Payment::Fame,
+            max_death_cost: 0,
+            shoot_range: 8.0,
+            max_runs: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Arena {
+    pub entering: bool, //an EnterArena went out and the server hasn't moved us yet
+    pub active: bool,   //we're on the arena map
+    pub wave: u32,      //waves announced on this run
+    pub best_wave: u32,
+    pub runs: u32,
+}
+
+impl Arena {
+    pub fn new() -> Arena {
+        Arena::default()
+    }
+}
+
+impl client::Client {
+    /// Pays the entry and asks the server to take us to the arena. Only works from the nexus
+    pub fn enter_arena(&mut self) {
+        if self.current_map != "Nexus" || self.arena.entering {
+            return;
+        }
+        info!("entering the arena");
+        let mut ea = client_packets::EnterArena::new();
+        ea.currency = i32::from(self.config.arena.currency.to_byte());
+        self.arena.entering = true;
+        self.send(ClientPackets::EnterArenaPacket(ea).write());
+    }
+    /// ``enter_arena`` for ``arena.enabled``, until ``arena.max_runs`` runs are done. Every entry is paid for,
+    /// so a bot that dies or escapes out of the arena doesn't keep buying its way back in
+    pub fn auto_enter_arena(&mut self) {
+        if !self.config.arena.enabled {
+            return;
+        }
+        let max_runs = self.config.arena.max_runs;
+        if max_runs > 0 && self.arena.runs >= max_runs {
+            return;
+        }
+        self.enter_arena();
+    }
+    /// Keeps the arena state in step with the map we joined
+    pub fn arena_joined(&mut self) {
+        let in_arena = self.current_map == ARENA_MAP;
+        if in_arena && !self.arena.active {
+            self.arena.runs += 1;
+            self.arena.wave = 0;
+        }
+        self.arena.entering = false;
+        self.arena.active = in_arena;
+    }
+    /// The closest enemy in sight. Anything with hp that isn't us, a player or a pet
+    pub fn nearest_enemy(&self) -> Option<types::WorldPosition> {
+        let here = self.movement.current_position;
+        self.objects
+            .entities
+            .values()
+            .map(|o| &o.status)
+            .filter(|s| s.object_id != self.object_id)
+            .filter(|s| s.stats.contains_key(&Stats::MAXIMUMHP.stat_to_u8()))
+            .filter(|s| !s.stats.contains_key(&Stats::ACCOUNTID.stat_to_u8()))
+            .filter(|s| !s.stats.contains_key(&Stats::PETINSTANCEID.stat_to_u8()))
+            .map(|s| s.position)
+            .min_by(|a, b| here.distance_to(a).total_cmp(&here.distance_to(b)))
+    }
+    /// One tick in the arena: go for the closest enemy, or hold the middle between waves
+    pub fn fight_arena(&mut self) {
+        match self.nearest_enemy() {
+            Some(enemy) => self.engage(enemy, self.config.arena.shoot_range),
+            None => self.movement.target_position = self.arena_center(),
+        }
+    }
+    pub fn arena_center(&self) -> types::WorldPosition {
+        types::WorldPosition::new_fill(
+            self.movement.map_width as f32 / 2.0,
+            self.movement.map_height as f32 / 2.0,
+        )
+    }
+    pub fn arena_wave(&mut self, w: &server_packets::ImminentArenaWave) {
+        self.arena.wave += 1;
+        self.arena.best_wave = self.arena.best_wave.max(self.arena.wave);
+        info!(
+            "arena wave {} is coming (runtime {})",
+            self.arena.wave, w.current_runtime
+        );
+        //regroup in the middle until the wave shows up
+        self.movement.target_position = self.arena_center();
+    }
+    /// Pays to keep going if it's within ``arena.max_death_cost``, otherwise heads back to the nexus
+    pub fn arena_death(&mut self, d: &server_packets::ArenaDeath) {
+        if d.cost <= self.config.arena.max_death_cost {
+            info!(
+                "died in the arena on wave {}, paying {} to go on",
+                self.arena.wave, d.cost
+            );
+            self.send(
+                ClientPackets::AcceptArenaDeathPacket(client_packets::AcceptArenaDeath::new())
+                    .write(),
+            );
+            return;
+        }
+        warn!(
+            "died in the arena on wave {}, going back to the nexus",
+            self.arena.wave
+        );
+        self.leave_arena();
+    }
+    pub fn leave_arena(&mut self) {
+        self.arena.active = false;
+        self.send(ClientPackets::EscapePacket(client_packets::Escape::new()).write());
+    }
+}
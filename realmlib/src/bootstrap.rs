@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::client;
+use super::network::packets::client_packets;
+use super::network::packets::client_packets::ClientPacket;
+use super::network::packets::client_packets::ClientPackets;
+use super::network::packets::server_packets;
+use super::network::types::Stats;
+use super::web;
+
+/* bootstrap.rs - Takes a fresh account to a playable state: picks a name for it and creates its first character */
+
+/// Longest name the server takes
+pub const MAX_NAME_LEN: usize = 10;
+const CONSONANTS: &[u8] = b"bcdfghklmnprstvz";
+const VOWELS: &[u8] = b"aeiou";
+
+/// Account bootstrap settings, under "bootstrap" in the settings file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BootstrapSettings {
+    pub choose_name: bool,      //pick a name when the account doesn't have one yet
+    pub names: Vec<String>,     //tried in order before any generated names
+    pub max_name_attempts: u32, //ChooseNames sent before giving up, per connection
+    pub create_character: bool, //create a character when the account has none alive
+    pub class_type: u16,        //the class' object type, 0x030e is the wizard
+    pub skin_type: u16,         //0 for the class' default skin
+}
+
+impl Default for BootstrapSettings {
+    fn default() -> BootstrapSettings {
+        BootstrapSettings {
+            choose_name: false,
+            names: Vec::new(),
+            max_name_attempts: 5,
+            create_character: false,
+            class_type: 0x030e,
+            skin_type: 0,
+        }
+    }
+}
+
+/// Only letters, at most MAX_NAME_LEN of them
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= MAX_NAME_LEN && name.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+/// A pronounceable name, like "Rokavemi"
+pub fn generate_name() -> String {
+    let pick = |set: &[u8]| set[rand::random::<usize>() % set.len()] as char;
+    let syllables = 3 + rand::random::<usize>() % 2;
+    let mut name = String::with_capacity(syllables * 2);
+    for _ in 0..syllables {
+        name.push(pick(CONSONANTS));
+        name.push(pick(VOWELS));
+    }
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => name,
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bootstrap {
+    pub pending_name: Option<String>, //a ChooseName is out for it
+    pub tried: Vec<String>,           //names the server turned down
+    pub attempts: u32,
+    pub creating: bool, //a Create went out instead of a Load
+    pub named: bool,    //the server took one of our names, NAMECHOSEN can lag behind
+}
+
+impl Bootstrap {
+    pub fn new() -> Bootstrap {
+        Bootstrap::default()
+    }
+    /// Forgets the requests in flight, their answers went with the connection. Keeps ``named``
+    pub fn reset(&mut self) {
+        self.pending_name = None;
+        self.attempts = 0;
+        self.creating = false;
+    }
+}
+
+impl client::Client {
+    /// What the server says about our name, None until our stats arrive
+    pub fn name_chosen(&self) -> Option<bool> {
+        self.stats.get_item(Stats::NAMECHOSEN).map(|v| v != 0)
+    }
+    /// The next name from ``bootstrap.names`` nobody turned down yet, or a generated one
+    pub fn next_name(&self) -> String {
+        self.config
+            .bootstrap
+            .names
+            .iter()
+            .find(|n| valid_name(n) && !self.bootstrap.tried.contains(n))
+            .cloned()
+            .unwrap_or_else(generate_name)
+    }
+    /// Sends a ChooseName if the account needs a name and we're not waiting on one already
+    pub fn check_name(&mut self) {
+        if !self.config.bootstrap.choose_name || self.bootstrap.named {
+            return;
+        }
+        if self.name_chosen() != Some(false) {
+            return;
+        }
+        if self.bootstrap.pending_name.is_some() {
+            return;
+        }
+        if self.bootstrap.attempts >= self.config.bootstrap.max_name_attempts {
+            return;
+        }
+        let name = self.next_name();
+        info!("choosing the name {}", name);
+        self.bootstrap.attempts += 1;
+        self.bootstrap.pending_name = Some(name.clone());
+        let mut cn = client_packets::ChooseName::new();
+        cn.my_name = name;
+        self.send(ClientPackets::ChooseNamePacket(cn).write());
+    }
+    /// Takes the name on success, tries the next one on an error
+    pub fn name_result(&mut self, nr: &server_packets::NameResult) {
+        let name = match self.bootstrap.pending_name.take() {
+            Some(n) => n,
+            None => return,
+        };
+        if !nr.success {
+            warn!("the name {} was turned down: {}", name, nr.error_message);
+            self.bootstrap.tried.push(name);
+            if self.bootstrap.attempts >= self.config.bootstrap.max_name_attempts {
+                warn!("giving up on choosing a name");
+            }
+            self.check_name();
+            return;
+        }
+        info!("we're called {} now", name);
+        self.bootstrap.named = true;
+        self.ign = name;
+        self.span.record("ign", self.ign.as_str());
+    }
+    /// True if a Create should go out instead of a Load: the char list came back without a living character
+    pub fn needs_character(&self) -> bool {
+        self.config.bootstrap.create_character
+            && self
+                .account_data
+                .as_ref()
+                .is_some_and(|l| l.first_char_id().is_none())
+    }
+    pub fn create_character(&mut self) {
+        info!(
+            "the account has no character, creating a {:#06x}",
+            self.config.bootstrap.class_type
+        );
+        let mut c = client_packets::Create::new();
+        c.class_type = self.config.bootstrap.class_type;
+        c.skin_type = self.config.bootstrap.skin_type;
+        self.bootstrap.creating = true;
+        self.send(ClientPackets::CreatePacket(c).write());
+    }
+    /// Loads the new character from now on
+    pub fn character_created(&mut self, char_id: i32) {
+        self.bootstrap.creating = false;
+        self.base.char_id = char_id;
+        let class = self.config.bootstrap.class_type;
+        if let Some(list) = self.account_data.as_mut() {
+            list.chars.push(web::CharInfo {
+                id: char_id,
+                class,
+                ..web::CharInfo::default()
+            });
+        }
+        info!("created character {}", char_id);
+    }
+}
@@ -1,8 +1,14 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
 use std::time;
 
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, warn};
+
 use crate::account;
+use crate::metrics::Connection;
 use crate::network;
 use crate::network::packets::client_packets;
 use crate::network::packets::client_packets::ClientPacket;
@@ -33,6 +39,24 @@ pub struct Client {
     pub combat: CombatBase,
     pub goods: TradeBase,
     pub objects: GameObjects,
+    pub account_data: Option<crate::web::CharList>,
+    pub servers: Option<Arc<crate::servers::ServerList>>,
+    pub tiles: Option<Arc<crate::tiles::TileDefs>>,
+    pub stop_signal: Arc<AtomicBool>, //set from another thread to make the client disconnect and return from start()
+    pub mailbox: Arc<crate::control::Mailbox>, //commands in from and state out to the control server
+    pub last_publish: Option<time::Instant>,   //when the state last went to the mailbox
+    pub metrics: Arc<crate::metrics::Metrics>,
+    pub snapshots: Option<Arc<crate::snapshot::SnapshotStore>>,
+    pub history: crate::snapshot::History, //deaths and reconnects, kept across restarts through the snapshots
+    pub health: crate::health::Health,     //hp predicted between stat updates
+    pub conditions: crate::conditions::Conditions, //condition timers on top of the server's bitfields
+    pub quest: crate::quests::QuestTracker,
+    pub daily: crate::daily::DailyQuests,
+    pub rewards: crate::rewards::LoginRewards,
+    pub pets: crate::pets::PetTracker,
+    pub arena: crate::arena::Arena,
+    pub bootstrap: crate::bootstrap::Bootstrap,
+    pub span: tracing::Span, //"client" span with the email, ign and map, entered while the client runs
     pub config: crate::Config,
 }
 
@@ -55,6 +79,24 @@ impl Client {
             combat: CombatBase::new(),
             goods: TradeBase::new(),
             objects: GameObjects::new(),
+            account_data: None,
+            servers: None,
+            tiles: None,
+            stop_signal: Arc::new(AtomicBool::new(false)),
+            mailbox: Arc::new(crate::control::Mailbox::new()),
+            last_publish: None,
+            metrics: crate::metrics::global(),
+            snapshots: None,
+            history: crate::snapshot::History::new(),
+            health: crate::health::Health::new(),
+            conditions: crate::conditions::Conditions::new(),
+            quest: crate::quests::QuestTracker::new(),
+            daily: crate::daily::DailyQuests::new(),
+            rewards: crate::rewards::LoginRewards::new(),
+            pets: crate::pets::PetTracker::new(),
+            arena: crate::arena::Arena::new(),
+            bootstrap: crate::bootstrap::Bootstrap::new(),
+            span: tracing::Span::none(),
             config: c,
         }
     }
@@ -63,6 +105,22 @@ impl Client {
         self.recon.recon_queued = false;
         self.is_running = false;
         self.disconnect();
+        self.game_connection.release_pooled_proxy();
+    }
+    /// True once someone asked this client to stop through 'stop_signal'
+    pub fn stop_requested(&self) -> bool {
+        self.stop_signal.load(Ordering::SeqCst)
+    }
+    /// Sleeps for the duration, waking up early if the client is asked to stop
+    pub fn sleep_unless_stopped(&self, dur: time::Duration) {
+        let end = time::Instant::now() + dur;
+        while !self.stop_requested() {
+            let now = time::Instant::now();
+            if now >= end {
+                return;
+            }
+            thread::sleep((end - now).min(time::Duration::from_millis(100)));
+        }
     }
 }
 
@@ -70,25 +128,61 @@ impl Client {
     /// The main loop that the client will be in. Any thread/task splitting should be done before this call.
     pub fn game_loop(&mut self) {
         'z: while self.is_running == true {
+            self.publish_state_if_due();
+            self.save_snapshot_if_due();
+            if self.stop_requested() {
+                self.set_connection(Connection::Stopped);
+                self.kill_client();
+                break 'z;
+            }
             if self.is_connected == false {
                 if self.recon.recon_queued == true {
-                    if self.recon.check() {
-                        println!(
-                            "{} is over the reconnect limit, killing client...",
-                            self.base.email
+                    self.set_connection(Connection::Reconnecting);
+                    let delay = if self.recon.check() {
+                        if self.recon.policy.give_up {
+                            self.set_connection(Connection::Dead);
+                            warn!(
+                                "over the {:?} reconnect limit, killing client...",
+                                self.recon.recon_reason
+                            );
+                            self.kill_client();
+                            return;
+                        }
+                        warn!(
+                            "over the {:?} reconnect limit, cooling down...",
+                            self.recon.recon_reason
                         );
-                        self.kill_client();
-                        return;
+                        self.recon.cool_down()
+                    } else {
+                        self.recon.next_delay()
+                    };
+                    self.sleep_unless_stopped(delay);
+                    if self.stop_requested() {
+                        continue 'z;
+                    }
+                } else {
+                    self.set_connection(Connection::Connecting);
+                }
+                if !self.acquire_connect_slot(&self.recon.current_server.clone()) {
+                    continue 'z;
+                }
+                if let Err(e) = self.connect(self.recon.current_server.clone(), 2050) {
+                    self.game_connection.permit = None;
+                    warn!("couldn't connect to {}: {}", self.recon.current_server, e);
+                    if self.base.rotate_servers {
+                        self.rotate_server();
                     }
-                    thread::sleep(time::Duration::from_millis(
-                        self.config.thread_delay_ms * self.recon.recon_wait_multiplier,
-                    ));
+                    self.queue_recon(
+                        ReconnectReason::ConnectFailed,
+                        self.recon.game_id,
+                        self.recon.game_key.clone(),
+                        self.recon.game_key_time,
+                    );
+                    continue 'z;
                 }
-                self.connect(self.recon.current_server.clone(), 2050);
-                //cool TODO: check if sockets Option is Ok, and set is_connected = true, instead of implied
                 self.is_connected = true;
                 self.recon.recon_queued = false;
-                //todo: increment bots connected
+                self.set_connection(Connection::Connected);
                 self.send_hello(
                     self.recon.game_id,
                     self.recon.game_key.clone(),
@@ -98,33 +192,54 @@ impl Client {
                 //otherwise this thread will lock up
             }
             'y: while self.is_connected == true {
+                if self.stop_requested() {
+                    self.disconnect();
+                    break 'y;
+                }
                 if self.recon.recon_queued == true {
                     self.disconnect();
                     break 'y;
                 }
                 self.receive();
+                self.handle_commands();
+                self.publish_state_if_due();
+                self.save_snapshot_if_due();
                 //Delay so that we dont hit 100% cpu usage, this sleep basically acts as our "FPS" limiter
                 // thread::sleep(time::Duration::from_millis(
                 //     self.config.thread_delay_ms,
                 // ));
                 //if this were a normal client with a game interface, we would do our draws here
-                //we could also implement some form of mailbox MPI system for multi-system botting.
                 //projectile handling will be implemented here later.
             }
             //wait for factory thread to die
-            self.factory_handle.take().unwrap().join().unwrap();
+            if let Some(h) = self.factory_handle.take() {
+                if h.join().is_err() {
+                    error!("network factory panicked");
+                }
+            }
         }
+        self.publish_state();
+        self.save_snapshot();
         //client is no longer running, maybe clean up resources?
     }
+    /// Reports where the client is in its connection lifecycle to the metrics
+    pub fn set_connection(&self, c: Connection) {
+        self.metrics.set_connection(&self.base.email, c);
+    }
     /// Queues the client to reconnect to the server
-    pub fn queue_recon(&mut self, gid: i32, key: Vec<u8>, key_time: u32) {
+    pub fn queue_recon(&mut self, reason: ReconnectReason, gid: i32, key: Vec<u8>, key_time: u32) {
         if self.recon.blocking_reconnects == false {
             self.recon.recon_queued = true;
             self.recon.game_id = gid;
             self.recon.game_key = key;
             self.recon.game_key_time = key_time;
-            self.recon.increment(false);
+            self.recon.increment(reason);
+            self.metrics.reconnect(reason);
+            self.record_reconnect(reason);
             self.clear_heaps();
+            //the next server counts ticks from its own start
+            self.time_keeper.new_clock();
+            self.combat.last_attack_time = 0;
             //do cleanups depending on the module
         }
     }
@@ -144,9 +259,11 @@ impl Client {
                     // println!("Stat: {:?}", stat); //debug
                     if stat.1.stat_type == Stats::NAME.stat_to_u8() {
                         self.ign = stat.1.str_stat_value.clone();
+                        self.span.record("ign", self.ign.as_str());
                     }
                     self.stats.stat_map.insert(stat.1.stat_type, stat.1.clone());
                 }
+                self.sync_health(&obj.status.stats);
             }
         }
         for drop in u.drops.iter() {
@@ -161,6 +278,7 @@ impl Client {
                     self.stats.stat_map.insert(stat.1.stat_type, stat.1.clone());
                     // println!("Updated stat: {:?}", stat); //debug
                 }
+                self.sync_health(&obj.stats);
             }
             if obj.object_id == self.objects.target_object.object_id {
                 self.movement.target_position = obj.position;
@@ -181,6 +299,11 @@ impl Client {
         self.movement.tiles.clear();
         // self.movement.targets.clear();
         self.stats.stat_map.clear();
+        self.health.reset();
+        self.conditions.clear();
+        self.quest.object_id = None;
+        self.daily.reset();
+        self.bootstrap.reset();
     }
     pub fn sleep_thread(&self, dur: u64) {
         thread::sleep(time::Duration::from_secs(dur));
@@ -197,7 +320,7 @@ impl Client {
             return;
         }
         if self.has_effect(Effects::PAUSED) == true {
-            println!("Paused!");
+            debug!("Paused!");
             self.movement.last_position = self.movement.current_position;
             self.movement.current_position = types::WorldPosition::new_fill(
                 -99999999999999999999999999999999999999.0,
@@ -212,16 +335,8 @@ impl Client {
             self.movement.reset_pos = false;
             return; //still cant move when coming out of being paused
         }
-        let float_current_time = f32::from_bits(self.time_keeper.current_tick_time as u32);
-        let float_last_time = f32::from_bits(self.time_keeper.last_tick_time as u32);
         let mut new_position = types::WorldPosition::new();
-        let elapsed: f32;
-        if float_current_time - float_last_time > 200.0 {
-            elapsed = float_current_time - float_last_time;
-        } else {
-            elapsed = 200.0;
-        }
-        let step = self.get_move_speed() * elapsed;
+        let step = self.get_move_speed() * self.time_keeper.tick_elapsed() as f32;
         if self.movement.current_position.sq_distance_to(&target) > step * step {
             let angle = self.movement.current_position.angle_to(&target);
             new_position.x = self.movement.current_position.x + angle.cos() * step;
@@ -232,26 +347,48 @@ impl Client {
         self.movement.last_position = self.movement.current_position;
         self.movement.current_position = new_position;
     }
+    /// Positions between the last two ticks, sampled every RECORD_INTERVAL_MS like the official client.
+    /// Call after move_to
+    pub fn move_records(&self) -> Vec<types::MoveRecord> {
+        if self.movement.reset_pos == true {
+            return Vec::new(); //paused, there's no real position to record
+        }
+        let elapsed = self.time_keeper.tick_elapsed();
+        let start = self.time_keeper.current_tick_time - elapsed;
+        let from = self.movement.last_position;
+        let to = self.movement.current_position;
+        self.time_keeper
+            .record_times()
+            .into_iter()
+            .map(|t| {
+                let f = (t - start) as f32 / elapsed as f32;
+                types::MoveRecord {
+                    time: t,
+                    x: from.x + (to.x - from.x) * f,
+                    y: from.y + (to.y - from.y) * f,
+                }
+            })
+            .collect()
+    }
     /// Gets the clients move speed
     pub fn get_move_speed(&self) -> f32 {
-        //todo: get speed mult from tiles
         if self.has_effect(Effects::SLOWED) == true {
-            return MIN_MOVE_SPEED;
+            return MIN_MOVE_SPEED * self.tile_speed();
         }
         let mut move_speed: f32 = MIN_MOVE_SPEED
-            + f32::from_bits(self.stats.get_item(Stats::SPEED) as u32) / 75.0
+            + self.stats.get_item(Stats::SPEED).unwrap_or(0) as f32 / 75.0
                 * (MAX_MOVE_SPEED - MIN_MOVE_SPEED);
         if self.has_effect(Effects::SPEEDY) == true {
             move_speed *= 1.5;
         }
-        move_speed * self.movement.move_multiplier
+        move_speed * self.movement.move_multiplier * self.tile_speed()
     }
     pub fn get_atk_freq(&self) -> f32 {
         if self.has_effect(Effects::DAZED) == true {
             return MIN_ATK_FREQ;
         }
         let mut atk_freq = MIN_ATK_FREQ
-            + f32::from_bits(self.stats.get_item(Stats::DEXTERITY) as u32) / 75.0
+            + self.stats.get_item(Stats::DEXTERITY).unwrap_or(0) as f32 / 75.0
                 * (MAX_ATK_FREQ - MIN_ATK_FREQ);
         if self.has_effect(Effects::BERSERK) == true {
             atk_freq *= 1.5;
@@ -263,16 +400,16 @@ impl Client {
             return MIN_ATK_MULT;
         }
         let mut atk_mult: f32 = MIN_ATK_MULT
-            + f32::from_bits(self.stats.get_item(Stats::ATTACK) as u32) / 75.0
+            + self.stats.get_item(Stats::ATTACK).unwrap_or(0) as f32 / 75.0
                 * (MAX_ATK_MULT - MIN_ATK_MULT);
         if self.has_effect(Effects::DAMAGING) == true {
             atk_mult *= 1.5;
         }
         atk_mult
     }
-    /// Check if the client is affected by a status
+    /// Check if the client is affected by a status, going by the server's stats and our own timers
     pub fn has_effect(&self, status: Effects) -> bool {
-        self.stats.has_effect(status)
+        self.has_effect_id(status.to_byte())
     }
 }
 
@@ -331,6 +468,15 @@ impl Movement {
             tiles: HashMap::with_capacity(256*256),
         }
     }
+    /// Type of the tile under 'pos', if the server sent it
+    pub fn tile_at(&self, pos: &types::WorldPosition) -> Option<u16> {
+        let key = types::GroundTile {
+            x: pos.x.floor() as i16,
+            y: pos.y.floor() as i16,
+            tile_type: 0,
+        };
+        self.tiles.get(&key).copied()
+    }
     fn is_out_of_bounds(&self, pos: &types::WorldPosition) -> bool {
         pos.x < 0.0 || pos.y < 0.0 || pos.x > self.map_width as f32 || pos.y > self.map_width as f32
     }
@@ -368,10 +514,13 @@ impl Movement {
 pub struct TimeKeeper {
     pub startup_time: time::Instant,
     pub last_tick_id: i32,
-    pub current_tick_time: i32,
-    pub last_tick_time: i32,
+    pub current_tick_time: i32, //client ms when the latest NewTick was handled, -1 before the first
+    pub last_tick_time: i32,    //client ms when the one before it was handled, -1 before the second
+    pub server_tick_time: i32,  //ms the server says the latest tick covers
+    pub last_ground_damage: i32, //client ms of the last GroundDamage sent, -1 for none yet
     pub swap_speed_ms: i32,
     pub thread_delay_ms: i32,
+    pub last_receive: time::Instant, //when the packet being handled came off the factory channel
 }
 
 impl TimeKeeper {
@@ -379,15 +528,46 @@ impl TimeKeeper {
         TimeKeeper {
             startup_time: time::Instant::now(),
             last_tick_id: 0,
-            current_tick_time: 0,
-            last_tick_time: 0,
+            current_tick_time: -1,
+            last_tick_time: -1,
+            server_tick_time: TICK_MS,
+            last_ground_damage: -1,
             swap_speed_ms: 750,
             thread_delay_ms: 2000, //TODO: grab and set from config file
+            last_receive: time::Instant::now(),
         }
     }
     /// Resets the client instances startup clock (Example: when switching servers)
     pub fn new_clock(&mut self) {
         self.startup_time = time::Instant::now();
+        self.current_tick_time = -1;
+        self.last_tick_time = -1;
+        self.server_tick_time = TICK_MS;
+        self.last_ground_damage = -1;
+    }
+    /// Marks a NewTick, 'tick_time' is the packet's tick_time
+    pub fn on_tick(&mut self, tick_time: i32) {
+        self.last_tick_time = self.current_tick_time;
+        self.current_tick_time = self.get_time();
+        self.server_tick_time = tick_time;
+    }
+    /// Real ms between the last two ticks. The first tick goes by the server's tick_time
+    pub fn tick_elapsed(&self) -> i32 {
+        if self.last_tick_time < 0 || self.current_tick_time <= self.last_tick_time {
+            return self.server_tick_time.max(0);
+        }
+        self.current_tick_time - self.last_tick_time
+    }
+    /// When the move records between the last two ticks were taken, counting back from the latest one.
+    /// A long gap keeps the last MAX_MOVE_RECORDS
+    pub fn record_times(&self) -> Vec<i32> {
+        let start = self.current_tick_time - self.tick_elapsed();
+        let mut times: Vec<i32> = (0..MAX_MOVE_RECORDS as i32)
+            .map(|i| self.current_tick_time - i * RECORD_INTERVAL_MS)
+            .take_while(|t| *t > start)
+            .collect();
+        times.reverse();
+        times
     }
     /// Returns the time elapsed in ms since the last initialization of the client timer
     pub fn get_time(&self) -> i32 {
@@ -401,15 +581,74 @@ impl TimeKeeper {
     }
 }
 
+/// Why the client is reconnecting. Each reason keeps its own attempt counter and limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ReconnectReason {
+    /// The server (or a command) asked us to change maps/servers
+    Requested,
+    /// The socket to the server or proxy couldn't be opened
+    ConnectFailed,
+    /// The server closed the connection on us
+    Dropped,
+    /// The server sent a Failure packet before dropping us
+    Failure,
+}
+
+/// Controls how long the client waits between reconnects, and when it stops trying.
+/// Lives under "reconnect" in the settings file, any missing field falls back to the default.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ReconnectPolicy {
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub backoff_factor: f64,
+    pub jitter: f64, //fraction of the delay that is randomized, 0.2 = +/-20%
+    pub max_connect_attempts: u32, //0 means unlimited
+    pub max_dropped_attempts: u32,
+    pub max_failure_attempts: u32,
+    pub cooldown_ms: u64, //how long to wait once a limit is hit, if we aren't giving up
+    pub give_up: bool,    //kill the client once a limit is hit instead of cooling down
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> ReconnectPolicy {
+        ReconnectPolicy {
+            base_delay_ms: 1000,
+            max_delay_ms: 60_000,
+            backoff_factor: 2.0,
+            jitter: 0.2,
+            max_connect_attempts: 10,
+            max_dropped_attempts: 10,
+            max_failure_attempts: 5,
+            cooldown_ms: 300_000,
+            give_up: false,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Returns the attempt limit for the reason, 0 being unlimited
+    pub fn limit_for(&self, reason: ReconnectReason) -> u32 {
+        match reason {
+            ReconnectReason::Requested => 0,
+            ReconnectReason::ConnectFailed => self.max_connect_attempts,
+            ReconnectReason::Dropped => self.max_dropped_attempts,
+            ReconnectReason::Failure => self.max_failure_attempts,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ReconnectBase {
     pub current_server: String,
     pub previous_server: String,
     pub blocking_reconnects: bool,
     pub recon_queued: bool,
-    pub recon_attempts: u32,
-    pub recon_wait_multiplier: u64,
-    pub recon_allowed_attempts: u32,
+    pub recon_attempts: u32, //consecutive failed attempts since we last joined a map, drives the backoff
+    pub recon_reason: ReconnectReason,
+    pub last_failure_id: Option<i32>, //set by a Failure packet, so the following disconnect is counted as a Failure
+    pub reason_attempts: HashMap<ReconnectReason, u32>,
+    pub policy: ReconnectPolicy,
     pub game_id: i32,
     pub game_key: Vec<u8>,
     pub game_key_time: u32,
@@ -422,31 +661,66 @@ impl ReconnectBase {
             previous_server: String::new(),
             blocking_reconnects: false,
             recon_queued: false,
-            recon_allowed_attempts: 3,
             recon_attempts: 0,
-            recon_wait_multiplier: 1,
+            recon_reason: ReconnectReason::Requested,
+            last_failure_id: None,
+            reason_attempts: HashMap::with_capacity(4),
+            policy: ReconnectPolicy::default(),
             game_id: -2,
             game_key: Vec::new(),
             game_key_time: u32::max_value(),
         }
     }
+    /// Called once we are in game again, clears every attempt counter
     pub fn reset(&mut self) {
         self.blocking_reconnects = false;
         self.recon_attempts = 0;
-        self.recon_wait_multiplier = 1;
+        self.last_failure_id = None;
+        self.reason_attempts.clear();
     }
-    /// Increments the recon attempts. If w is true, also increments the wait period
-    pub fn increment(&mut self, w: bool) {
-        if self.recon_attempts > self.recon_allowed_attempts {
-            self.recon_queued = false;
+    /// Records a reconnect attempt. Requested reconnects don't count towards the backoff or limits
+    pub fn increment(&mut self, reason: ReconnectReason) {
+        self.recon_reason = reason;
+        if reason == ReconnectReason::Requested {
+            return;
         }
         self.recon_attempts += 1;
-        if w == true {
-            self.recon_wait_multiplier += 1;
-        }
+        *self.reason_attempts.entry(reason).or_insert(0) += 1;
+    }
+    /// Returns the number of attempts made for the reason since the last reset
+    pub fn attempts_for(&self, reason: ReconnectReason) -> u32 {
+        *self.reason_attempts.get(&reason).unwrap_or(&0)
     }
+    /// Returns true once the current reason used up its attempt limit
     pub fn check(&self) -> bool {
-        self.recon_attempts > self.recon_allowed_attempts
+        let limit = self.policy.limit_for(self.recon_reason);
+        limit != 0 && self.attempts_for(self.recon_reason) >= limit
+    }
+    /// The delay before the next attempt, without jitter: base * factor^(attempts - 1), capped at the max delay
+    pub fn backoff_delay_ms(&self) -> u64 {
+        if self.recon_reason == ReconnectReason::Requested || self.recon_attempts == 0 {
+            return self.policy.base_delay_ms.min(self.policy.max_delay_ms);
+        }
+        let exp = (self.recon_attempts - 1).min(63) as i32;
+        let delay =
+            self.policy.base_delay_ms as f64 * self.policy.backoff_factor.max(1.0).powi(exp);
+        delay.min(self.policy.max_delay_ms as f64) as u64
+    }
+    /// The delay before the next attempt, with jitter applied so a farm of bots doesn't reconnect in lockstep
+    pub fn next_delay(&self) -> time::Duration {
+        let delay = self.backoff_delay_ms() as f64;
+        let jitter = self.policy.jitter.max(0.0).min(1.0);
+        let spread = delay * jitter * (rand::random::<f64>() * 2.0 - 1.0);
+        let jittered = (delay + spread)
+            .max(0.0)
+            .min(self.policy.max_delay_ms as f64);
+        time::Duration::from_millis(jittered as u64)
+    }
+    /// Clears the counter of the reason that hit its limit and returns how long to wait before trying again
+    pub fn cool_down(&mut self) -> time::Duration {
+        self.reason_attempts.remove(&self.recon_reason);
+        self.recon_attempts = 0;
+        time::Duration::from_millis(self.policy.cooldown_ms)
     }
 }
 
@@ -461,18 +735,15 @@ impl StatBase {
             stat_map: HashMap::with_capacity(102),
         }
     }
-    pub fn get_item(&self, item: Stats) -> i32 {
-        self.stat_map[&item.stat_to_u8()].stat_value
+    /// None until the server sends the stat
+    pub fn get_item(&self, item: Stats) -> Option<i32> {
+        self.stat_map.get(&item.stat_to_u8()).map(|s| s.stat_value)
     }
+    /// Only the server's bitfields, false until they arrive. ``Client::has_effect`` adds the local timers
     pub fn has_effect(&self, status: Effects) -> bool {
-        if status.to_byte() > 31 {
-            let condition = self.get_item(Stats::EFFECTS2);
-            let effect_bit = 1 << (status.to_byte() - 32);
-            (condition & effect_bit) == effect_bit
-        } else {
-            let condition = self.get_item(Stats::EFFECTS);
-            let effect_bit = 1 << (status.to_byte() - 1);
-            (condition & effect_bit) == effect_bit
+        match crate::conditions::effect_bit(status.to_byte()) {
+            Some((stat, bit)) => self.get_item(stat).is_some_and(|field| field & bit == bit),
+            None => false,
         }
     }
 }
@@ -579,8 +850,11 @@ impl Client {
         }
         let time = self.time_keeper.get_time();
         //get item from slot0 and get rate of fire
-        let attack_period = 1.0 / self.get_atk_freq() * (1.0 / 100.0); //replace 100 with weapon rate of fire
-        if time < self.combat.last_attack_time + f32::to_bits(attack_period) as i32 {
+        let rate_of_fire = 1.0; //replace with the weapon's rate of fire
+        let attack_period = 1.0 / self.get_atk_freq() * (1.0 / rate_of_fire); //ms between shots
+        if self.combat.last_attack_time > 0
+            && time < self.combat.last_attack_time + attack_period as i32
+        {
             return;
         }
         self.combat.last_attack_time = time;
@@ -596,7 +870,7 @@ impl Client {
             ps.time = time;
             ps.bullet_id = self.combat.get_bullet_id();
             ps.angle = angle;
-            ps.container_type = self.stats.get_item(Stats::INVENTORY0) as i16;
+            ps.container_type = self.stats.get_item(Stats::INVENTORY0).unwrap_or(-1) as i16;
             ps.position = self.movement.current_position;
             ps.position.x += f32::cos(angle) * 0.3;
             ps.position.y += f32::sin(angle) * 0.3;
@@ -604,6 +878,20 @@ impl Client {
             //todo: push projectile onto projectile map
         }
     }
+    /// Heads for 'target', circles it at ``Movement.radius`` once there and shoots it
+    /// when it's within 'shoot_range' tiles
+    pub fn engage(&mut self, target: types::WorldPosition, shoot_range: f32) {
+        let distance = self.movement.current_position.distance_to(&target);
+        if distance > self.movement.radius + 1.0 {
+            self.movement.target_position = target;
+        } else {
+            self.movement.rotate_clockwise(target);
+        }
+        if distance <= shoot_range {
+            debug!("shooting at {:.1} tiles", distance);
+            self.shoot(self.movement.current_position.angle_to(&target));
+        }
+    }
     pub fn send_text(&mut self, text: String) {
         let mut t = client_packets::PlayerText::new();
         t.message = text;
@@ -611,13 +899,16 @@ impl Client {
     }
     pub fn track_player(&mut self, name: String) {
         match self.objects.get_obj_by_name(name) {
-            Some(v) => println!("Tracked: {:?}", v.position),
+            Some(v) => info!("Tracked: {:?}", v.position),
             None => (),
         }
     }
 }
 
-pub const MIN_MOVE_SPEED: f32 = 0.0041;
+pub const TICK_MS: i32 = 200; //how often the server sends a NewTick
+pub const RECORD_INTERVAL_MS: i32 = 100; //how often the official client records its position
+pub const MAX_MOVE_RECORDS: usize = 10;
+pub const MIN_MOVE_SPEED: f32 = 0.0041; //tiles per ms
 pub const MAX_MOVE_SPEED: f32 = 0.00961;
 pub const MIN_ATK_MULT: f32 = 0.5;
 pub const MAX_ATK_MULT: f32 = 2.0;
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::client;
+use super::network::packets::client_packets;
+use super::network::packets::client_packets::ClientPacket;
+use super::network::packets::client_packets::ClientPackets;
+use super::network::types::Effects;
+use super::network::types::Stats;
+
+/* conditions.rs - The client's condition effects: the server's EFFECTS bitfields plus the timers we know about locally */
+
+/// Highest effect id the two bitfields can hold
+pub const MAX_EFFECT_ID: u8 = 63;
+
+/// Conditions and the immunity that blocks them
+const IMMUNITIES: [(Effects, Effects); 8] = [
+    (Effects::SLOWED, Effects::SLOWIMMUNE),
+    (Effects::DAZED, Effects::DAZEIMMUNE),
+    (Effects::STUNNED, Effects::STUNIMMUNE),
+    (Effects::PARALYZED, Effects::PARALYZEIMMUNE),
+    (Effects::STASIS, Effects::STASISIMMUNE),
+    (Effects::ARMORBROKEN, Effects::ARMORBROKENIMMUNE),
+    (Effects::PETRIFIED, Effects::PETRIFIEDIMMUNE),
+    (Effects::CURSE, Effects::CURSEIMMUNE),
+];
+
+/// Which bitfield stat holds the effect id, and its bit there. Ids 1-31 are in EFFECTS, 32-63 in EFFECTS2
+pub fn effect_bit(id: u8) -> Option<(Stats, i32)> {
+    match id {
+        1..=31 => Some((Stats::EFFECTS, 1 << (id - 1))),
+        32..=MAX_EFFECT_ID => Some((Stats::EFFECTS2, 1 << (id - 32))),
+        _ => None,
+    }
+}
+
+/// An effect with its duration, like a projectile's <ConditionEffect> in the gamedata
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ConditionEffect {
+    pub effect: u8,
+    pub duration_ms: i32,
+}
+
+/// Conditions applied locally, until the server's bitfields catch up
+#[derive(Debug, Clone, Default)]
+pub struct Conditions {
+    pub timers: HashMap<u8, i32>, //effect id to the client ms it wears off at
+}
+
+impl Conditions {
+    pub fn new() -> Conditions {
+        Conditions::default()
+    }
+    /// Starts or extends the timer, a shorter duration doesn't cut a longer one short
+    pub fn apply(&mut self, id: u8, now: i32, duration_ms: i32) {
+        let end = now + duration_ms;
+        let entry = self.timers.entry(id).or_insert(end);
+        *entry = (*entry).max(end);
+    }
+    pub fn remove(&mut self, id: u8) {
+        self.timers.remove(&id);
+    }
+    pub fn is_active(&self, id: u8, now: i32) -> bool {
+        self.timers.get(&id).is_some_and(|end| *end > now)
+    }
+    /// Drops the timers that wore off
+    pub fn prune(&mut self, now: i32) {
+        self.timers.retain(|_, end| *end > now);
+    }
+    pub fn clear(&mut self) {
+        self.timers.clear();
+    }
+}
+
+impl client::Client {
+    /// The server's bit for the effect id, None if its bitfield hasn't arrived yet
+    pub fn server_has_effect(&self, id: u8) -> Option<bool> {
+        let (stat, bit) = effect_bit(id)?;
+        let field = self.stats.get_item(stat)?;
+        Some(field & bit == bit)
+    }
+    /// True if the server says we have the effect or a local timer for it is running
+    pub fn has_effect_id(&self, id: u8) -> bool {
+        self.server_has_effect(id).unwrap_or(false)
+            || self.conditions.is_active(id, self.time_keeper.get_time())
+    }
+    /// Every effect id we have, from either source
+    pub fn active_effect_ids(&self) -> Vec<u8> {
+        (1..=MAX_EFFECT_ID)
+            .filter(|id| self.has_effect_id(*id))
+            .collect()
+    }
+    /// Like ``active_effect_ids``, typed. Unnamed ids come back as ``Effects::UNKNOWN``
+    pub fn active_effects(&self) -> Vec<Effects> {
+        self.active_effect_ids()
+            .into_iter()
+            .filter_map(Effects::from_byte)
+            .collect()
+    }
+    /// Applies the effect for 'duration_ms', unless we're immune to it
+    pub fn apply_effect(&mut self, effect: u8, duration_ms: i32) {
+        if effect == 0 || duration_ms <= 0 {
+            return;
+        }
+        let immune = IMMUNITIES
+            .iter()
+            .any(|(e, immunity)| e.to_byte() == effect && self.has_effect(*immunity));
+        if immune {
+            return;
+        }
+        let now = self.time_keeper.get_time();
+        self.conditions.prune(now);
+        self.conditions.apply(effect, now, duration_ms);
+    }
+    /// Applies a list of effects, like the ones on a projectile that hit us
+    pub fn apply_effects(&mut self, effects: &[ConditionEffect]) {
+        for e in effects.iter() {
+            self.apply_effect(e.effect, e.duration_ms);
+        }
+    }
+    /// Puts a condition on ourselves and tells the server with SetCondition
+    pub fn set_condition(&mut self, effect: Effects, duration_secs: f32) {
+        self.apply_effect(effect.to_byte(), (duration_secs * 1000.0) as i32);
+        let mut sc = client_packets::SetCondition::new();
+        sc.condition_effect = effect.to_byte();
+        sc.condition_duration = duration_secs;
+        self.send(ClientPackets::SetConditionPacket(sc).write());
+    }
+}
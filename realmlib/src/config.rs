@@ -0,0 +1,421 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+extern crate serde_path_to_error;
+extern crate serde_yaml;
+extern crate toml;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::account;
+use super::control;
+use super::logging;
+use super::network::proxy;
+use super::web;
+use super::Config;
+
+/* config.rs - Loads, layers and validates the settings and accounts files */
+
+pub const DEFAULT_SETTINGS_PATH: &str = "config/settings.json";
+pub const DEFAULT_ACCOUNTS_PATH: &str = "config/accounts.json";
+/// Environment variables starting with this override settings, "__" separates nested fields:
+/// REALMLIB_AMOUNT=5, REALMLIB_RECONNECT__BASE_DELAY_MS=500
+pub const ENV_PREFIX: &str = "REALMLIB_";
+
+pub const MIN_STACK_KB: usize = 16;
+pub const MAX_STACK_KB: usize = 64 * 1024;
+
+/// A problem with one setting, 'field' is the path to it such as "reconnect.jitter" or "[1].email"
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> FieldError {
+        FieldError {
+            field: field.to_owned(),
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file couldn't be read
+    Io(PathBuf, io::Error),
+    /// The file isn't valid json/toml/yaml
+    Parse(PathBuf, String),
+    /// The file parsed, but some values are missing, have the wrong type or are out of range
+    Invalid(PathBuf, Vec<FieldError>),
+}
+
+impl ConfigError {
+    /// The field errors, empty if the file couldn't be read or parsed at all
+    pub fn fields(&self) -> &[FieldError] {
+        match self {
+            ConfigError::Invalid(_, errors) => errors,
+            _ => &[],
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(p, e) => write!(f, "couldn't read {}: {}", p.display(), e),
+            ConfigError::Parse(p, e) => write!(f, "couldn't parse {}: {}", p.display(), e),
+            ConfigError::Invalid(p, errors) => {
+                write!(f, "invalid settings in {}:", p.display())?;
+                for e in errors {
+                    write!(f, "\n    {}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// Picks the format from the file extension, anything unknown is read as json
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("toml") => Format::Toml,
+            Some(e) if e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml") => {
+                Format::Yaml
+            }
+            _ => Format::Json,
+        }
+    }
+
+    /// Parses the text into a json value so every format goes through the same checks
+    pub fn parse(self, text: &str) -> Result<Value, String> {
+        match self {
+            Format::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            Format::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+            Format::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Where the settings and accounts are read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigPaths {
+    pub settings: PathBuf,
+    pub accounts: PathBuf,
+}
+
+impl Default for ConfigPaths {
+    fn default() -> ConfigPaths {
+        ConfigPaths {
+            settings: PathBuf::from(DEFAULT_SETTINGS_PATH),
+            accounts: PathBuf::from(DEFAULT_ACCOUNTS_PATH),
+        }
+    }
+}
+
+fn read_value(path: &Path) -> Result<Value, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+    Format::from_path(path)
+        .parse(&text)
+        .map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+}
+
+/// Deserializes the value, keeping the path to the field that failed
+fn from_value<T: DeserializeOwned>(path: &Path, value: Value) -> Result<T, ConfigError> {
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        let message = e.inner().to_string();
+        let mut field = match e.path().to_string() {
+            ref p if p == "." => String::new(),
+            p => p,
+        };
+        //serde reports missing fields on the parent, point at the field itself
+        if let Some(name) = message
+            .strip_prefix("missing field `")
+            .and_then(|m| m.split('`').next())
+        {
+            if !field.is_empty() {
+                field.push('.');
+            }
+            field.push_str(name);
+        }
+        if field.is_empty() {
+            field = String::from("(top level)");
+        }
+        ConfigError::Invalid(path.to_path_buf(), vec![FieldError { field, message }])
+    })
+}
+
+/// Applies ENV_PREFIX overrides to a settings value. Unknown settings and values of the wrong type are errors
+pub fn apply_overrides<I: IntoIterator<Item = (String, String)>>(
+    value: &mut Value,
+    vars: I,
+) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    for (key, raw) in vars {
+        if !key.starts_with(ENV_PREFIX) {
+            continue;
+        }
+        let path: Vec<String> = key[ENV_PREFIX.len()..]
+            .split("__")
+            .map(|p| p.to_ascii_lowercase())
+            .collect();
+        let field = path.join(".");
+        let mut slot = Some(&mut *value);
+        for part in &path {
+            slot = slot.and_then(|v| v.get_mut(part.as_str()));
+        }
+        let slot = match slot {
+            Some(s) if !s.is_object() => s,
+            _ => {
+                errors.push(FieldError::new(
+                    &field,
+                    &format!("{} doesn't match a setting", key),
+                ));
+                continue;
+            }
+        };
+        let new = if slot.is_string() {
+            Value::String(raw)
+        } else {
+            match serde_json::from_str::<Value>(&raw) {
+                Ok(v)
+                    if v.is_number() == slot.is_number() && v.is_boolean() == slot.is_boolean() =>
+                {
+                    v
+                }
+                _ => {
+                    errors.push(FieldError::new(
+                        &field,
+                        &format!("{}={} has the wrong type", key, raw),
+                    ));
+                    continue;
+                }
+            }
+        };
+        *slot = new;
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Turns "reconnect.jitter" and a value into the matching override variable, REALMLIB_RECONNECT__JITTER
+pub fn override_var(field: &str, value: &str) -> (String, String) {
+    let key = field.replace('.', "__").to_ascii_uppercase();
+    (format!("{}{}", ENV_PREFIX, key), value.to_owned())
+}
+
+impl Config {
+    /// Loads the settings file, applies environment overrides and validates the result
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        Config::load_with_env(path, std::env::vars())
+    }
+
+    /// Same as ``load``, with the environment passed in
+    pub fn load_with_env<P: AsRef<Path>, I: IntoIterator<Item = (String, String)>>(
+        path: P,
+        vars: I,
+    ) -> Result<Config, ConfigError> {
+        let path = path.as_ref();
+        let c: Config = from_value(path, read_value(path)?)?;
+        //round trip through a value so overrides can also set fields the file left to their defaults
+        let mut value = serde_json::to_value(&c)
+            .map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()))?;
+        apply_overrides(&mut value, vars)
+            .map_err(|e| ConfigError::Invalid(path.to_path_buf(), e))?;
+        let c: Config = from_value(path, value)?;
+        c.validate()
+            .map_err(|e| ConfigError::Invalid(path.to_path_buf(), e))?;
+        Ok(c)
+    }
+
+    /// Checks every setting, returning all of the problems at once
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if self.amount == 0 {
+            errors.push(FieldError::new("amount", "must be at least 1"));
+        }
+        if self.index >= self.amount {
+            errors.push(FieldError::new(
+                "index",
+                &format!("must be less than amount ({})", self.amount),
+            ));
+        }
+        if self.conn_limit < 0 {
+            errors.push(FieldError::new("conn_limit", "can't be negative"));
+        }
+        if self.game_version.trim().is_empty() {
+            errors.push(FieldError::new("game_version", "can't be empty"));
+        }
+        if self.thread_delay_ms == 0 {
+            errors.push(FieldError::new("thread_delay_ms", "must be at least 1"));
+        }
+        let stacks = [
+            ("factory_stack_size_kb", self.factory_stack_size_kb),
+            (
+                "client_thread_stack_size_kb",
+                self.client_thread_stack_size_kb,
+            ),
+        ];
+        for (field, kb) in stacks.iter() {
+            if *kb < MIN_STACK_KB || *kb > MAX_STACK_KB {
+                errors.push(FieldError::new(
+                    field,
+                    &format!("must be between {} and {}", MIN_STACK_KB, MAX_STACK_KB),
+                ));
+            }
+        }
+        if self.save_delay_secs < 0 {
+            errors.push(FieldError::new("save_delay_secs", "can't be negative"));
+        }
+        let r = &self.reconnect;
+        if r.backoff_factor.is_nan() || r.backoff_factor < 1.0 {
+            errors.push(FieldError::new(
+                "reconnect.backoff_factor",
+                "must be at least 1.0",
+            ));
+        }
+        if !(0.0..=1.0).contains(&r.jitter) {
+            errors.push(FieldError::new(
+                "reconnect.jitter",
+                "must be between 0.0 and 1.0",
+            ));
+        }
+        if r.base_delay_ms > r.max_delay_ms {
+            errors.push(FieldError::new(
+                "reconnect.base_delay_ms",
+                "can't be more than reconnect.max_delay_ms",
+            ));
+        }
+        if let Err(e) = web::Url::parse(&self.api_base_url) {
+            errors.push(FieldError::new("api_base_url", &e.to_string()));
+        }
+        let g = &self.governor;
+        let rates = [
+            ("governor.connects_per_sec", g.connects_per_sec),
+            (
+                "governor.server_connects_per_sec",
+                g.server_connects_per_sec,
+            ),
+        ];
+        for (field, rate) in rates.iter() {
+            if rate.is_nan() || *rate < 0.0 {
+                errors.push(FieldError::new(field, "can't be negative"));
+            }
+        }
+        if let Err(e) = logging::parse_filter(&self.logging.filter) {
+            errors.push(FieldError::new("logging.filter", &e));
+        }
+        if let Err(e) = logging::parse_filter(&self.logging.file_filter) {
+            errors.push(FieldError::new("logging.file_filter", &e));
+        }
+        if let Err(e) = control::Bind::parse(&self.control.bind) {
+            errors.push(FieldError::new("control.bind", &e));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks that there are enough accounts for 'amount'
+    pub fn validate_accounts(&self, accounts: &[account::Account]) -> Result<(), Vec<FieldError>> {
+        if self.amount > accounts.len() {
+            return Err(vec![FieldError::new(
+                "amount",
+                &format!(
+                    "is {} but there are only {} accounts",
+                    self.amount,
+                    accounts.len()
+                ),
+            )]);
+        }
+        Ok(())
+    }
+}
+
+/// Loads and validates the accounts file. Json and yaml files hold a list of accounts,
+/// toml files an "accounts" array of tables
+pub fn load_accounts<P: AsRef<Path>>(path: P) -> Result<Vec<account::Account>, ConfigError> {
+    let path = path.as_ref();
+    let mut value = read_value(path)?;
+    if let Some(list) = value.get_mut("accounts") {
+        value = list.take();
+    }
+    let accounts: Vec<account::Account> = from_value(path, value)?;
+    let mut errors = Vec::new();
+    for (i, a) in accounts.iter().enumerate() {
+        if a.email.trim().is_empty() {
+            errors.push(FieldError::new(&format!("[{}].email", i), "can't be empty"));
+        }
+        if a.password.is_empty() {
+            errors.push(FieldError::new(
+                &format!("[{}].password", i),
+                "can't be empty",
+            ));
+        }
+        if a.use_socks {
+            if let Err(e) = proxy::Proxy::parse(&a.socks_proxy) {
+                errors.push(FieldError::new(&format!("[{}].socks_proxy", i), &e));
+            }
+        }
+        if a.use_http {
+            if let Err(e) = proxy::Proxy::parse_or(&a.http_proxy, proxy::ProxyKind::Http) {
+                errors.push(FieldError::new(&format!("[{}].http_proxy", i), &e));
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(accounts)
+    } else {
+        Err(ConfigError::Invalid(path.to_path_buf(), errors))
+    }
+}
+
+/// Loads both files and checks them against each other
+pub fn load(paths: &ConfigPaths) -> Result<(Config, Vec<account::Account>), ConfigError> {
+    load_with_env(paths, std::env::vars())
+}
+
+/// Same as ``load``, with the environment passed in. Later variables win, so command line overrides can be chained on
+pub fn load_with_env<I: IntoIterator<Item = (String, String)>>(
+    paths: &ConfigPaths,
+    vars: I,
+) -> Result<(Config, Vec<account::Account>), ConfigError> {
+    let c = Config::load_with_env(&paths.settings, vars)?;
+    let accounts = load_accounts(&paths.accounts)?;
+    c.validate_accounts(&accounts)
+        .map_err(|e| ConfigError::Invalid(paths.settings.clone(), e))?;
+    Ok((c, accounts))
+}
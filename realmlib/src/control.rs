@@ -0,0 +1,661 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::{Read, Write};
+use std::net;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::client;
+use super::manager::BotState;
+use super::metrics;
+use super::network::packets::client_packets;
+use super::network::packets::client_packets::ClientPacket;
+use super::network::packets::client_packets::ClientPackets;
+use super::network::types;
+use super::network::types::Stats;
+use super::pets;
+use super::rewards;
+
+/* control.rs - Local http api for looking at running bots and sending them commands, on localhost or a unix socket */
+
+pub const DEFAULT_BIND: &str = "127.0.0.1:7700";
+/// Binds to a unix socket instead, `unix:/tmp/realmlib.sock`
+pub const UNIX_PREFIX: &str = "unix:";
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+const IO_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+const ACCEPT_POLL: time::Duration = time::Duration::from_millis(50);
+/// How stale a bot's published state can get, the game loop runs once per packet
+pub const PUBLISH_INTERVAL: time::Duration = time::Duration::from_millis(250);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ControlSettings {
+    pub enabled: bool,
+    pub bind: String, //"127.0.0.1:7700" or "unix:/path/to.sock"
+}
+
+impl Default for ControlSettings {
+    fn default() -> ControlSettings {
+        ControlSettings {
+            enabled: false,
+            bind: String::from(DEFAULT_BIND),
+        }
+    }
+}
+
+/// Where the control server listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bind {
+    Tcp(net::SocketAddr),
+    Unix(PathBuf),
+}
+
+impl Bind {
+    /// Parses a bind setting. Tcp addresses have to be loopback, the api has no authentication
+    pub fn parse(bind: &str) -> Result<Bind, String> {
+        if let Some(path) = bind.strip_prefix(UNIX_PREFIX) {
+            if path.is_empty() {
+                return Err(String::from("unix socket path is empty"));
+            }
+            if cfg!(unix) {
+                return Ok(Bind::Unix(PathBuf::from(path)));
+            }
+            return Err(String::from(
+                "unix sockets aren't supported on this platform",
+            ));
+        }
+        let addr: net::SocketAddr = bind
+            .parse()
+            .map_err(|_| format!("{} isn't an ip:port or unix:path", bind))?;
+        if !addr.ip().is_loopback() {
+            return Err(format!("{} isn't a loopback address", addr.ip()));
+        }
+        Ok(Bind::Tcp(addr))
+    }
+}
+
+/// Commands the control server hands to a client. Run on the client's own thread by ``Client::handle_commands``
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ClientCommand {
+    MoveTo {
+        x: f32,
+        y: f32,
+    },
+    /// Follows the player with this name, if it's in sight
+    Follow {
+        name: String,
+    },
+    /// Stops moving, following and hunting
+    Stop,
+    /// Hunts the current quest, see ``Client::hunt_quest``
+    Hunt,
+    /// Fetches the daily quest and redeems it if we have the items
+    DailyQuest,
+    /// Enters the arena from the nexus, paying with ``arena.currency``
+    Arena,
+    /// Follows with this pet, switching from the active one
+    PetFollow {
+        instance_id: i32,
+    },
+    PetUnfollow,
+    PetRelease {
+        instance_id: i32,
+    },
+    /// Feeds the item in this inventory slot to the active pet
+    PetFeed {
+        slot: u8,
+        #[serde(default)]
+        payment: pets::Payment,
+    },
+    PetFuse {
+        pet_one: i32,
+        pet_two: i32,
+        #[serde(default)]
+        payment: pets::Payment,
+    },
+    PetSkin {
+        pet_id: i32,
+        skin_type: i32,
+        #[serde(default)]
+        payment: pets::Payment,
+    },
+    Nexus,
+    Say {
+        text: String,
+    },
+    Trade {
+        name: String,
+    },
+    Reconnect,
+}
+
+/// What the client last published about itself
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ClientState {
+    pub connected: bool,
+    pub server: String,
+    pub map: String,
+    pub object_id: i32,
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    pub hp: i32, //predicted, with the damage taken since the last stat update
+    pub max_hp: i32,
+    pub inventory: Vec<i32>, //slots 0-3 are gear, -1 is empty
+    pub quest_id: Option<i32>,
+    pub quest_distance: Option<f32>, //None while the quest is out of sight
+    pub hunting: bool,
+    pub daily_tier: Option<i32>, //the daily quest's, None until it's fetched
+    pub daily_redeemed: u32,
+    pub login_rewards: Vec<rewards::LoginReward>, //what the claims brought in this session
+    pub pet: Option<pets::Pet>,
+    pub last_hatch: Option<pets::Hatched>,
+    pub last_evolve: Option<pets::Evolved>,
+    pub arena_wave: Option<u32>, //None outside the arena
+}
+
+/// Shared between a client and the control server. Survives restarts, the manager hands it to every new client
+#[derive(Debug)]
+pub struct Mailbox {
+    commands: Mutex<VecDeque<ClientCommand>>,
+    state: Mutex<ClientState>,
+    bot_state: Mutex<BotState>,
+}
+
+impl Mailbox {
+    pub fn new() -> Mailbox {
+        Mailbox {
+            commands: Mutex::new(VecDeque::new()),
+            state: Mutex::new(ClientState::default()),
+            bot_state: Mutex::new(BotState::Idle),
+        }
+    }
+    pub fn push(&self, cmd: ClientCommand) {
+        self.commands.lock().unwrap().push_back(cmd);
+    }
+    /// Takes every queued command, oldest first
+    pub fn take(&self) -> Vec<ClientCommand> {
+        self.commands.lock().unwrap().drain(..).collect()
+    }
+    pub fn state(&self) -> ClientState {
+        self.state.lock().unwrap().clone()
+    }
+    pub fn set_state(&self, s: ClientState) {
+        *self.state.lock().unwrap() = s;
+    }
+    pub fn bot_state(&self) -> BotState {
+        *self.bot_state.lock().unwrap()
+    }
+    pub fn set_bot_state(&self, s: BotState) {
+        *self.bot_state.lock().unwrap() = s;
+    }
+}
+
+impl Default for Mailbox {
+    fn default() -> Mailbox {
+        Mailbox::new()
+    }
+}
+
+impl client::Client {
+    /// Runs the commands queued through the control server
+    pub fn handle_commands(&mut self) {
+        for cmd in self.mailbox.take() {
+            self.run_command(cmd);
+        }
+    }
+    pub fn run_command(&mut self, cmd: ClientCommand) {
+        match cmd {
+            ClientCommand::MoveTo { x, y } => {
+                //a target object would overwrite target_position every tick
+                self.objects.target_object = types::ObjectStatusData::new();
+                self.movement.target_position = types::WorldPosition::new_fill(x, y);
+            }
+            ClientCommand::Follow { name } => match self.objects.get_obj_by_name(name.clone()) {
+                Some(v) => {
+                    self.movement.target_position = v.position;
+                    self.objects.target_object = v;
+                }
+                None => warn!("can't see {} to follow", name),
+            },
+            ClientCommand::Stop => {
+                self.objects.target_object = types::ObjectStatusData::new();
+                self.stop_hunting();
+            }
+            ClientCommand::Hunt => self.start_hunting(),
+            ClientCommand::DailyQuest => {
+                self.daily.redeem_next = true;
+                self.fetch_daily_quest();
+            }
+            ClientCommand::Arena => self.enter_arena(),
+            ClientCommand::PetFollow { instance_id } => self.follow_pet(instance_id),
+            ClientCommand::PetUnfollow => self.unfollow_pet(),
+            ClientCommand::PetRelease { instance_id } => self.release_pet(instance_id),
+            ClientCommand::PetFeed { slot, payment } => {
+                if !self.feed_pet(slot, payment) {
+                    warn!("nothing to feed from slot {}, or no pet", slot);
+                }
+            }
+            ClientCommand::PetFuse {
+                pet_one,
+                pet_two,
+                payment,
+            } => self.fuse_pets(pet_one, pet_two, payment),
+            ClientCommand::PetSkin {
+                pet_id,
+                skin_type,
+                payment,
+            } => self.change_pet_skin(pet_id, skin_type, payment),
+            ClientCommand::Nexus => {
+                self.send(ClientPackets::EscapePacket(client_packets::Escape::new()).write());
+            }
+            ClientCommand::Say { text } => self.send_text(text),
+            ClientCommand::Trade { name } => self.send_text(format!("/trade {}", name)),
+            ClientCommand::Reconnect => {
+                self.queue_recon(
+                    client::ReconnectReason::Requested,
+                    self.recon.game_id,
+                    self.recon.game_key.clone(),
+                    self.recon.game_key_time,
+                );
+            }
+        }
+    }
+    /// ``publish_state`` at most every ``PUBLISH_INTERVAL``, and only with the control server on
+    pub fn publish_state_if_due(&mut self) {
+        if !self.config.control.enabled {
+            return;
+        }
+        if self
+            .last_publish
+            .is_some_and(|t| t.elapsed() < PUBLISH_INTERVAL)
+        {
+            return;
+        }
+        self.last_publish = Some(time::Instant::now());
+        self.publish_state();
+    }
+    /// Copies the client's state into the mailbox for the control server
+    pub fn publish_state(&self) {
+        let stat = |s: Stats| self.stats.stat_map.get(&s.stat_to_u8());
+        let inventory = (Stats::INVENTORY0.stat_to_u8()..=Stats::INVENTORY11.stat_to_u8())
+            .map(|i| self.stats.stat_map.get(&i).map_or(-1, |s| s.stat_value))
+            .collect();
+        self.mailbox.set_state(ClientState {
+            connected: self.is_connected,
+            server: self.recon.current_server.clone(),
+            map: self.current_map.clone(),
+            object_id: self.object_id,
+            name: stat(Stats::NAME).map_or_else(String::new, |s| s.str_stat_value.clone()),
+            x: self.movement.current_position.x,
+            y: self.movement.current_position.y,
+            hp: self.predicted_hp().unwrap_or(0),
+            max_hp: stat(Stats::MAXIMUMHP).map_or(0, |s| s.stat_value),
+            inventory,
+            quest_id: self.quest.object_id,
+            quest_distance: self.quest_distance(),
+            hunting: self.quest.hunting,
+            daily_tier: self.daily.quest.as_ref().map(|q| q.tier),
+            daily_redeemed: self.daily.redeemed,
+            login_rewards: self.rewards.received.clone(),
+            pet: self.active_pet(),
+            last_hatch: self.pets.last_hatch.clone(),
+            last_evolve: self.pets.last_evolve,
+            arena_wave: Some(self.arena.wave).filter(|_| self.arena.active),
+        });
+    }
+}
+
+/// A bot the control server can reach
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub id: usize,
+    pub email: String,
+    pub mailbox: Arc<Mailbox>,
+}
+
+#[derive(Serialize, Debug)]
+struct BotView {
+    id: usize,
+    email: String,
+    bot: BotState,
+    #[serde(flatten)]
+    client: ClientState,
+}
+
+impl BotView {
+    fn of(t: &Target) -> BotView {
+        BotView {
+            id: t.id,
+            email: t.email.clone(),
+            bot: t.mailbox.bot_state(),
+            client: t.mailbox.state(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    fn json<T: Serialize>(status: u16, value: &T) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(value).unwrap_or_default(),
+        }
+    }
+    fn text(body: String) -> Response {
+        Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body,
+        }
+    }
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, &serde_json::json!({ "error": message }))
+    }
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            202 => "Accepted",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            _ => "Error",
+        }
+    }
+}
+
+/// What the control server serves
+#[derive(Debug, Clone)]
+pub struct Api {
+    pub targets: Vec<Target>,
+    pub metrics: Arc<metrics::Metrics>,
+}
+
+/// Picks bots by id, email or "all"
+fn select<'a>(targets: &'a [Target], selector: &str) -> Vec<&'a Target> {
+    if selector == "all" {
+        return targets.iter().collect();
+    }
+    match selector.parse::<usize>() {
+        Ok(id) => targets.iter().filter(|t| t.id == id).collect(),
+        Err(_) => targets
+            .iter()
+            .filter(|t| t.email.eq_ignore_ascii_case(selector))
+            .collect(),
+    }
+}
+
+/// Builds the command from the url's command name and the json body holding its arguments
+fn parse_command(name: &str, body: &str) -> Result<ClientCommand, String> {
+    let mut args = if body.trim().is_empty() {
+        serde_json::Map::new()
+    } else {
+        match serde_json::from_str(body) {
+            Ok(serde_json::Value::Object(m)) => m,
+            _ => return Err(String::from("the body has to be a json object")),
+        }
+    };
+    args.insert(
+        String::from("command"),
+        serde_json::Value::String(name.to_owned()),
+    );
+    serde_json::from_value(serde_json::Value::Object(args)).map_err(|e| e.to_string())
+}
+
+/// Routes one request.
+/// GET /bots, GET /bots/{id|email} and POST /bots/{id|email|all}/{command} with the command's arguments as json.
+/// GET /metrics has the fleet's metrics in the prometheus text format
+pub fn handle_request(api: &Api, method: &str, path: &str, body: &str) -> Response {
+    let targets = &api.targets;
+    let path = path.split('?').next().unwrap_or("");
+    let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    match (method, parts.as_slice()) {
+        ("GET", ["metrics"]) => Response::text(api.metrics.render()),
+        ("GET", ["bots"]) => {
+            let views: Vec<BotView> = targets.iter().map(BotView::of).collect();
+            Response::json(200, &views)
+        }
+        ("GET", ["bots", selector]) => match select(targets, selector).first() {
+            Some(t) => Response::json(200, &BotView::of(t)),
+            None => Response::error(404, "no such bot"),
+        },
+        ("POST", ["bots", selector, command]) => {
+            let cmd = match parse_command(command, body) {
+                Ok(c) => c,
+                Err(e) => return Response::error(400, &e),
+            };
+            let picked = select(targets, selector);
+            if picked.is_empty() {
+                return Response::error(404, "no such bot");
+            }
+            for t in picked.iter() {
+                t.mailbox.push(cmd.clone());
+            }
+            Response::json(202, &serde_json::json!({ "queued": picked.len() }))
+        }
+        (_, ["metrics"]) | (_, ["bots"]) | (_, ["bots", _]) | (_, ["bots", _, _]) => {
+            Response::error(405, "method not allowed")
+        }
+        _ => Response::error(404, "not found"),
+    }
+}
+
+/// Reads a request, answers it and closes the connection
+fn serve<S: Read + Write>(mut s: S, api: &Api) -> io::Result<()> {
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 4096];
+    let split = loop {
+        if let Some(i) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i;
+        }
+        if raw.len() > MAX_REQUEST_BYTES {
+            return respond(&mut s, &Response::error(413, "request too large"));
+        }
+        let n = s.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        raw.extend_from_slice(&buf[..n]);
+    };
+    let head = String::from_utf8_lossy(&raw[..split]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("").to_owned();
+    let path = request_line.next().unwrap_or("").to_owned();
+    let length = lines
+        .filter_map(|l| l.split_once(':'))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if length > MAX_REQUEST_BYTES {
+        return respond(&mut s, &Response::error(413, "request too large"));
+    }
+    let mut body = raw[split + 4..].to_vec();
+    while body.len() < length {
+        let n = s.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&buf[..n]);
+    }
+    body.truncate(length);
+    let response = handle_request(api, &method, &path, &String::from_utf8_lossy(&body));
+    respond(&mut s, &response)
+}
+
+/// Serves the connection on a thread of its own, so a slow or idle client doesn't hold up the rest
+fn spawn_serve<S: Read + Write + Send + 'static>(s: S, api: &Arc<Api>) -> io::Result<()> {
+    let api = api.clone();
+    thread::Builder::new()
+        .name(String::from("control-request"))
+        .spawn(move || {
+            if let Err(e) = serve(s, &api) {
+                warn!("control request failed: {}", e);
+            }
+        })?;
+    Ok(())
+}
+
+fn respond<S: Write>(s: &mut S, r: &Response) -> io::Result<()> {
+    let out = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        r.status,
+        r.reason(),
+        r.content_type,
+        r.body.len(),
+        r.body
+    );
+    s.write_all(out.as_bytes())?;
+    s.flush()
+}
+
+enum Listener {
+    Tcp(net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener, PathBuf),
+}
+
+impl Listener {
+    fn bind(bind: &Bind) -> io::Result<Listener> {
+        let l = match bind {
+            Bind::Tcp(addr) => Listener::Tcp(net::TcpListener::bind(addr)?),
+            #[cfg(unix)]
+            Bind::Unix(path) => {
+                //a socket left behind by a previous run would make bind fail, anything else there isn't ours to remove
+                if let Ok(meta) = std::fs::symlink_metadata(path) {
+                    use std::os::unix::fs::FileTypeExt;
+                    if !meta.file_type().is_socket() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{} exists and isn't a socket", path.display()),
+                        ));
+                    }
+                    std::fs::remove_file(path)?;
+                }
+                Listener::Unix(std::os::unix::net::UnixListener::bind(path)?, path.clone())
+            }
+            #[cfg(not(unix))]
+            Bind::Unix(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "unix sockets aren't supported on this platform",
+                ))
+            }
+        };
+        match &l {
+            Listener::Tcp(t) => t.set_nonblocking(true)?,
+            #[cfg(unix)]
+            Listener::Unix(u, _) => u.set_nonblocking(true)?,
+        }
+        Ok(l)
+    }
+    /// Hands one pending connection to ``spawn_serve``. Returns false if nobody was waiting
+    fn accept(&self, api: &Arc<Api>) -> io::Result<bool> {
+        let served = match self {
+            Listener::Tcp(t) => match t.accept() {
+                Ok((s, _)) => {
+                    s.set_nonblocking(false)?;
+                    s.set_read_timeout(Some(IO_TIMEOUT))?;
+                    s.set_write_timeout(Some(IO_TIMEOUT))?;
+                    spawn_serve(s, api)
+                }
+                Err(e) => Err(e),
+            },
+            #[cfg(unix)]
+            Listener::Unix(u, _) => match u.accept() {
+                Ok((s, _)) => {
+                    s.set_nonblocking(false)?;
+                    s.set_read_timeout(Some(IO_TIMEOUT))?;
+                    s.set_write_timeout(Some(IO_TIMEOUT))?;
+                    spawn_serve(s, api)
+                }
+                Err(e) => Err(e),
+            },
+        };
+        match served {
+            Ok(()) => Ok(true),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            if let Listener::Unix(_, path) = self {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// Answers control requests on its own thread until stopped or dropped
+pub struct ControlServer {
+    local_addr: Option<net::SocketAddr>,
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl ControlServer {
+    pub fn start(bind: &str, api: Api) -> io::Result<ControlServer> {
+        let bind = Bind::parse(bind).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let listener = Listener::bind(&bind)?;
+        let local_addr = match &listener {
+            Listener::Tcp(t) => Some(t.local_addr()?),
+            #[cfg(unix)]
+            Listener::Unix(..) => None,
+        };
+        let api = Arc::new(api);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let handle = thread::Builder::new()
+            .name(String::from("control"))
+            .spawn(move || {
+                while !thread_stop.load(Ordering::SeqCst) {
+                    match listener.accept(&api) {
+                        Ok(true) => (),
+                        Ok(false) => thread::sleep(ACCEPT_POLL),
+                        Err(e) => warn!("control request failed: {}", e),
+                    }
+                }
+            })?;
+        Ok(ControlServer {
+            local_addr,
+            stop,
+            handle: Some(handle),
+        })
+    }
+    /// The tcp address it listens on, handy when binding to port 0. None for unix sockets
+    pub fn local_addr(&self) -> Option<net::SocketAddr> {
+        self.local_addr
+    }
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
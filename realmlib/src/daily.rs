@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::client;
+use super::network::packets::client_packets;
+use super::network::packets::client_packets::ClientPacket;
+use super::network::packets::client_packets::ClientPackets;
+use super::network::packets::server_packets;
+use super::network::types;
+use super::network::types::Stats;
+
+/* daily.rs - The daily quests: fetch the current one, find the items it wants and redeem them */
+
+/// Slots we hand items over from: the inventory after the gear, then the backpack
+const INVENTORY_SLOTS: std::ops::RangeInclusive<u8> = 4..=11;
+const BACKPACK_SLOTS: std::ops::RangeInclusive<u8> = 12..=19;
+
+/// Daily quest settings, under "daily_quests" in the settings file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DailySettings {
+    pub enabled: bool,     //fetch the quest as soon as the bot joins the nexus
+    pub auto_redeem: bool, //redeem as soon as the quest comes in and we have its items
+}
+
+impl Default for DailySettings {
+    fn default() -> DailySettings {
+        DailySettings {
+            enabled: false,
+            auto_redeem: true,
+        }
+    }
+}
+
+/// The quest from a QuestFetchResponse
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyQuest {
+    pub tier: i32,
+    pub goal: String,
+    pub description: String,
+    pub image: String,
+    pub requirements: Vec<i32>, //item types, parsed from the goal
+}
+
+impl DailyQuest {
+    pub fn new(q: &server_packets::QuestFetchResponse) -> DailyQuest {
+        DailyQuest {
+            tier: q.tier,
+            goal: q.goal.clone(),
+            description: q.description.clone(),
+            image: q.image.clone(),
+            requirements: parse_goal(&q.goal),
+        }
+    }
+}
+
+/// The goal holds the item types the quest wants, "0xa22,0xa23" or "2594". Skips anything that isn't one
+pub fn parse_goal(goal: &str) -> Vec<i32> {
+    goal.split(',')
+        .map(|s| s.trim())
+        .filter_map(
+            |s| match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                Some(hex) => i32::from_str_radix(hex, 16).ok(),
+                None => s.parse().ok(),
+            },
+        )
+        .collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DailyQuests {
+    pub quest: Option<DailyQuest>, //None until a QuestFetchResponse with a goal comes in
+    pub fetching: bool,
+    pub redeeming: bool,
+    pub redeem_next: bool, //redeem the next quest that comes in, even without auto_redeem
+    pub redeemed: u32,
+    pub last_message: String, //from the last QuestRedeemResponse
+}
+
+impl DailyQuests {
+    pub fn new() -> DailyQuests {
+        DailyQuests::default()
+    }
+    /// Forgets the requests in flight, their answers went with the connection
+    pub fn reset(&mut self) {
+        self.fetching = false;
+        self.redeeming = false;
+    }
+}
+
+impl client::Client {
+    /// The item type in an inventory slot, 0-11 like the INVENTORY stats and 12-19 for the backpack. None if it's empty
+    pub fn inventory_item(&self, slot: u8) -> Option<i32> {
+        let stat = match slot {
+            0..=11 => Stats::INVENTORY0.stat_to_u8() + slot,
+            12..=19 => Stats::BACKPACK0.stat_to_u8() + slot - 12,
+            _ => return None,
+        };
+        self.stats
+            .stat_map
+            .get(&stat)
+            .map(|s| s.stat_value)
+            .filter(|item| *item != -1)
+    }
+    /// The slots outside the gear, the backpack's too if we have one
+    fn item_slots(&self) -> impl Iterator<Item = u8> {
+        let backpack = self.stats.get_item(Stats::HASBACKPACK).unwrap_or(0) == 1;
+        INVENTORY_SLOTS.chain(BACKPACK_SLOTS.filter(move |_| backpack))
+    }
+    /// The first slot outside the gear holding the item type
+    pub fn find_item(&self, item_type: i32) -> Option<u8> {
+        self.item_slots()
+            .find(|slot| self.inventory_item(*slot) == Some(item_type))
+    }
+    /// A slot of its own for each item the daily quest wants, in order, and the item types left without one
+    fn daily_item_slots(&self) -> (Vec<u8>, Vec<i32>) {
+        let mut slots = Vec::new();
+        let mut missing = Vec::new();
+        let requirements = self.daily.quest.iter().flat_map(|q| q.requirements.iter());
+        for item in requirements {
+            match self
+                .item_slots()
+                .find(|s| !slots.contains(s) && self.inventory_item(*s) == Some(*item))
+            {
+                Some(slot) => slots.push(slot),
+                None => missing.push(*item),
+            }
+        }
+        (slots, missing)
+    }
+    /// Item types the daily quest wants that aren't in the inventory. An item wanted twice needs two slots
+    pub fn missing_daily_items(&self) -> Vec<i32> {
+        self.daily_item_slots().1
+    }
+    /// Asks the server for the current daily quest, the answer is handled by ``on_questfetchresponse``
+    pub fn fetch_daily_quest(&mut self) {
+        self.daily.fetching = true;
+        self.send(ClientPackets::QuestFetchAskPacket(client_packets::QuestFetchAsk::new()).write());
+    }
+    /// Redeems the daily quest if every item it wants is in the inventory. The packet only has room for
+    /// one slot, so it names the first goal item's and the server checks the rest
+    pub fn redeem_daily_quest(&mut self) -> bool {
+        let item = match self
+            .daily
+            .quest
+            .as_ref()
+            .and_then(|q| q.requirements.first())
+        {
+            Some(item) => *item,
+            None => return false,
+        };
+        let (slots, missing) = self.daily_item_slots();
+        if !missing.is_empty() {
+            info!("can't redeem the daily quest, missing {:?}", missing);
+            return false;
+        }
+        let mut qr = client_packets::QuestRedeem::new();
+        qr.slot = types::SlotObjectData {
+            object_id: self.object_id,
+            slot_id: slots[0],
+            object_type: item,
+        };
+        self.daily.redeeming = true;
+        self.daily.redeem_next = false;
+        self.send(ClientPackets::QuestRedeemPacket(qr).write());
+        true
+    }
+    pub fn got_daily_quest(&mut self, q: &server_packets::QuestFetchResponse) {
+        self.daily.fetching = false;
+        let quest = DailyQuest::new(q);
+        if quest.requirements.is_empty() {
+            info!("no daily quest to do (tier {})", q.tier);
+            self.daily.quest = None;
+            return;
+        }
+        info!(
+            "daily quest tier {}: {} wants {:?}",
+            quest.tier, quest.description, quest.requirements
+        );
+        self.daily.quest = Some(quest);
+        if self.config.daily_quests.auto_redeem || self.daily.redeem_next {
+            self.redeem_daily_quest();
+        }
+    }
+    /// On success the next tier gets fetched straight away
+    pub fn daily_quest_redeemed(&mut self, r: &server_packets::QuestRedeemResponse) {
+        self.daily.redeeming = false;
+        self.daily.last_message = r.message.clone();
+        if !r.success {
+            warn!("daily quest redeem failed: {}", r.message);
+            return;
+        }
+        self.daily.redeemed += 1;
+        self.metrics.daily_quest();
+        info!("redeemed the daily quest! {}", r.message);
+        self.daily.quest = None;
+        self.fetch_daily_quest();
+    }
+}
@@ -1,50 +1,79 @@
 use crate::client;
+use crate::client::ReconnectReason;
 use crate::network::packets::client_packets;
 use crate::network::packets::client_packets::ClientPacket;
 use crate::network::packets::client_packets::ClientPackets;
 use crate::network::packets::server_packets;
+use tracing::{debug, info, warn};
 
 /* game.rs - Contains the functions for game processing. Packet handling, game-related functions/utils, etc */
 
 impl client::Client {
     pub fn on_failure(&mut self, f: server_packets::Failure) {
-        println!("Failure! {0}: {1}", f.failure_id, f.failure_message);
+        warn!(failure_id = f.failure_id, "Failure! {}", f.failure_message);
+        self.recon.last_failure_id = Some(f.failure_id);
+        self.metrics.failure(f.failure_id);
         self.handle_failure(&f);
     }
     pub fn on_map_info(&mut self, mp: server_packets::MapInfo) {
         self.movement.map_width = mp.width;
         self.movement.map_height = mp.height;
+        self.current_map = mp.name;
+        self.health.escaping = false;
+        self.arena_joined();
+        self.span.record("map", self.current_map.as_str());
+        if self.needs_character() {
+            self.create_character();
+            return;
+        }
         let mut load = client_packets::Load::new();
         load.char_id = self.base.char_id;
-        self.current_map = mp.name;
         self.send(ClientPackets::LoadPacket(load).write());
     }
     pub fn on_create_success(&mut self, cs: server_packets::CreateSuccess) {
         self.object_id = cs.object_id;
+        if self.bootstrap.creating {
+            self.character_created(cs.char_id);
+        }
         self.recon.reset();
-        println!(
-            "{0} joined {2}! ObjectID: {1}",
-            self.base.email, self.object_id, self.current_map
-        );
+        info!(object_id = self.object_id, "joined {}!", self.current_map);
+        if self.config.hunt.enabled && !self.quest.hunting {
+            self.start_hunting();
+        }
+        let daily = self.config.daily_quests.enabled && self.current_map == "Nexus";
+        if daily && self.daily.quest.is_none() && !self.daily.fetching {
+            self.fetch_daily_quest();
+        }
+        self.claim_login_rewards();
+        self.auto_enter_arena();
     }
     pub fn on_update(&mut self, u: server_packets::Update) {
         self.send(ClientPackets::UpdateAckPacket(client_packets::UpdateAck::new()).write());
         self.parse_update(&u);
+        self.check_name();
     }
     pub fn on_new_tick(&mut self, nt: server_packets::NewTick) {
         let mut mov = client_packets::Move::new();
-        self.time_keeper.last_tick_time = self.time_keeper.current_tick_time;
-        self.time_keeper.current_tick_time = self.time_keeper.get_time();
+        self.time_keeper.on_tick(nt.tick_time);
         self.movement.tick_count += 1;
-        if self.current_map != "Nexus" {
+        let busy = self.arena.active || self.quest.hunting;
+        if self.current_map != "Nexus" && !busy {
             self.send(ClientPackets::EscapePacket(client_packets::Escape::new()).write());
-            
+        }
+        if self.arena.active {
+            self.fight_arena();
+        } else if self.quest.hunting {
+            self.hunt_quest();
         }
         self.move_to(self.movement.target_position);
         mov.tick_id = nt.tick_id;
-        mov.time = self.time_keeper.get_time();
+        mov.time = self.time_keeper.current_tick_time;
         mov.new_position = self.movement.current_position;
+        mov.records = self.move_records();
+        self.report_ground_damage(&mov.records);
         self.send(ClientPackets::MovePacket(mov).write());
+        self.metrics
+            .tick_latency(self.time_keeper.last_receive.elapsed());
         self.parse_newtick(&nt);
         self.movement.last_tick_id = nt.tick_id;
     }
@@ -55,7 +84,11 @@ impl client::Client {
         self.send(ClientPackets::PongPacket(pong).write());
     }
     pub fn on_reconnect(&mut self, r: server_packets::Reconnect) {
-        println!("{:?}", r);
+        debug!("{:?}", r);
+        if r.is_from_arena {
+            info!("leaving the arena after {} waves", self.arena.wave);
+            self.arena.active = false;
+        }
         if self.recon.blocking_reconnects == true {
             return;
         }
@@ -63,8 +96,13 @@ impl client::Client {
             self.recon.previous_server = self.recon.current_server.clone();
             self.recon.current_server = r.host;
         }
-        println!("Reconnecting to {}", r.name);
-        self.queue_recon(r.game_id, r.key, r.key_time as u32);
+        info!("Reconnecting to {}", r.name);
+        self.queue_recon(
+            ReconnectReason::Requested,
+            r.game_id,
+            r.key,
+            r.key_time as u32,
+        );
     }
     pub fn on_allyshoot(&mut self, _a: server_packets::AllyShoot) {}
     pub fn on_goto(&mut self, gt: server_packets::Goto) {
@@ -79,7 +117,9 @@ impl client::Client {
         let mut ack = client_packets::AoEAck::new();
         ack.position = self.movement.current_position;
         ack.time = self.time_keeper.get_time();
-        if self.movement.current_position.distance_to(&a.position) < a.radius {}
+        if self.movement.current_position.distance_to(&a.position) < a.radius {
+            self.hit_by_aoe(&a);
+        }
         self.send(ClientPackets::AoEAckPacket(ack).write());
     }
     pub fn on_server_player_shoot(&mut self, s: server_packets::ServerPlayerShoot) {
@@ -102,7 +142,7 @@ impl client::Client {
     pub fn on_tradedone(&mut self, _td: server_packets::TradeDone) {}
     pub fn on_traderequested(&mut self, tr: server_packets::TradeRequested) {
         let mut rt = client_packets::RequestTrade::new();
-        println!("{} requests a trade!", &tr.name);
+        info!("{} requests a trade!", &tr.name);
         rt.player_name = tr.name;
         self.send(ClientPackets::RequestTradePacket(rt).write());
     }
@@ -114,32 +154,64 @@ impl client::Client {
     }
     pub fn on_invresult(&mut self, _i: server_packets::InvResult) {}
     pub fn on_accountlist(&mut self, _a: server_packets::AccountList) {}
-    pub fn on_questobjid(&mut self, _q: server_packets::QuestObjID) {}
+    pub fn on_questobjid(&mut self, q: server_packets::QuestObjID) {
+        if self.quest.object_id != Some(q.object_id) {
+            info!("new quest: {}", q.object_id);
+        }
+        self.quest.object_id = Some(q.object_id);
+    }
     pub fn on_death(&mut self, d: server_packets::Death) {
-        println!("{0} died, killed by {1}", self.base.email, d.killed_by);
+        warn!("died, killed by {}", d.killed_by);
+        self.metrics.death();
+        self.history.deaths += 1;
     }
     pub fn on_damage(&mut self, _d: server_packets::Damage) {}
     pub fn on_showeffect(&mut self, _s: server_packets::ShowEffect) {}
     pub fn on_buyresult(&mut self, _br: server_packets::BuyResult) {}
-    pub fn on_nameresult(&mut self, _nr: server_packets::NameResult) {}
+    pub fn on_nameresult(&mut self, nr: server_packets::NameResult) {
+        self.name_result(&nr);
+    }
     pub fn on_createguildresult(&mut self, _cgr: server_packets::CreateGuildResult) {}
     pub fn on_clientstat(&mut self, _cs: server_packets::ClientStat) {}
     pub fn on_invited_to_guild(&mut self, _itg: server_packets::InvitedToGuild) {}
-    pub fn on_loginrewardrecv(&mut self, _lrr: server_packets::LoginRewardRecv) {}
+    pub fn on_loginrewardrecv(&mut self, lrr: server_packets::LoginRewardRecv) {
+        self.got_login_reward(&lrr);
+    }
     pub fn on_keyinforesponse(&mut self, _kir: server_packets::KeyInfoResponse) {}
-    pub fn on_questredeemresponse(&mut self, _qrr: server_packets::QuestRedeemResponse) {}
-    pub fn on_questfetchresponse(&mut self, _qfr: server_packets::QuestFetchResponse) {}
+    pub fn on_questredeemresponse(&mut self, qrr: server_packets::QuestRedeemResponse) {
+        self.daily_quest_redeemed(&qrr);
+    }
+    pub fn on_questfetchresponse(&mut self, qfr: server_packets::QuestFetchResponse) {
+        self.got_daily_quest(&qfr);
+    }
     pub fn on_passwordprompt(&mut self, _pp: server_packets::PasswordPrompt) {}
     pub fn on_reskinunlock(&mut self, _ru: server_packets::ReSkinUnlock) {}
     pub fn on_verifyemail(&mut self, _ve: server_packets::VerifyEmail) {}
-    pub fn on_arenadeath(&mut self, _ad: server_packets::ArenaDeath) {}
-    pub fn on_imminentarenawave(&mut self, _iaw: server_packets::ImminentArenaWave) {}
-    pub fn on_hatchpet(&mut self, _hp: server_packets::HatchPet) {}
-    pub fn on_deletepet(&mut self, _dp: server_packets::DeletePet) {}
-    pub fn on_evolvepet(&mut self, _ep: server_packets::EvolvePet) {}
-    pub fn on_petyardupdate(&mut self, _pyu: server_packets::PetYardUpdate) {}
-    pub fn on_newability(&mut self, _na: server_packets::NewAbility) {}
-    pub fn on_activepetupdaterecv(&mut self, _apur: server_packets::ActivePetUpdateRecv) {}
+    pub fn on_arenadeath(&mut self, ad: server_packets::ArenaDeath) {
+        self.arena_death(&ad);
+    }
+    pub fn on_imminentarenawave(&mut self, iaw: server_packets::ImminentArenaWave) {
+        self.arena_wave(&iaw);
+    }
+    pub fn on_hatchpet(&mut self, hp: server_packets::HatchPet) {
+        self.pet_hatched(&hp);
+    }
+    pub fn on_deletepet(&mut self, dp: server_packets::DeletePet) {
+        self.pet_deleted(dp.pet_id);
+    }
+    pub fn on_evolvepet(&mut self, ep: server_packets::EvolvePet) {
+        self.pet_evolved(&ep);
+    }
+    pub fn on_petyardupdate(&mut self, pyu: server_packets::PetYardUpdate) {
+        self.pets.yard_type = Some(pyu.yard_type);
+    }
+    pub fn on_newability(&mut self, na: server_packets::NewAbility) {
+        info!("the pet learned ability {}", na.ability_type);
+    }
+    pub fn on_activepetupdaterecv(&mut self, apur: server_packets::ActivePetUpdateRecv) {
+        debug!("active pet is {}", apur.instance_id);
+        self.pets.instance_id = Some(apur.instance_id);
+    }
     pub fn on_realmheroleft(&mut self, _rh: server_packets::RealmHeroLeft) {}
     pub fn on_playsound(&mut self, _ps: server_packets::PlaySound) {}
     pub fn on_file(&mut self, _f: server_packets::File) {}
@@ -152,8 +224,8 @@ impl client::Client {
             if t.name == "{REDACTED}" { //insert ign of your main
                 let mut args = t.message.split_whitespace();
                 match args.next().unwrap() {
-                    "tiles" => println!("Tiles: {:?}", self.movement.tiles),
-                    "drops" => println!("Drops: {:?}", self.goods.drops),
+                    "tiles" => info!("Tiles: {:?}", self.movement.tiles),
+                    "drops" => info!("Drops: {:?}", self.goods.drops),
                     "grab" => {
                         match self
                             .objects
@@ -166,7 +238,12 @@ impl client::Client {
                         }
                     }
                     "vault" => {
-                        self.queue_recon(-5, Vec::new(), u32::max_value());
+                        self.queue_recon(
+                            ReconnectReason::Requested,
+                            -5,
+                            Vec::new(),
+                            u32::max_value(),
+                        );
                     }
                     "nexus" => {
                         self.send(
@@ -174,7 +251,12 @@ impl client::Client {
                         );
                     }
                     "fnexus" => {
-                        self.queue_recon(-2, Vec::new(), u32::max_value());
+                        self.queue_recon(
+                            ReconnectReason::Requested,
+                            -2,
+                            Vec::new(),
+                            u32::max_value(),
+                        );
                     }
                     "enter" => {
                         let mut up = client_packets::UsePortal::new();
@@ -199,6 +281,7 @@ impl client::Client {
                     },
                     "recon" => {
                         self.queue_recon(
+                            ReconnectReason::Requested,
                             self.recon.game_id,
                             self.recon.game_key.clone(),
                             self.recon.game_key_time,
@@ -217,7 +300,7 @@ impl client::Client {
                         self.send(ClientPackets::TeleportPacket(tp).write());
                     }
                     "range" => {
-                        println!(
+                        info!(
                             "Found: {:?}",
                             self.objects.get_entities_in_range(
                                 &self.movement.current_position,
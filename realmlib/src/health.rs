@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use super::client;
+use super::network::packets::client_packets;
+use super::network::packets::client_packets::ClientPacket;
+use super::network::packets::client_packets::ClientPackets;
+use super::network::packets::server_packets;
+use super::network::types;
+use super::network::types::Effects;
+use super::network::types::Stats;
+
+/* health.rs - Predicts the client's hp between stat updates, and escapes to the nexus when hp runs low */
+
+/// Damage never drops below this share of the raw damage, however high the defense
+pub const MIN_DAMAGE_FRACTION: f32 = 0.15;
+/// Defense multiplier while armored
+pub const ARMORED_MULT: f32 = 2.0;
+pub const PETRIFIED_MULT: f32 = 0.9;
+pub const CURSE_MULT: f32 = 1.2;
+
+/// Safety settings, under "safety" in the settings file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SafetySettings {
+    pub nexus_hp_percent: f32, //escape once the predicted hp drops to this share of max hp, 0 disables it
+}
+
+impl Default for SafetySettings {
+    fn default() -> SafetySettings {
+        SafetySettings {
+            nexus_hp_percent: 0.3,
+        }
+    }
+}
+
+/// What the client predicts about itself until the server's next stat update
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    pub hp: Option<i32>, //None until the server sends our hp
+    pub escaping: bool,  //an auto nexus Escape went out and we're still on the map
+    pub aoe_hits: u64,
+    pub damage_taken: u64,
+}
+
+impl Health {
+    pub fn new() -> Health {
+        Health::default()
+    }
+    /// Forgets the predictions, the counters stay
+    pub fn reset(&mut self) {
+        self.hp = None;
+        self.escaping = false;
+    }
+}
+
+impl client::Client {
+    pub fn max_hp(&self) -> Option<i32> {
+        self.stats.get_item(Stats::MAXIMUMHP)
+    }
+    /// Our hp with the damage taken since the server's last update
+    pub fn predicted_hp(&self) -> Option<i32> {
+        self.health.hp.or_else(|| self.stats.get_item(Stats::HP))
+    }
+    /// Takes the server's hp over the predicted one, if the stats that came in carry it.
+    /// Most ticks don't, and the prediction has to survive those
+    pub fn sync_health(&mut self, stats: &HashMap<u8, types::StatData>) {
+        if let Some(hp) = stats.get(&Stats::HP.stat_to_u8()) {
+            self.health.hp = Some(hp.stat_value);
+        }
+    }
+    /// Damage after our defense and conditions, worked out the way the official client does
+    pub fn damage_with_defense(&self, damage: i32, armor_pierce: bool) -> i32 {
+        if self.has_effect(Effects::INVINCIBLE) || self.has_effect(Effects::INVULNERABLE) {
+            return 0;
+        }
+        let mut def = self.stats.get_item(Stats::DEFENSE).unwrap_or(0) as f32;
+        if armor_pierce || self.has_effect(Effects::ARMORBROKEN) {
+            def = 0.0;
+        } else if self.has_effect(Effects::ARMORED) {
+            def *= ARMORED_MULT;
+        }
+        let raw = damage as f32;
+        let mut d = (raw - def).max(raw * MIN_DAMAGE_FRACTION);
+        if self.has_effect(Effects::PETRIFIED) {
+            d *= PETRIFIED_MULT;
+        }
+        if self.has_effect(Effects::CURSE) {
+            d *= CURSE_MULT;
+        }
+        d as i32
+    }
+    /// Lowers the predicted hp and checks whether it's time to leave
+    pub fn take_damage(&mut self, damage: i32) {
+        if damage <= 0 {
+            return;
+        }
+        self.health.damage_taken += damage as u64;
+        self.metrics.damage_taken(damage as u64);
+        if let Some(hp) = self.predicted_hp() {
+            self.health.hp = Some(hp - damage);
+        }
+        self.check_safety();
+    }
+    /// Applies an AoE we're standing in
+    pub fn hit_by_aoe(&mut self, a: &server_packets::AoE) {
+        let damage = self.damage_with_defense(i32::from(a.damage), a.armor_pierce);
+        debug!(
+            "hit by an aoe for {} ({} raw), effect {}",
+            damage, a.damage, a.effects
+        );
+        self.health.aoe_hits += 1;
+        self.metrics.aoe_hit();
+        self.apply_effect(a.effects, (a.effect_duration * 1000.0) as i32);
+        self.take_damage(damage);
+    }
+    /// Escapes to the nexus once the predicted hp drops to ``safety.nexus_hp_percent`` of max hp
+    pub fn check_safety(&mut self) {
+        let percent = self.config.safety.nexus_hp_percent;
+        if percent <= 0.0 || self.health.escaping || self.current_map == "Nexus" {
+            return;
+        }
+        let (hp, max) = match (self.predicted_hp(), self.max_hp()) {
+            (Some(hp), Some(max)) if max > 0 => (hp, max),
+            _ => return,
+        };
+        if hp as f32 > max as f32 * percent {
+            return;
+        }
+        warn!("hp is down to {}/{}, escaping to the nexus", hp, max);
+        self.health.escaping = true;
+        self.metrics.auto_nexus();
+        self.send(ClientPackets::EscapePacket(client_packets::Escape::new()).write());
+    }
+}
@@ -1,16 +1,36 @@
-use std::fs::File;
-use std::io::BufReader;
+use std::io;
+use std::sync::Arc;
 use std::thread;
 use std::time;
 
 extern crate serde;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use account::IAccount;
 
 pub mod account;
+pub mod arena;
+pub mod bootstrap;
 pub mod client;
+pub mod conditions;
+pub mod config;
+pub mod control;
+pub mod daily;
 pub mod game;
+pub mod health;
+pub mod logging;
+pub mod manager;
+pub mod metrics;
 pub mod network;
+pub mod pets;
+pub mod quests;
+pub mod rewards;
+pub mod servers;
+pub mod snapshot;
+pub mod tiles;
 pub mod utils;
+pub mod web;
 
 /// Creates a new account object
 pub fn new_account() {}
@@ -18,44 +38,83 @@ pub fn new_account() {}
 impl client::Client {
     /// Sets variables that might not be created at creation time, then starts the client. Returns the Client back if it ever needs to be relaunched.
     pub fn start(mut self, gid: i32, key: Vec<u8>, key_time: u32) -> Self {
+        //error level, so the span gets past any filter and every event keeps the client's context
+        self.span = tracing::error_span!(
+            "client",
+            email = %self.base.email,
+            ign = tracing::field::Empty,
+            map = tracing::field::Empty
+        );
+        let span = self.span.clone();
+        let _entered = span.enter();
         self.is_running = true;
         self.time_keeper.thread_delay_ms = self.config.thread_delay_ms as i32;
+        self.recon.policy = self.config.reconnect.clone();
         self.recon.game_id = gid;
         self.recon.game_key = key;
         self.recon.game_key_time = key_time;
+        self.resume_snapshot();
+        if self.base.fetch_new_data {
+            if let Err(e) = self.get_new_char_id() {
+                warn!("couldn't fetch account data: {}", e);
+            }
+        }
+        if self.config.login_rewards.enabled && self.rewards.calendar.is_empty() {
+            if let Err(e) = self.fetch_login_calendar() {
+                warn!("couldn't fetch the login calendar: {}", e);
+            }
+        }
+        self.select_server();
         self.recon.current_server = self.base.server_ip.clone();
         self.game_loop();
         self
     }
 }
 
-/// Launches ALL accounts in the supplied Vec
+/// Launches ALL accounts in the supplied Vec. Clients without a governor are spaced out by 3 seconds instead
 pub fn launch_clients(mut clients: Vec<client::Client>) {
     clients.reverse(); //load the accounts in the same order as the accounts file
     for _ in 0..clients.len() {
-        launch_client(clients.pop().unwrap());
-        thread::sleep(time::Duration::from_millis(3000));
+        let client = clients.pop().unwrap();
+        let governed = client.game_connection.governor.is_some();
+        launch_client(client);
+        if !governed {
+            thread::sleep(time::Duration::from_millis(3000));
+        }
     }
 }
 
-pub fn launch_client(client: client::Client) {
+pub fn launch_client(client: client::Client) -> thread::JoinHandle<client::Client> {
+    spawn_client(client).unwrap()
+}
+
+/// Starts the client on its own thread. The handle gives the Client back once it stops
+pub fn spawn_client(client: client::Client) -> io::Result<thread::JoinHandle<client::Client>> {
     thread::Builder::new()
         .name(client.base.email.clone())
         .stack_size(client.config.client_thread_stack_size_kb * 1024)
-        .spawn(move || {
-            client.start(-2, Vec::new(), u32::max_value());
-        })
-        .unwrap();
-}
-
-pub fn read_accounts() -> Vec<account::Account> {
-    account::read_accounts()
+        .spawn(move || client.start(-2, Vec::new(), u32::max_value()))
 }
 
+/// Creates the clients, handing the shared proxy pool (if configured) to accounts without a proxy of their own
+/// and the server list and tile definitions files (if configured), the snapshot store and the connection governor to everyone
 pub fn accounts_to_clients(mut accounts: Vec<account::Account>, c: Config) -> Vec<client::Client> {
+    let pool = network::pool::ProxyPool::from_settings(&c.proxy_pool);
+    let server_list = servers::load_server_list(&c.server_list_file);
+    let tile_defs = tiles::load_tile_defs(&c.tiles_file);
+    let governor = network::governor::Governor::from_config(&c);
+    let store = snapshot::SnapshotStore::from_settings(&c.snapshots).map(Arc::new);
     let mut cli = Vec::new();
     for _ in 0..accounts.len() {
-        cli.push(accounts.pop().unwrap().create_client(c.clone()));
+        let mut client = accounts.pop().unwrap().create_client(c.clone());
+        if !client.base.use_socks && !client.base.use_http {
+            client.game_connection.proxy_pool = pool.clone();
+        }
+        client.servers = server_list.clone();
+        client.tiles = tile_defs.clone();
+        client.game_connection.governor = Some(governor.clone());
+        client.snapshots = store.clone();
+        cli.push(client);
     }
     cli.reverse(); //original order
     cli
@@ -72,13 +131,58 @@ pub struct Config {
     pub factory_stack_size_kb: usize,
     pub client_thread_stack_size_kb: usize,
     pub save_delay_secs: i32,
+    #[serde(default = "default_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+    #[serde(default = "default_read_timeout_ms")]
+    pub read_timeout_ms: u64,
+    #[serde(default = "default_write_timeout_ms")]
+    pub write_timeout_ms: u64,
+    #[serde(default)]
+    pub reconnect: client::ReconnectPolicy,
+    #[serde(default)]
+    pub proxy_pool: network::pool::PoolSettings,
+    #[serde(default = "default_api_base_url")]
+    pub api_base_url: String,
+    #[serde(default)]
+    pub server_list_file: String,
+    #[serde(default)]
+    pub tiles_file: String,
+    #[serde(default)]
+    pub supervisor: manager::SupervisorSettings,
+    #[serde(default)]
+    pub control: control::ControlSettings,
+    #[serde(default)]
+    pub governor: network::governor::GovernorSettings,
+    #[serde(default)]
+    pub logging: logging::LogSettings,
+    #[serde(default)]
+    pub snapshots: snapshot::SnapshotSettings,
+    #[serde(default)]
+    pub safety: health::SafetySettings,
+    #[serde(default)]
+    pub hunt: quests::HuntSettings,
+    #[serde(default)]
+    pub daily_quests: daily::DailySettings,
+    #[serde(default)]
+    pub login_rewards: rewards::LoginRewardSettings,
+    #[serde(default)]
+    pub arena: arena::ArenaSettings,
+    #[serde(default)]
+    pub bootstrap: bootstrap::BootstrapSettings,
 }
 
-impl Config {
-    pub fn new() -> Config {
-        let c: Config =
-            serde_json::from_reader(BufReader::new(File::open("config/settings.json").unwrap()))
-                .unwrap();
-        c
-    }
+fn default_connect_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_read_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_write_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_api_base_url() -> String {
+    String::from(web::DEFAULT_API_BASE)
 }
@@ -0,0 +1,105 @@
+use std::fs;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer, Registry};
+
+/* logging.rs - Sets up where the crate's tracing events go: stderr and/or a json lines file, each with its own filter */
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    Text,
+    /// One json object per line, with the client span's fields
+    Json,
+}
+
+/// Logging settings, under "logging" in the settings file.
+/// Filters use the env_logger syntax, like "info,realmlib::network=trace"
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LogSettings {
+    pub filter: String,
+    pub stderr: bool,
+    pub stderr_format: LogFormat,
+    pub file: String,        //json lines are appended here, empty disables the file
+    pub file_filter: String, //empty uses 'filter'
+}
+
+impl Default for LogSettings {
+    fn default() -> LogSettings {
+        LogSettings {
+            filter: String::from("info"),
+            stderr: true,
+            stderr_format: LogFormat::Text,
+            file: String::new(),
+            file_filter: String::new(),
+        }
+    }
+}
+
+impl LogSettings {
+    pub fn file_filter(&self) -> &str {
+        if self.file_filter.is_empty() {
+            &self.filter
+        } else {
+            &self.file_filter
+        }
+    }
+}
+
+/// Checks a filter string without installing anything
+pub fn parse_filter(filter: &str) -> Result<EnvFilter, String> {
+    EnvFilter::builder()
+        .parse(filter)
+        .map_err(|e| format!("bad filter {:?}: {}", filter, e))
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// The layers for the settings, without installing them
+pub fn layers(settings: &LogSettings) -> Result<Vec<BoxedLayer>, String> {
+    let mut layers: Vec<BoxedLayer> = Vec::new();
+    if settings.stderr {
+        let filter = parse_filter(&settings.filter)?;
+        let layer = fmt::layer().with_writer(std::io::stderr);
+        layers.push(match settings.stderr_format {
+            LogFormat::Text => layer.with_filter(filter).boxed(),
+            LogFormat::Json => layer
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_filter(filter)
+                .boxed(),
+        });
+    }
+    if !settings.file.is_empty() {
+        let filter = parse_filter(settings.file_filter())?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&settings.file)
+            .map_err(|e| format!("couldn't open {}: {}", settings.file, e))?;
+        layers.push(
+            fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .with_filter(filter)
+                .boxed(),
+        );
+    }
+    Ok(layers)
+}
+
+/// Installs the global subscriber. Fails if the settings are bad or a subscriber is already installed
+pub fn init(settings: &LogSettings) -> Result<(), String> {
+    tracing_subscriber::registry()
+        .with(layers(settings)?)
+        .try_init()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,378 @@
+use std::collections::VecDeque;
+use std::io;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time;
+
+extern crate ctrlc;
+
+use serde::{Deserialize, Serialize};
+
+use tracing::{error, info, warn};
+
+use super::account;
+use super::client;
+use super::control;
+use super::metrics;
+use super::network::governor;
+use super::network::pool;
+use super::servers;
+use super::snapshot;
+use super::tiles;
+
+/* manager.rs - Owns the client threads, stops/restarts bots and brings them down cleanly on shutdown */
+
+/// What the supervisor does when a client thread ends without being asked to
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    Never,
+    /// Restart client threads that panicked
+    OnCrash,
+    /// Also restart clients that returned on their own, like after giving up on reconnecting
+    Always,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SupervisorSettings {
+    pub restart: RestartPolicy,
+    pub max_restarts: u32, //per bot within restart_window_secs, 0 means unlimited
+    pub restart_window_secs: u64,
+    pub restart_delay_ms: u64,
+}
+
+impl Default for SupervisorSettings {
+    fn default() -> SupervisorSettings {
+        SupervisorSettings {
+            restart: RestartPolicy::OnCrash,
+            max_restarts: 5,
+            restart_window_secs: 600,
+            restart_delay_ms: 5000,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BotState {
+    /// Added but never launched
+    Idle,
+    Running,
+    /// Stopped through the manager
+    Stopped,
+    /// The client returned from start() on its own
+    Finished,
+    /// The client thread panicked
+    Crashed,
+    /// Ended and ran out of restarts, or the policy doesn't restart it
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct BotStatus {
+    pub id: usize,
+    pub email: String,
+    pub state: BotState,
+    pub restarts: u32,
+}
+
+struct ManagedBot {
+    account: account::Account, //untouched copy, crashed clients are rebuilt from it
+    config: crate::Config,
+    proxy_pool: Option<Arc<pool::ProxyPool>>,
+    servers: Option<Arc<servers::ServerList>>,
+    tiles: Option<Arc<tiles::TileDefs>>,
+    governor: Option<Arc<governor::Governor>>,
+    snapshots: Option<Arc<snapshot::SnapshotStore>>,
+    stop_signal: Arc<AtomicBool>,
+    mailbox: Arc<control::Mailbox>,
+    metrics: Arc<metrics::Metrics>,
+    handle: Option<thread::JoinHandle<client::Client>>,
+    client: Option<client::Client>, //the client while it isn't running
+    state: BotState,
+    restarts: u32,
+    restart_times: VecDeque<time::Instant>,
+    restart_at: Option<time::Instant>,
+}
+
+impl ManagedBot {
+    fn fresh_client(&self) -> client::Client {
+        let mut c = self.account.clone().create_client(self.config.clone());
+        c.game_connection.proxy_pool = self.proxy_pool.clone();
+        c.servers = self.servers.clone();
+        c.tiles = self.tiles.clone();
+        c.game_connection.governor = self.governor.clone();
+        c.snapshots = self.snapshots.clone();
+        c.stop_signal = self.stop_signal.clone();
+        c.mailbox = self.mailbox.clone();
+        c.metrics = self.metrics.clone();
+        c
+    }
+    fn set_state(&mut self, state: BotState) {
+        self.state = state;
+        self.mailbox.set_bot_state(state);
+    }
+}
+
+pub struct BotManager {
+    bots: Vec<ManagedBot>,
+    settings: SupervisorSettings,
+}
+
+impl BotManager {
+    pub fn new(settings: SupervisorSettings) -> BotManager {
+        BotManager {
+            bots: Vec::new(),
+            settings,
+        }
+    }
+
+    /// Takes ownership of the client without launching it. Returns the bot's id
+    pub fn add(&mut self, client: client::Client) -> usize {
+        self.bots.push(ManagedBot {
+            account: client.base.clone(),
+            config: client.config.clone(),
+            proxy_pool: client.game_connection.proxy_pool.clone(),
+            servers: client.servers.clone(),
+            tiles: client.tiles.clone(),
+            governor: client.game_connection.governor.clone(),
+            snapshots: client.snapshots.clone(),
+            stop_signal: client.stop_signal.clone(),
+            mailbox: client.mailbox.clone(),
+            metrics: client.metrics.clone(),
+            handle: None,
+            client: Some(client),
+            state: BotState::Idle,
+            restarts: 0,
+            restart_times: VecDeque::new(),
+            restart_at: None,
+        });
+        self.bots.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.bots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bots.is_empty()
+    }
+
+    /// Finds a bot by its account email
+    pub fn find(&self, email: &str) -> Option<usize> {
+        self.bots
+            .iter()
+            .position(|b| b.account.email.eq_ignore_ascii_case(email))
+    }
+
+    pub fn state(&self, id: usize) -> Option<BotState> {
+        self.bots.get(id).map(|b| b.state)
+    }
+
+    pub fn status(&self) -> Vec<BotStatus> {
+        self.bots
+            .iter()
+            .enumerate()
+            .map(|(id, b)| BotStatus {
+                id,
+                email: b.account.email.clone(),
+                state: b.state,
+                restarts: b.restarts,
+            })
+            .collect()
+    }
+
+    /// Runs the client on a new thread
+    fn launch(&mut self, id: usize, mut c: client::Client) -> io::Result<()> {
+        let bot = &mut self.bots[id];
+        bot.stop_signal.store(false, Ordering::SeqCst);
+        c.recon.reset();
+        bot.handle = Some(super::spawn_client(c)?);
+        bot.set_state(BotState::Running);
+        bot.restart_at = None;
+        Ok(())
+    }
+
+    /// Launches a bot that isn't running, reusing the Client it returned when it stopped
+    pub fn relaunch(&mut self, id: usize) -> io::Result<()> {
+        let bot = match self.bots.get_mut(id) {
+            Some(b) => b,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "no such bot")),
+        };
+        if bot.state == BotState::Running {
+            return Ok(());
+        }
+        let c = match bot.client.take() {
+            Some(c) => c,
+            None => bot.fresh_client(),
+        };
+        self.launch(id, c)
+    }
+
+    /// Launches every bot that isn't running yet
+    pub fn start_all(&mut self) -> io::Result<()> {
+        for id in 0..self.bots.len() {
+            self.relaunch(id)?;
+        }
+        Ok(())
+    }
+
+    /// Asks the bot to disconnect and waits for its thread. Returns false if it wasn't running
+    pub fn stop(&mut self, id: usize) -> bool {
+        let bot = match self.bots.get_mut(id) {
+            Some(b) if b.state == BotState::Running => b,
+            Some(b) => {
+                //don't let a pending restart bring it back
+                if b.restart_at.take().is_some() {
+                    b.set_state(BotState::Stopped);
+                }
+                return false;
+            }
+            None => return false,
+        };
+        bot.stop_signal.store(true, Ordering::SeqCst);
+        BotManager::join(bot);
+        bot.set_state(BotState::Stopped);
+        true
+    }
+
+    /// Stops the bot and launches it again
+    pub fn restart(&mut self, id: usize) -> io::Result<()> {
+        self.stop(id);
+        self.relaunch(id)
+    }
+
+    /// Waits for the bot's thread, keeping the returned Client. Returns false if the thread panicked
+    fn join(bot: &mut ManagedBot) -> bool {
+        match bot.handle.take() {
+            Some(h) => match h.join() {
+                Ok(c) => {
+                    bot.client = Some(c);
+                    true
+                }
+                Err(_) => {
+                    bot.client = None;
+                    false
+                }
+            },
+            None => true,
+        }
+    }
+
+    /// The bots' mailboxes, for the control server
+    pub fn control_targets(&self) -> Vec<control::Target> {
+        self.bots
+            .iter()
+            .enumerate()
+            .map(|(id, b)| control::Target {
+                id,
+                email: b.account.email.clone(),
+                mailbox: b.mailbox.clone(),
+            })
+            .collect()
+    }
+
+    /// Reaps bots whose threads ended and restarts them according to the policy. Call this regularly
+    pub fn supervise(&mut self) {
+        let now = time::Instant::now();
+        for id in 0..self.bots.len() {
+            let settings = self.settings.clone();
+            let bot = &mut self.bots[id];
+            let finished = match &bot.handle {
+                Some(h) => bot.state == BotState::Running && h.is_finished(),
+                None => false,
+            };
+            if finished {
+                let crashed = !BotManager::join(bot);
+                bot.set_state(if crashed {
+                    BotState::Crashed
+                } else {
+                    BotState::Finished
+                });
+                if crashed {
+                    warn!(email = %bot.account.email, "bot crashed");
+                } else {
+                    info!(email = %bot.account.email, "bot stopped");
+                }
+                let wanted = match settings.restart {
+                    RestartPolicy::Never => false,
+                    RestartPolicy::OnCrash => crashed,
+                    RestartPolicy::Always => true,
+                };
+                if !wanted {
+                    bot.set_state(BotState::Failed);
+                    continue;
+                }
+                let window = time::Duration::from_secs(settings.restart_window_secs);
+                while let Some(t) = bot.restart_times.front() {
+                    if now.duration_since(*t) > window {
+                        bot.restart_times.pop_front();
+                    } else {
+                        break;
+                    }
+                }
+                if settings.max_restarts > 0
+                    && bot.restart_times.len() >= settings.max_restarts as usize
+                {
+                    warn!(email = %bot.account.email, "bot is out of restarts");
+                    bot.set_state(BotState::Failed);
+                    continue;
+                }
+                bot.restart_at = Some(now + time::Duration::from_millis(settings.restart_delay_ms));
+            }
+            if bot.restart_at.is_some_and(|t| now >= t) {
+                bot.restarts += 1;
+                bot.restart_times.push_back(now);
+                let c = match bot.client.take() {
+                    Some(c) => c,
+                    None => bot.fresh_client(),
+                };
+                if let Err(e) = self.launch(id, c) {
+                    error!(bot = id, "couldn't restart bot: {}", e);
+                    self.bots[id].set_state(BotState::Failed);
+                }
+            }
+        }
+    }
+
+    /// Stops every bot. The stop signals all go out first so the bots disconnect in parallel
+    pub fn shutdown(&mut self) {
+        for bot in self.bots.iter_mut() {
+            bot.restart_at = None;
+            if bot.state == BotState::Running {
+                bot.stop_signal.store(true, Ordering::SeqCst);
+            }
+        }
+        for bot in self.bots.iter_mut() {
+            if bot.state == BotState::Running {
+                BotManager::join(bot);
+                bot.set_state(BotState::Stopped);
+            }
+        }
+    }
+
+    /// Supervises the bots until 'stop' is set, then shuts them all down
+    pub fn run_until(&mut self, stop: &AtomicBool, tick: time::Duration) {
+        while !stop.load(Ordering::SeqCst) {
+            self.supervise();
+            thread::sleep(tick);
+        }
+        info!("Shutting down {} bots...", self.bots.len());
+        self.shutdown();
+    }
+}
+
+/// Sets the returned flag on SIGINT/SIGTERM (Ctrl-C on windows). A second signal exits right away
+pub fn install_signal_handler() -> Result<Arc<AtomicBool>, ctrlc::Error> {
+    let flag = Arc::new(AtomicBool::new(false));
+    let handler_flag = flag.clone();
+    ctrlc::set_handler(move || {
+        if handler_flag.swap(true, Ordering::SeqCst) {
+            process::exit(130);
+        }
+    })?;
+    Ok(flag)
+}
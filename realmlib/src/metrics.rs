@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time;
+
+use super::client::ReconnectReason;
+use super::network::packets;
+
+/* metrics.rs - Counters for the whole fleet of bots, rendered in the prometheus text format */
+
+/// Upper bounds of the tick latency histogram buckets, in seconds
+pub const TICK_LATENCY_BUCKETS: [f64; 9] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+/// Where a bot is in its connection lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Connection {
+    Connecting,
+    Connected,
+    Reconnecting,
+    /// Asked to stop
+    Stopped,
+    /// Gave up reconnecting
+    Dead,
+}
+
+impl Connection {
+    pub const ALL: [Connection; 5] = [
+        Connection::Connecting,
+        Connection::Connected,
+        Connection::Reconnecting,
+        Connection::Stopped,
+        Connection::Dead,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Connection::Connecting => "connecting",
+            Connection::Connected => "connected",
+            Connection::Reconnecting => "reconnecting",
+            Connection::Stopped => "stopped",
+            Connection::Dead => "dead",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 2] = [Direction::In, Direction::Out];
+    fn index(self) -> usize {
+        match self {
+            Direction::In => 0,
+            Direction::Out => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketCount {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct PacketCounter {
+    packets: AtomicU64,
+    bytes: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [AtomicU64; TICK_LATENCY_BUCKETS.len()],
+    sum_nanos: AtomicU64,
+    count: AtomicU64,
+}
+
+/// The registry. Clients share ``global()`` unless they're handed their own.
+/// The counters bumped on every packet or tick are atomics, so the bots don't wait on each other to count
+#[derive(Debug)]
+pub struct Metrics {
+    bots: Mutex<BTreeMap<String, Connection>>,
+    packets: [Vec<PacketCounter>; 2], //by direction, then one per packet id
+    reconnects: Mutex<BTreeMap<ReconnectReason, u64>>,
+    deaths: AtomicU64,
+    aoe_hits: AtomicU64,
+    damage_taken: AtomicU64,
+    auto_nexus: AtomicU64,
+    daily_quests: AtomicU64,
+    failures: Mutex<BTreeMap<i32, u64>>,
+    tick_latency: Histogram,
+}
+
+static GLOBAL: OnceLock<Arc<Metrics>> = OnceLock::new();
+
+/// The registry every client reports to by default
+pub fn global() -> Arc<Metrics> {
+    GLOBAL.get_or_init(|| Arc::new(Metrics::new())).clone()
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let per_id = || (0..=u8::MAX).map(|_| PacketCounter::default()).collect();
+        Metrics {
+            bots: Mutex::new(BTreeMap::new()),
+            packets: [per_id(), per_id()],
+            reconnects: Mutex::new(BTreeMap::new()),
+            deaths: AtomicU64::new(0),
+            aoe_hits: AtomicU64::new(0),
+            damage_taken: AtomicU64::new(0),
+            auto_nexus: AtomicU64::new(0),
+            daily_quests: AtomicU64::new(0),
+            failures: Mutex::new(BTreeMap::new()),
+            tick_latency: Histogram::default(),
+        }
+    }
+    pub fn set_connection(&self, bot: &str, c: Connection) {
+        self.bots.lock().unwrap().insert(bot.to_owned(), c);
+    }
+    pub fn connection(&self, bot: &str) -> Option<Connection> {
+        self.bots.lock().unwrap().get(bot).copied()
+    }
+    /// How many bots are in that state
+    pub fn bots(&self, c: Connection) -> usize {
+        self.bots
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|v| **v == c)
+            .count()
+    }
+    pub fn packet(&self, dir: Direction, id: u8, bytes: usize) {
+        let count = &self.packets[dir.index()][id as usize];
+        count.packets.fetch_add(1, Ordering::Relaxed);
+        count.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+    pub fn packets(&self, dir: Direction, id: u8) -> PacketCount {
+        let count = &self.packets[dir.index()][id as usize];
+        PacketCount {
+            packets: count.packets.load(Ordering::Relaxed),
+            bytes: count.bytes.load(Ordering::Relaxed),
+        }
+    }
+    pub fn reconnect(&self, reason: ReconnectReason) {
+        *self.reconnects.lock().unwrap().entry(reason).or_insert(0) += 1;
+    }
+    pub fn reconnects(&self, reason: ReconnectReason) -> u64 {
+        self.reconnects
+            .lock()
+            .unwrap()
+            .get(&reason)
+            .copied()
+            .unwrap_or(0)
+    }
+    pub fn death(&self) {
+        self.deaths.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn deaths(&self) -> u64 {
+        self.deaths.load(Ordering::Relaxed)
+    }
+    pub fn aoe_hit(&self) {
+        self.aoe_hits.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn aoe_hits(&self) -> u64 {
+        self.aoe_hits.load(Ordering::Relaxed)
+    }
+    /// Damage after defense, as predicted by the clients
+    pub fn damage_taken(&self, damage: u64) {
+        self.damage_taken.fetch_add(damage, Ordering::Relaxed);
+    }
+    pub fn total_damage_taken(&self) -> u64 {
+        self.damage_taken.load(Ordering::Relaxed)
+    }
+    /// Escapes sent because hp ran low
+    pub fn auto_nexus(&self) {
+        self.auto_nexus.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn auto_nexuses(&self) -> u64 {
+        self.auto_nexus.load(Ordering::Relaxed)
+    }
+    /// Daily quests redeemed
+    pub fn daily_quest(&self) {
+        self.daily_quests.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn daily_quests(&self) -> u64 {
+        self.daily_quests.load(Ordering::Relaxed)
+    }
+    pub fn failure(&self, code: i32) {
+        *self.failures.lock().unwrap().entry(code).or_insert(0) += 1;
+    }
+    pub fn failures(&self, code: i32) -> u64 {
+        self.failures
+            .lock()
+            .unwrap()
+            .get(&code)
+            .copied()
+            .unwrap_or(0)
+    }
+    /// Time between a NewTick coming off the factory channel and our Move going out
+    pub fn tick_latency(&self, latency: time::Duration) {
+        let secs = latency.as_secs_f64();
+        let h = &self.tick_latency;
+        for (i, bound) in TICK_LATENCY_BUCKETS.iter().enumerate() {
+            if secs <= *bound {
+                h.buckets[i].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        h.sum_nanos
+            .fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+        h.count.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn tick_latency_count(&self) -> u64 {
+        self.tick_latency.count.load(Ordering::Relaxed)
+    }
+    /// Every packet type seen so far, in direction then id order
+    fn packet_counts(&self) -> Vec<(Direction, u8, PacketCount)> {
+        Direction::ALL
+            .iter()
+            .flat_map(|dir| (0..=u8::MAX).map(move |id| (*dir, id)))
+            .map(|(dir, id)| (dir, id, self.packets(dir, id)))
+            .filter(|(_, _, count)| count.packets > 0)
+            .collect()
+    }
+
+    /// Renders everything in the prometheus text exposition format
+    pub fn render(&self) -> String {
+        let bots = self.bots.lock().unwrap().clone();
+        let packets = self.packet_counts();
+        let mut out = String::new();
+        //writing to a String can't fail
+        let _ = writeln!(out, "# HELP realmlib_bots Bots by connection state");
+        let _ = writeln!(out, "# TYPE realmlib_bots gauge");
+        for c in Connection::ALL.iter() {
+            let n = bots.values().filter(|v| *v == c).count();
+            let _ = writeln!(out, "realmlib_bots{{state=\"{}\"}} {}", c.name(), n);
+        }
+        let _ = writeln!(
+            out,
+            "# HELP realmlib_packets_total Packets by direction and type"
+        );
+        let _ = writeln!(out, "# TYPE realmlib_packets_total counter");
+        for (dir, id, count) in packets.iter() {
+            let _ = writeln!(
+                out,
+                "realmlib_packets_total{{{}}} {}",
+                packet_labels(*dir, *id),
+                count.packets
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP realmlib_packet_bytes_total Packet bytes by direction and type"
+        );
+        let _ = writeln!(out, "# TYPE realmlib_packet_bytes_total counter");
+        for (dir, id, count) in packets.iter() {
+            let _ = writeln!(
+                out,
+                "realmlib_packet_bytes_total{{{}}} {}",
+                packet_labels(*dir, *id),
+                count.bytes
+            );
+        }
+        let _ = writeln!(out, "# HELP realmlib_reconnects_total Reconnects by reason");
+        let _ = writeln!(out, "# TYPE realmlib_reconnects_total counter");
+        for (reason, n) in self.reconnects.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "realmlib_reconnects_total{{reason=\"{:?}\"}} {}",
+                reason, n
+            );
+        }
+        let _ = writeln!(out, "# HELP realmlib_deaths_total Character deaths");
+        let _ = writeln!(out, "# TYPE realmlib_deaths_total counter");
+        let _ = writeln!(out, "realmlib_deaths_total {}", self.deaths());
+        let _ = writeln!(out, "# HELP realmlib_aoe_hits_total AoEs the bots stood in");
+        let _ = writeln!(out, "# TYPE realmlib_aoe_hits_total counter");
+        let _ = writeln!(out, "realmlib_aoe_hits_total {}", self.aoe_hits());
+        let _ = writeln!(
+            out,
+            "# HELP realmlib_damage_taken_total Damage taken after defense"
+        );
+        let _ = writeln!(out, "# TYPE realmlib_damage_taken_total counter");
+        let _ = writeln!(
+            out,
+            "realmlib_damage_taken_total {}",
+            self.total_damage_taken()
+        );
+        let _ = writeln!(
+            out,
+            "# HELP realmlib_auto_nexus_total Escapes to the nexus on low hp"
+        );
+        let _ = writeln!(out, "# TYPE realmlib_auto_nexus_total counter");
+        let _ = writeln!(out, "realmlib_auto_nexus_total {}", self.auto_nexuses());
+        let _ = writeln!(
+            out,
+            "# HELP realmlib_daily_quests_total Daily quests redeemed"
+        );
+        let _ = writeln!(out, "# TYPE realmlib_daily_quests_total counter");
+        let _ = writeln!(out, "realmlib_daily_quests_total {}", self.daily_quests());
+        let _ = writeln!(
+            out,
+            "# HELP realmlib_failures_total Failure packets by code"
+        );
+        let _ = writeln!(out, "# TYPE realmlib_failures_total counter");
+        for (code, n) in self.failures.lock().unwrap().iter() {
+            let _ = writeln!(out, "realmlib_failures_total{{code=\"{}\"}} {}", code, n);
+        }
+        let h = &self.tick_latency;
+        let count = h.count.load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "# HELP realmlib_tick_latency_seconds NewTick received to Move sent"
+        );
+        let _ = writeln!(out, "# TYPE realmlib_tick_latency_seconds histogram");
+        for (bound, n) in TICK_LATENCY_BUCKETS.iter().zip(h.buckets.iter()) {
+            let _ = writeln!(
+                out,
+                "realmlib_tick_latency_seconds_bucket{{le=\"{}\"}} {}",
+                bound,
+                n.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "realmlib_tick_latency_seconds_bucket{{le=\"+Inf\"}} {}",
+            count
+        );
+        let sum = h.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "realmlib_tick_latency_seconds_sum {}", sum);
+        let _ = writeln!(out, "realmlib_tick_latency_seconds_count {}", count);
+        out
+    }
+}
+
+fn packet_labels(dir: Direction, id: u8) -> String {
+    let dir = match dir {
+        Direction::In => "in",
+        Direction::Out => "out",
+    };
+    match packets::packet_name(id) {
+        Some(name) => format!("direction=\"{}\",type=\"{}\"", dir, name),
+        None => format!("direction=\"{}\",type=\"{}\"", dir, id),
+    }
+}
@@ -246,8 +246,10 @@ impl Buffer {
         self.write_f32(loc.x);
         self.write_f32(loc.y);
     }
-    pub fn write_position_record(&mut self, record: &types::PositionRecords) {
+    pub fn write_move_record(&mut self, record: &types::MoveRecord) {
         self.write_i32(record.time);
+        self.write_f32(record.x);
+        self.write_f32(record.y);
     }
     pub fn write_slot_object(&mut self, slot: &types::SlotObjectData) {
         self.write_i32(slot.object_id);
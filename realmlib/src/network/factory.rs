@@ -1,20 +1,97 @@
+use std::error;
+use std::fmt;
+use std::io;
 use std::io::ErrorKind;
 use std::io::{Read, Write};
 use std::net;
+use std::net::ToSocketAddrs;
 use std::sync::mpsc;
 use std::thread;
 use std::time;
 
-use crate::network::buffer;
+use tracing::error;
 
-extern crate socks;
+use crate::network::buffer;
+use crate::network::proxy;
+use crate::utils;
 
 /* factory.rs - Networking factory for handling all low-level socket related stuff */
 
+/// Everything that can go wrong while opening the game connection
+#[derive(Debug)]
+pub enum ConnectError {
+    /// The server address couldn't be parsed or resolved
+    BadAddress(String),
+    /// The account's proxy url couldn't be parsed
+    BadProxy(String),
+    /// The tcp connection to the server couldn't be opened
+    Connect(io::Error),
+    /// The proxy refused or failed the handshake
+    Proxy(io::Error),
+    /// The socket opened, but setting its options failed
+    Socket(io::Error),
+    /// The factory thread couldn't be spawned
+    Spawn(io::Error),
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectError::BadAddress(a) => write!(f, "bad address: {}", a),
+            ConnectError::BadProxy(e) => write!(f, "bad proxy: {}", e),
+            ConnectError::Connect(e) => write!(f, "couldn't connect to server: {}", e),
+            ConnectError::Proxy(e) => write!(f, "proxy error: {}", e),
+            ConnectError::Socket(e) => write!(f, "couldn't configure socket: {}", e),
+            ConnectError::Spawn(e) => write!(f, "couldn't spawn network factory: {}", e),
+        }
+    }
+}
+
+impl error::Error for ConnectError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConnectError::BadAddress(_) | ConnectError::BadProxy(_) => None,
+            ConnectError::Connect(e)
+            | ConnectError::Proxy(e)
+            | ConnectError::Socket(e)
+            | ConnectError::Spawn(e) => Some(e),
+        }
+    }
+}
+
+/// Socket timeouts for the game connection. A value of 0 disables that timeout.
+/// The read timeout doubles as a dead connection check, as the server sends a NewTick every 200ms.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    pub connect_ms: u64,
+    pub read_ms: u64,
+    pub write_ms: u64,
+}
+
+impl Timeouts {
+    fn duration(ms: u64) -> Option<time::Duration> {
+        match ms {
+            0 => None,
+            v => Some(time::Duration::from_millis(v)),
+        }
+    }
+    /// Applies the read/write timeouts and nodelay to an open socket
+    fn apply(&self, stream: &net::TcpStream) -> Result<(), ConnectError> {
+        stream.set_nodelay(true).map_err(ConnectError::Socket)?;
+        stream
+            .set_read_timeout(Timeouts::duration(self.read_ms))
+            .map_err(ConnectError::Socket)?;
+        stream
+            .set_write_timeout(Timeouts::duration(self.write_ms))
+            .map_err(ConnectError::Socket)
+    }
+}
+
 /// Holds the network factory's connection
 enum Connection {
     Normal(net::TcpStream),
-    Socks(socks::Socks5Stream),
+    Socks(net::TcpStream), //plain socket once the handshake is done
+    Http(net::TcpStream),  //the tunnel is a plain socket once CONNECT succeeds
 }
 
 impl Connection {
@@ -22,7 +99,8 @@ impl Connection {
     fn pull_stream(&self) -> &net::TcpStream {
         match self {
             Self::Normal(v) => v,
-            Self::Socks(v) => v.get_ref(),
+            Self::Socks(v) => v,
+            Self::Http(v) => v,
         }
     }
 }
@@ -41,9 +119,16 @@ impl NetworkFactory {
     /// The "main()" for NetworkFactory
     pub fn start(mut self) {
         //block for the hello packet
-        self.send_packet(&self.rx.recv().expect("Didn't get hello packet??"));
+        match self.rx.recv() {
+            Ok(hello) => self.send_packet(&hello),
+            Err(_) => {
+                //client hung up before saying hello
+                self.kill_stream();
+                return;
+            }
+        }
         let mut packets: Vec<buffer::Buffer> = Vec::with_capacity(5);
-        'z: loop {
+        loop {
             if self.stream_killed == true {
                 return;
             }
@@ -87,7 +172,7 @@ impl NetworkFactory {
                 ErrorKind::ConnectionReset => (),
                 ErrorKind::BrokenPipe => (),
                 _ => {
-                    eprintln!("Got unknown error writing packet: {}", e);
+                    error!("Got unknown error writing packet: {}", e);
                 }
             },
         }
@@ -107,6 +192,7 @@ impl NetworkFactory {
                         return None;
                     }
                     match self.stream().read(&mut p.data[bytes_read..goal_bytes]) {
+                        Ok(0) => return None, //eof, the server or proxy closed the socket
                         Ok(read) => {
                             bytes_read += read;
                             if bytes_read == goal_bytes {
@@ -117,13 +203,10 @@ impl NetworkFactory {
                             match e.kind() {
                                 ErrorKind::ConnectionReset => (),
                                 _ => {
-                                    eprintln!(
-                                        "NetFactory::recv_packet: Error reading packet: {}",
-                                        e
-                                    );
+                                    error!("NetFactory::recv_packet: Error reading packet: {}", e);
                                 }
                             };
-                            break 'z;
+                            return None; //half a packet is no use, the stream is out of step now
                         }
                     };
                 }
@@ -141,6 +224,7 @@ impl NetworkFactory {
                 return None;
             }
             match self.stream().read(&mut p.data[bytes_read..]) {
+                Ok(0) => return None, //eof, the server or proxy closed the socket
                 Ok(read) => {
                     bytes_read += read;
                     if p.data[0] == 255 {
@@ -155,7 +239,7 @@ impl NetworkFactory {
                     match e.kind() {
                         ErrorKind::ConnectionReset => (),
                         _ => {
-                            eprintln!("NetFactory::recv_packet: Error reading packet: {}", e);
+                            error!("NetFactory::recv_packet: Error reading packet: {}", e);
                         }
                     };
                     return None; //break 'z
@@ -216,64 +300,304 @@ impl NetworkFactory {
     }
 }
 
+/// The client's ends of the factory channels, plus the factory thread handle
+pub type FactoryHandles = (
+    mpsc::Sender<buffer::Buffer>,
+    mpsc::Receiver<buffer::Buffer>,
+    thread::JoinHandle<()>,
+);
+
+/// Opens the connection and spins up the factory thread. The connection is made on the calling thread,
+/// so any failure is returned here instead of taking down the factory.
 pub fn begin_networking(
     server: String,
-    proxy: String,
+    proxy: Option<proxy::Proxy>,
     timeout: u64,
+    timeouts: Timeouts,
     stack: usize,
-) -> (
-    mpsc::Sender<buffer::Buffer>,
-    mpsc::Receiver<buffer::Buffer>,
-    thread::JoinHandle<()>,
-) {
+) -> Result<FactoryHandles, ConnectError> {
     let (c2s_tx, c2s_rx) = mpsc::channel::<buffer::Buffer>(); //client to server transceivers
     let (s2c_tx, s2c_rx) = mpsc::channel::<buffer::Buffer>(); //server to client transceivers
-    let net_factory = NetworkFactory::new(server, proxy, timeout, s2c_tx, c2s_rx);
+    let net_factory = NetworkFactory::new(server, proxy, timeout, timeouts, s2c_tx, c2s_rx)?;
     let built_thread = thread::Builder::new()
         .name(String::from("NetFactory"))
         .stack_size(stack*1024);
-    let handle = built_thread.spawn(move || net_factory.start()).unwrap();
-    (c2s_tx, s2c_rx, handle)
+    //the factory logs inside the client's span
+    let span = tracing::Span::current();
+    let handle = built_thread
+        .spawn(move || span.in_scope(|| net_factory.start()))
+        .map_err(ConnectError::Spawn)?;
+    Ok((c2s_tx, s2c_rx, handle))
+}
+
+/// Resolves the address and tries each result in turn, honoring the connect timeout
+fn connect_direct(
+    server: &str,
+    timeout: Option<time::Duration>,
+) -> Result<net::TcpStream, ConnectError> {
+    let addrs = server
+        .to_socket_addrs()
+        .map_err(|_| ConnectError::BadAddress(server.to_owned()))?;
+    let mut last_err = None;
+    for addr in addrs {
+        let attempt = match timeout {
+            Some(t) => net::TcpStream::connect_timeout(&addr, t),
+            None => net::TcpStream::connect(addr),
+        };
+        match attempt {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    match last_err {
+        Some(e) => Err(ConnectError::Connect(e)),
+        None => Err(ConnectError::BadAddress(server.to_owned())),
+    }
+}
+
+/// Where the socks proxy should connect us to
+enum SocksTarget {
+    Ip(net::SocketAddr),
+    Domain(String, u16), //resolved by the proxy
+}
+
+/// Builds the socks target for the server. Anything that isn't an ip is resolved by the proxy
+fn socks_target(server: &str) -> Result<SocksTarget, ConnectError> {
+    if let Ok(v) = server.parse::<net::SocketAddr>() {
+        return Ok(SocksTarget::Ip(v));
+    }
+    match proxy::split_host_port(server) {
+        Some((host, port)) => Ok(SocksTarget::Domain(host.to_owned(), port)),
+        None => Err(ConnectError::BadAddress(server.to_owned())),
+    }
+}
+
+fn proxy_error(message: String) -> io::Error {
+    io::Error::new(ErrorKind::ConnectionRefused, message)
+}
+
+/// Opens the socket to the proxy like any other connection, so the timeouts cover the handshake,
+/// then performs it, authenticating if the proxy url had credentials
+fn connect_socks(
+    p: &proxy::Proxy,
+    target: SocksTarget,
+    timeouts: Timeouts,
+) -> Result<Connection, ConnectError> {
+    if p.kind == proxy::ProxyKind::Http {
+        return Err(ConnectError::BadProxy(String::from("not a socks proxy")));
+    }
+    let mut stream = connect_direct(&p.address, Timeouts::duration(timeouts.connect_ms))?;
+    timeouts.apply(&stream)?;
+    let handshake = match p.kind {
+        proxy::ProxyKind::Socks4 => socks4_handshake(&mut stream, p, &target),
+        _ => socks5_handshake(&mut stream, p, &target),
+    };
+    handshake.map_err(ConnectError::Proxy)?;
+    Ok(Connection::Socks(stream))
+}
+
+/// RFC 1928, with RFC 1929 username/password auth
+fn socks5_handshake(
+    s: &mut net::TcpStream,
+    p: &proxy::Proxy,
+    target: &SocksTarget,
+) -> io::Result<()> {
+    match &p.username {
+        Some(_) => s.write_all(&[5, 2, 0, 2])?, //no auth or username/password
+        None => s.write_all(&[5, 1, 0])?,
+    }
+    let mut choice = [0u8; 2];
+    s.read_exact(&mut choice)?;
+    match (choice, &p.username) {
+        ([5, 0], _) => (),
+        ([5, 2], Some(user)) => {
+            let pass = p.password.as_deref().unwrap_or("");
+            if user.len() > 255 || pass.len() > 255 {
+                return Err(proxy_error(String::from("socks5 credentials are too long")));
+            }
+            let mut auth = vec![1, user.len() as u8];
+            auth.extend_from_slice(user.as_bytes());
+            auth.push(pass.len() as u8);
+            auth.extend_from_slice(pass.as_bytes());
+            s.write_all(&auth)?;
+            let mut status = [0u8; 2];
+            s.read_exact(&mut status)?;
+            if status[1] != 0 {
+                return Err(proxy_error(String::from(
+                    "socks5 proxy rejected our credentials",
+                )));
+            }
+        }
+        _ => {
+            return Err(proxy_error(String::from(
+                "no socks5 auth method we can use",
+            )))
+        }
+    }
+    let mut request = vec![5, 1, 0]; //connect
+    let port = match target {
+        SocksTarget::Ip(net::SocketAddr::V4(a)) => {
+            request.push(1);
+            request.extend_from_slice(&a.ip().octets());
+            a.port()
+        }
+        SocksTarget::Ip(net::SocketAddr::V6(a)) => {
+            request.push(4);
+            request.extend_from_slice(&a.ip().octets());
+            a.port()
+        }
+        SocksTarget::Domain(host, port) => {
+            if host.len() > 255 {
+                return Err(proxy_error(format!("{} is too long for socks5", host)));
+            }
+            request.push(3);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+            *port
+        }
+    };
+    request.extend_from_slice(&port.to_be_bytes());
+    s.write_all(&request)?;
+    let mut reply = [0u8; 4];
+    s.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(proxy_error(format!(
+            "socks5 proxy refused the connect: {}",
+            reply[1]
+        )));
+    }
+    //skip the bound address, nothing of the game data comes before it
+    let bound = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0u8; 1];
+            s.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        t => return Err(proxy_error(format!("bad socks5 address type {}", t))),
+    };
+    let mut rest = vec![0u8; bound + 2];
+    s.read_exact(&mut rest)
+}
+
+/// SOCKS4, or 4a when the proxy has to resolve the server's name
+fn socks4_handshake(
+    s: &mut net::TcpStream,
+    p: &proxy::Proxy,
+    target: &SocksTarget,
+) -> io::Result<()> {
+    let (ip, port, domain) = match target {
+        SocksTarget::Ip(net::SocketAddr::V4(a)) => (a.ip().octets(), a.port(), None),
+        SocksTarget::Ip(net::SocketAddr::V6(_)) => {
+            return Err(proxy_error(String::from("socks4 can't connect to ipv6")))
+        }
+        SocksTarget::Domain(host, port) => ([0, 0, 0, 1], *port, Some(host)),
+    };
+    let mut request = vec![4, 1]; //connect
+    request.extend_from_slice(&port.to_be_bytes());
+    request.extend_from_slice(&ip);
+    request.extend_from_slice(p.username.as_deref().unwrap_or("").as_bytes());
+    request.push(0);
+    if let Some(host) = domain {
+        request.extend_from_slice(host.as_bytes());
+        request.push(0);
+    }
+    s.write_all(&request)?;
+    let mut reply = [0u8; 8];
+    s.read_exact(&mut reply)?;
+    if reply[1] != 90 {
+        return Err(proxy_error(format!(
+            "socks4 proxy refused the connect: {}",
+            reply[1]
+        )));
+    }
+    Ok(())
+}
+
+/// Opens a tunnel to the server through an HTTP proxy with CONNECT, using basic auth if the url had credentials
+fn connect_http(
+    p: &proxy::Proxy,
+    server: &str,
+    timeouts: Timeouts,
+) -> Result<Connection, ConnectError> {
+    if proxy::split_host_port(server).is_none() {
+        return Err(ConnectError::BadAddress(server.to_owned()));
+    }
+    let mut stream = connect_direct(&p.address, Timeouts::duration(timeouts.connect_ms))?;
+    timeouts.apply(&stream)?;
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", server);
+    if let Some(user) = &p.username {
+        let credentials = format!("{}:{}", user, p.password.as_deref().unwrap_or(""));
+        request += &format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            utils::encode_base64(credentials)
+        );
+    }
+    request += "\r\n";
+    stream
+        .write_all(request.as_bytes())
+        .map_err(ConnectError::Proxy)?;
+    //read the response a byte at a time, so we don't eat into the game data following the headers
+    let mut response = Vec::with_capacity(128);
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(0) => {
+                return Err(ConnectError::Proxy(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "proxy closed the connection during CONNECT",
+                )))
+            }
+            Ok(_) => response.push(byte[0]),
+            Err(e) => return Err(ConnectError::Proxy(e)),
+        }
+        if response.len() > 8192 {
+            return Err(ConnectError::Proxy(io::Error::new(
+                ErrorKind::InvalidData,
+                "proxy response headers too long",
+            )));
+        }
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or("");
+    let status = status_line.split_whitespace().nth(1).unwrap_or("");
+    if !status.starts_with('2') {
+        return Err(ConnectError::Proxy(io::Error::new(
+            ErrorKind::ConnectionRefused,
+            format!("proxy refused CONNECT: {}", status_line),
+        )));
+    }
+    Ok(Connection::Http(stream))
 }
 
 impl NetworkFactory {
+    /// Connects to the server, directly or through the proxy if one is given
     pub fn new(
         server: String,
-        proxy: String,
+        proxy: Option<proxy::Proxy>,
         recv_timeout: u64,
+        timeouts: Timeouts,
         transmitter: mpsc::Sender<buffer::Buffer>,
         receiver: mpsc::Receiver<buffer::Buffer>,
-    ) -> NetworkFactory {
-        if proxy.is_empty() {
-            let stream = net::TcpStream::connect(server).expect("Couldn't connect to server");
-            stream
-                .set_nodelay(true)
-                .expect("Couldn't set nodelay on socket");
-            // println!("Connected to server!");
-            NetworkFactory {
-                rx: receiver,
-                tx: transmitter,
-                estream: Connection::Normal(stream),
-                timeout_ms: recv_timeout,
-                stream_killed: false,
-            }
-        } else {
-            let target = socks::TargetAddr::Ip(server.parse::<net::SocketAddr>().unwrap());
-            let mut stream = socks::Socks5Stream::connect(proxy.clone(), target)
-                .expect("Couldn't connect to server");
-            stream
-                .get_mut()
-                .set_nodelay(true)
-                .expect("Couldn't set nodelay on socks");
-            // println!("Connected to server!");
-            NetworkFactory {
-                rx: receiver,
-                tx: transmitter,
-                estream: Connection::Socks(stream),
-                timeout_ms: recv_timeout,
-                stream_killed: false,
+    ) -> Result<NetworkFactory, ConnectError> {
+        let estream = match proxy {
+            None => {
+                let stream = connect_direct(&server, Timeouts::duration(timeouts.connect_ms))?;
+                timeouts.apply(&stream)?;
+                Connection::Normal(stream)
             }
-        }
+            Some(ref p) if p.kind == proxy::ProxyKind::Http => connect_http(p, &server, timeouts)?,
+            Some(ref p) => connect_socks(p, socks_target(&server)?, timeouts)?,
+        };
+        // println!("Connected to server!");
+        Ok(NetworkFactory {
+            rx: receiver,
+            tx: transmitter,
+            estream,
+            timeout_ms: recv_timeout,
+            stream_killed: false,
+        })
     }
     fn stream(&mut self) -> &net::TcpStream {
         self.estream.pull_stream()
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time;
+
+use serde::{Deserialize, Serialize};
+
+/* governor.rs - Fleet-wide cap on open connections and rate limit on new connects, overall and per server */
+
+/// How long a client waits before asking again when it's over the connection cap
+pub const CAP_RETRY: time::Duration = time::Duration::from_millis(100);
+
+/// Governor settings, under "governor" in the settings file. The connection cap itself is ``conn_limit``
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GovernorSettings {
+    pub connects_per_sec: f64,        //fleet-wide, 0 disables the limit
+    pub burst: u32,                   //connects allowed back to back before the rate kicks in
+    pub server_connects_per_sec: f64, //per server ip, 0 disables the limit
+    pub server_burst: u32,
+    pub max_per_server: usize, //open connections per server ip, 0 means unlimited
+}
+
+impl Default for GovernorSettings {
+    fn default() -> GovernorSettings {
+        GovernorSettings {
+            connects_per_sec: 5.0,
+            burst: 10,
+            server_connects_per_sec: 2.0,
+            server_burst: 5,
+            max_per_server: 0,
+        }
+    }
+}
+
+/// Token bucket, refilled at 'rate' tokens per second up to 'burst'
+#[derive(Debug, Clone)]
+struct Bucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: time::Instant,
+}
+
+impl Bucket {
+    fn new(rate: f64, burst: u32, now: time::Instant) -> Bucket {
+        let burst = f64::from(burst.max(1));
+        Bucket {
+            rate,
+            burst,
+            tokens: burst,
+            last: now,
+        }
+    }
+    fn refill(&mut self, now: time::Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+    }
+    /// How long until a token is available, zero if one is there now
+    fn wait(&self) -> time::Duration {
+        if self.rate <= 0.0 || self.tokens >= 1.0 {
+            return time::Duration::from_secs(0);
+        }
+        time::Duration::from_secs_f64((1.0 - self.tokens) / self.rate)
+    }
+    fn take(&mut self) {
+        if self.rate > 0.0 {
+            self.tokens -= 1.0;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct GovernorState {
+    active: usize,
+    per_server: HashMap<String, usize>,
+    fleet: Bucket,
+    servers: HashMap<String, Bucket>,
+}
+
+/// Shared between every client. Hand it out in an ``Arc``
+#[derive(Debug)]
+pub struct Governor {
+    max_connections: usize, //0 means unlimited
+    settings: GovernorSettings,
+    state: Mutex<GovernorState>,
+}
+
+/// An open connection slot. Dropping it gives the slot back
+#[derive(Debug)]
+pub struct Permit {
+    governor: Arc<Governor>,
+    server: String,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.governor.release(&self.server);
+    }
+}
+
+impl Governor {
+    pub fn new(max_connections: usize, settings: GovernorSettings) -> Governor {
+        let fleet = Bucket::new(
+            settings.connects_per_sec,
+            settings.burst,
+            time::Instant::now(),
+        );
+        Governor {
+            max_connections,
+            settings,
+            state: Mutex::new(GovernorState {
+                active: 0,
+                per_server: HashMap::new(),
+                fleet,
+                servers: HashMap::new(),
+            }),
+        }
+    }
+
+    /// The governor for ``conn_limit`` and the "governor" settings
+    pub fn from_config(c: &crate::Config) -> Arc<Governor> {
+        Arc::new(Governor::new(
+            c.conn_limit.max(0) as usize,
+            c.governor.clone(),
+        ))
+    }
+
+    /// Takes a connection slot for 'server' if both caps and both rate limits allow it right now.
+    /// Otherwise returns how long to wait before trying again
+    pub fn try_acquire(self: &Arc<Self>, server: &str) -> Result<Permit, time::Duration> {
+        let now = time::Instant::now();
+        let mut state = self.state.lock().unwrap();
+        if self.max_connections > 0 && state.active >= self.max_connections {
+            return Err(CAP_RETRY);
+        }
+        let on_server = state.per_server.get(server).copied().unwrap_or(0);
+        if self.settings.max_per_server > 0 && on_server >= self.settings.max_per_server {
+            return Err(CAP_RETRY);
+        }
+        let settings = &self.settings;
+        let state = &mut *state;
+        let bucket = state.servers.entry(server.to_owned()).or_insert_with(|| {
+            Bucket::new(settings.server_connects_per_sec, settings.server_burst, now)
+        });
+        bucket.refill(now);
+        state.fleet.refill(now);
+        let wait = bucket.wait().max(state.fleet.wait());
+        if wait > time::Duration::from_secs(0) {
+            return Err(wait);
+        }
+        bucket.take();
+        state.fleet.take();
+        state.active += 1;
+        *state.per_server.entry(server.to_owned()).or_insert(0) += 1;
+        Ok(Permit {
+            governor: self.clone(),
+            server: server.to_owned(),
+        })
+    }
+
+    fn release(&self, server: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.active = state.active.saturating_sub(1);
+        if let Some(n) = state.per_server.get_mut(server) {
+            *n = n.saturating_sub(1);
+            if *n == 0 {
+                state.per_server.remove(server);
+            }
+        }
+    }
+
+    /// Open connections across the fleet
+    pub fn active(&self) -> usize {
+        self.state.lock().unwrap().active
+    }
+
+    /// Open connections to one server
+    pub fn active_on(&self, server: &str) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .per_server
+            .get(server)
+            .copied()
+            .unwrap_or(0)
+    }
+}
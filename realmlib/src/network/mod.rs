@@ -1,11 +1,19 @@
 pub mod buffer;
 pub mod factory;
+pub mod governor;
 pub mod packets;
+pub mod pool;
+pub mod proxy;
 pub mod types;
 
 use std::sync::mpsc;
+use std::sync::Arc;
+use std::time;
+
+use tracing::{error, trace, warn};
 
 use crate::client;
+use crate::metrics;
 use crate::network::packets::client_packets;
 use crate::network::packets::client_packets::ClientPacket;
 use crate::utils;
@@ -13,38 +21,108 @@ use crate::utils::rc4;
 
 /* mod.rs (network module) - TCP communications with server and packet controls */
 
+/// How long receive() waits on the factory before giving the game loop a turn
+pub const RECEIVE_POLL: time::Duration = time::Duration::from_millis(250);
+
 pub fn netstat() {}
 
+/// Per-packet trace event, enable it with "realmlib::network=trace"
+fn trace_packet(direction: &str, p: &buffer::Buffer) {
+    trace!(
+        direction,
+        packet = packets::packet_name(p.data[4]).unwrap_or("UNKNOWN"),
+        id = p.data[4],
+        bytes = p.data.len(),
+        "packet"
+    );
+}
+
 impl client::Client {
     /// Connects the client to the server with the provided IP address and sets the socket to nodelay
-    pub fn connect(&mut self, ip: String, port: i32) {
+    pub fn connect(&mut self, ip: String, port: i32) -> Result<(), factory::ConnectError> {
         if self.recon.recon_queued == true {
             //were reconnecting, reset the ciphers
             self.game_connection.key_pair = rc4::CipherPair::new_const();
         }
         let full_ip = ip + ":" + &port.to_string();
-        if self.base.use_socks {
-            let (tx, rx, h) = factory::begin_networking(
-                full_ip.clone(),
-                self.base.socks_proxy.clone(),
-                self.config.factory_delay_ms,
-                self.config.factory_stack_size_kb,
-            );
-            self.game_connection.game_socket.tx = Some(tx);
-            self.game_connection.game_socket.rx = Some(rx);
-            self.factory_handle = Some(h);
+        let parsed = if self.base.use_socks {
+            Some(proxy::Proxy::parse(&self.base.socks_proxy))
+        } else if self.base.use_http {
+            Some(proxy::Proxy::parse_or(
+                &self.base.http_proxy,
+                proxy::ProxyKind::Http,
+            ))
+        } else if self.game_connection.proxy_pool.is_some() {
+            Some(self.pick_pooled_proxy())
         } else {
-            let (tx, rx, h) = factory::begin_networking(
-                full_ip.clone(),
-                String::new(),
-                self.config.factory_delay_ms,
-                self.config.factory_stack_size_kb,
-            );
-            self.game_connection.game_socket.tx = Some(tx);
-            self.game_connection.game_socket.rx = Some(rx);
-            self.factory_handle = Some(h);
-        }
+            None
+        };
+        let proxy = match parsed {
+            Some(Ok(v)) => Some(v),
+            Some(Err(e)) => return Err(factory::ConnectError::BadProxy(e)),
+            None => None,
+        };
+        let timeouts = factory::Timeouts {
+            connect_ms: self.config.connect_timeout_ms,
+            read_ms: self.config.read_timeout_ms,
+            write_ms: self.config.write_timeout_ms,
+        };
+        let result = factory::begin_networking(
+            full_ip,
+            proxy,
+            self.config.factory_delay_ms,
+            timeouts,
+            self.config.factory_stack_size_kb,
+        );
+        self.game_connection.report_pooled_proxy(&result);
+        let (tx, rx, h) = result?;
+        self.game_connection.game_socket.tx = Some(tx);
+        self.game_connection.game_socket.rx = Some(rx);
+        self.factory_handle = Some(h);
         // self.sleep_thread(10); //reproduces the 100% usage bug
+        Ok(())
+    }
+    /// Picks the pooled proxy for this connect. The client keeps its proxy across requested reconnects,
+    /// and moves to a different healthy one after a failure or if its proxy was marked dead.
+    fn pick_pooled_proxy(&mut self) -> Result<proxy::Proxy, String> {
+        let pool = match self.game_connection.proxy_pool.clone() {
+            Some(v) => v,
+            None => return Err(String::from("no proxy pool")),
+        };
+        let current = self.game_connection.pooled_proxy.take();
+        if let Some(p) = current.as_ref() {
+            if self.recon.recon_reason == client::ReconnectReason::Requested && !pool.is_dead(p) {
+                self.game_connection.pooled_proxy = current.clone();
+                return Ok(p.clone());
+            }
+        }
+        match pool.rotate(current.as_ref()) {
+            Some(p) => {
+                self.game_connection.pooled_proxy = Some(p.clone());
+                Ok(p)
+            }
+            None => Err(String::from("no healthy proxy left in the pool")),
+        }
+    }
+    /// Waits until the governor lets this client open a connection to 'server'.
+    /// Returns false if the client was asked to stop while waiting
+    pub fn acquire_connect_slot(&mut self, server: &str) -> bool {
+        let governor = match self.game_connection.governor.clone() {
+            Some(g) => g,
+            None => return true,
+        };
+        loop {
+            if self.stop_requested() {
+                return false;
+            }
+            match governor.try_acquire(server) {
+                Ok(p) => {
+                    self.game_connection.permit = Some(p);
+                    return true;
+                }
+                Err(wait) => self.sleep_unless_stopped(wait),
+            }
+        }
     }
     /// Disconnects the client from its socket
     pub fn disconnect(&mut self) {
@@ -53,19 +131,40 @@ impl client::Client {
     }
     /// Sends the packet to the server
     pub fn send(&mut self, mut packet: buffer::Buffer) {
+        self.metrics
+            .packet(metrics::Direction::Out, packet.data[4], packet.data.len());
+        trace_packet("out", &packet);
         self.game_connection.cipher_data(&mut packet.data, false);
         self.game_connection.game_socket.send_packet_to_factory(packet);
     }
     /// Receives the packet from the server, return a packet enum
     pub fn receive(&mut self) {
-        let packet = self.game_connection.game_socket.receive_packet_from_factory();
+        //wake up now and then so a stop request doesn't wait on the next packet
+        let packet = match self
+            .game_connection
+            .game_socket
+            .poll_packet_from_factory(RECEIVE_POLL)
+        {
+            Ok(p) => Some(p),
+            Err(mpsc::RecvTimeoutError::Timeout) => return,
+            Err(mpsc::RecvTimeoutError::Disconnected) => None,
+        };
         match packet {
             Some(mut pkt) => {
+                self.time_keeper.last_receive = time::Instant::now();
+                self.metrics
+                    .packet(metrics::Direction::In, pkt.data[4], pkt.data.len());
+                trace_packet("in", &pkt);
                 self.game_connection.cipher_data(&mut pkt.data, true);
                 self.evaluate_packet(pkt);
             }
             None => { //todo: clean this spot up a bit???
+                let reason = match self.recon.last_failure_id.take() {
+                    Some(_) => client::ReconnectReason::Failure,
+                    None => client::ReconnectReason::Dropped,
+                };
                 self.queue_recon(
+                    reason,
                     self.recon.game_id,
                     self.recon.game_key.clone(),
                     self.recon.game_key_time,
@@ -109,6 +208,10 @@ pub struct GameConnection {
     pub key_pair: rc4::CipherPair,
     pub debug_socket: bool,
     pub reading_packets: bool,
+    pub proxy_pool: Option<Arc<pool::ProxyPool>>, //only set for accounts without their own proxy
+    pub pooled_proxy: Option<proxy::Proxy>,       //the proxy currently held from the pool
+    pub governor: Option<Arc<governor::Governor>>,
+    pub permit: Option<governor::Permit>, //held while connected, gives the governor slot back when dropped
 }
 
 /// Implements base GameConnection related stuff
@@ -119,12 +222,39 @@ impl GameConnection {
             key_pair: rc4::new_key_pair(rc4::OUTGOING_KEY, rc4::INCOMING_KEY),
             debug_socket: false,
             reading_packets: false,
+            proxy_pool: None,
+            pooled_proxy: None,
+            governor: None,
+            permit: None,
+        }
+    }
+    /// Feeds the connect result back to the pool, so proxies that keep failing get marked dead
+    fn report_pooled_proxy<T>(&self, result: &Result<T, factory::ConnectError>) {
+        let (pool, p) = match (&self.proxy_pool, &self.pooled_proxy) {
+            (Some(pool), Some(p)) => (pool, p),
+            _ => return,
+        };
+        match result {
+            Ok(_) => pool.report_success(p),
+            Err(factory::ConnectError::Proxy(_)) | Err(factory::ConnectError::Connect(_)) => {
+                if pool.report_failure(p) {
+                    warn!("Proxy {} failed too many times, marking it dead", p);
+                }
+            }
+            Err(_) => (),
+        }
+    }
+    /// Gives the pooled proxy back, so another client can use the slot
+    pub fn release_pooled_proxy(&mut self) {
+        if let (Some(pool), Some(p)) = (&self.proxy_pool, self.pooled_proxy.take()) {
+            pool.release(&p);
         }
     }
     pub fn kill_connection(&mut self) {
         //Not sure if the keypairs need to be zeroed out, but heck, why not
         self.reading_packets = false;
         self.key_pair.reset();
+        self.permit = None;
         //drop channels
         match self.game_socket.rx.take() {
             Some(rx) => drop(rx),
@@ -173,13 +303,23 @@ impl GameSocket {
             Some(v) => match v.send(b) {
                 Ok(_) => (),
                 Err(e) => {
-                    eprintln!("Error sending to netfactory: {}", e);
+                    error!("Error sending to netfactory: {}", e);
                 }
             },
             None => (), //channel is closed
         }
     }
-    /// Receives a packet from the ``NetworkFactory``.
+    /// Waits up to 'timeout' for a packet from the ``NetworkFactory``, so the caller can do other work in between
+    pub fn poll_packet_from_factory(
+        &self,
+        timeout: time::Duration,
+    ) -> Result<buffer::Buffer, mpsc::RecvTimeoutError> {
+        match self.rx.as_ref() {
+            Some(v) => v.recv_timeout(timeout),
+            None => Err(mpsc::RecvTimeoutError::Disconnected),
+        }
+    }
+    /// Blocks until the ``NetworkFactory`` has a packet for us. None once the channel is closed
     pub fn receive_packet_from_factory(&self) -> Option<buffer::Buffer> {
         match self.rx.as_ref() {
             Some(v) => {
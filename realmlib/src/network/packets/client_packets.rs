@@ -15,6 +15,7 @@ pub enum ClientPackets {
     PongPacket(Pong),
     UpdateAckPacket(UpdateAck),
     MovePacket(Move),
+    GroundDamagePacket(GroundDamage),
     ShootAckPacket(ShootAck),
     AoEAckPacket(AoEAck),
     GotoAckPacket(GotoAck),
@@ -65,6 +66,7 @@ impl ClientPackets {
             ClientPackets::PlayerShootPacket(v) => v.write().finalize(super::PLAYERSHOOT),
             ClientPackets::PongPacket(v) => v.write().finalize(super::PONG),
             ClientPackets::MovePacket(v) => v.write().finalize(super::MOVE),
+            ClientPackets::GroundDamagePacket(v) => v.write().finalize(super::GROUNDDAMAGE),
             ClientPackets::GotoAckPacket(v) => v.write().finalize(super::GOTOACK),
             ClientPackets::AoEAckPacket(v) => v.write().finalize(super::AOEACK),
             ClientPackets::PlayerTextPacket(v) => v.write().finalize(super::PLAYERTEXT),
@@ -239,7 +241,7 @@ pub struct Move {
     pub tick_id: i32,
     pub time: i32,
     pub new_position: types::WorldPosition,
-    pub records: Vec<types::PositionRecords>,
+    pub records: Vec<types::MoveRecord>,
 }
 
 impl ClientPacket for Move {
@@ -251,7 +253,7 @@ impl ClientPacket for Move {
         p.write_world_position(&self.new_position);
         p.write_u16(self.records.len() as u16);
         for i in self.records.iter() {
-            p.write_position_record(i);
+            p.write_move_record(i);
         }
         p
     }
@@ -1,9 +1,13 @@
 pub mod client_packets;
 pub mod server_packets;
 
+use std::panic;
+
 use crate::client;
 use crate::network::buffer;
+use crate::utils::rc4;
 use server_packets::ServerPacket;
+use tracing::warn;
 
 /* mod.rs (packets module) - Module for packet controls, types, methods */
 
@@ -90,8 +94,8 @@ impl client::Client {
             FILE => self.on_file(server_packets::File::new(p)),
             PIC => self.on_pic(server_packets::Pic::new(p)),
             _ => {
-                println!(
-                    "Got fucked packet id: {}. Maybe packets need to be updated?",
+                warn!(
+                    "Got unknown packet id: {}. Maybe packets need to be updated?",
                     p.data[4]
                 );
             }
@@ -101,6 +105,226 @@ impl client::Client {
     pub fn write_packet_id() {}
 }
 
+/// Returns the name of a packet id, such as "NEWTICK"
+pub fn packet_name(id: u8) -> Option<&'static str> {
+    let name = match id {
+        FAILURE => "FAILURE",
+        CREATESUCCESS => "CREATESUCCESS",
+        CREATE => "CREATE",
+        PLAYERSHOOT => "PLAYERSHOOT",
+        MOVE => "MOVE",
+        PLAYERTEXT => "PLAYERTEXT",
+        TEXT => "TEXT",
+        SERVERPLAYERSHOOT => "SERVERPLAYERSHOOT",
+        DAMAGE => "DAMAGE",
+        UPDATE => "UPDATE",
+        UPDATEACK => "UPDATEACK",
+        NOTIFICATION => "NOTIFICATION",
+        NEWTICK => "NEWTICK",
+        INVSWAP => "INVSWAP",
+        USEITEM => "USEITEM",
+        SHOWEFFECT => "SHOWEFFECT",
+        HELLO => "HELLO",
+        GOTO => "GOTO",
+        INVDROP => "INVDROP",
+        INVRESULT => "INVRESULT",
+        RECONNECT => "RECONNECT",
+        PING => "PING",
+        PONG => "PONG",
+        MAPINFO => "MAPINFO",
+        LOAD => "LOAD",
+        PIC => "PIC",
+        SETCONDITION => "SETCONDITION",
+        TELEPORT => "TELEPORT",
+        USEPORTAL => "USEPORTAL",
+        DEATH => "DEATH",
+        BUY => "BUY",
+        BUYRESULT => "BUYRESULT",
+        AOE => "AOE",
+        GROUNDDAMAGE => "GROUNDDAMAGE",
+        PLAYERHIT => "PLAYERHIT",
+        ENEMYHIT => "ENEMYHIT",
+        AOEACK => "AOEACK",
+        SHOOTACK => "SHOOTACK",
+        OTHERHIT => "OTHERHIT",
+        SQUAREHIT => "SQUAREHIT",
+        GOTOACK => "GOTOACK",
+        EDITACCOUNTLIST => "EDITACCOUNTLIST",
+        ACCOUNTLIST => "ACCOUNTLIST",
+        QUESTOBJID => "QUESTOBJID",
+        CHOOSENAME => "CHOOSENAME",
+        NAMERESULT => "NAMERESULT",
+        CREATEGUILD => "CREATEGUILD",
+        CREATEGUILDRESULT => "CREATEGUILDRESULT",
+        GUILDREMOVE => "GUILDREMOVE",
+        GUILDINVITE => "GUILDINVITE",
+        ALLYSHOOT => "ALLYSHOOT",
+        ENEMYSHOOT => "ENEMYSHOOT",
+        REQUESTTRADE => "REQUESTTRADE",
+        TRADEREQUESTED => "TRADEREQUESTED",
+        TRADESTART => "TRADESTART",
+        CHANGETRADE => "CHANGETRADE",
+        TRADECHANGED => "TRADECHANGED",
+        ACCEPTTRADE => "ACCEPTTRADE",
+        CANCELTRADE => "CANCELTRADE",
+        TRADEDONE => "TRADEDONE",
+        TRADEACCEPTED => "TRADEACCEPTED",
+        CLIENTSTAT => "CLIENTSTAT",
+        CHECKCREDITS => "CHECKCREDITS",
+        ESCAPE => "ESCAPE",
+        FILE => "FILE",
+        INVITEDTOGUILD => "INVITEDTOGUILD",
+        JOINGUILD => "JOINGUILD",
+        CHANGEGUILDRANK => "CHANGEGUILDRANK",
+        PLAYSOUND => "PLAYSOUND",
+        GLOBALNOTIFICATION => "GLOBALNOTIFICATION",
+        RESKIN => "RESKIN",
+        PETUPGRADEREQUEST => "PETUPGRADEREQUEST",
+        ACTIVEPETUPDATESEND => "ACTIVEPETUPDATESEND",
+        ACTIVEPETUPDATERECV => "ACTIVEPETUPDATERECV",
+        NEWABILITY => "NEWABILITY",
+        PETYARDUPDATE => "PETYARDUPDATE",
+        EVOLVEPET => "EVOLVEPET",
+        DELETEPET => "DELETEPET",
+        HATCHPET => "HATCHPET",
+        ENTERARENA => "ENTERARENA",
+        IMMINENTARENAWAVE => "IMMINENTARENAWAVE",
+        ARENADEATH => "ARENADEATH",
+        ACCEPTARENADEATH => "ACCEPTARENADEATH",
+        VERIFYEMAIL => "VERIFYEMAIL",
+        RESKINUNLOCK => "RESKINUNLOCK",
+        PASSWORDPROMPT => "PASSWORDPROMPT",
+        QUESTFETCHASK => "QUESTFETCHASK",
+        QUESTREDEEM => "QUESTREDEEM",
+        QUESTFETCHRESPONSE => "QUESTFETCHRESPONSE",
+        QUESTREDEEMRESPONSE => "QUESTREDEEMRESPONSE",
+        PETCHANGEFORMMSG => "PETCHANGEFORMMSG",
+        KEYINFOREQUEST => "KEYINFOREQUEST",
+        KEYINFORESPONSE => "KEYINFORESPONSE",
+        LOGINREWARDSEND => "LOGINREWARDSEND",
+        LOGINREWARDRECV => "LOGINREWARDRECV",
+        QUESTROOMMESSAGE => "QUESTROOMMESSAGE",
+        PETCHANGESKIN => "PETCHANGESKIN",
+        REALMHEROLEFT => "REALMHEROLEFT",
+        RESETDAILYQUESTS => "RESETDAILYQUESTS",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Splits a captured stream into packets using their length headers
+pub fn split_packets(data: &[u8]) -> Result<Vec<buffer::Buffer>, String> {
+    let mut packets = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        if rest.len() < 5 {
+            return Err(format!(
+                "{} trailing bytes are too short for a packet header",
+                rest.len()
+            ));
+        }
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        if len < 5 || len > rest.len() {
+            return Err(format!(
+                "packet {} says it is {} bytes long, but {} bytes are left",
+                packets.len(),
+                len,
+                rest.len()
+            ));
+        }
+        let mut p = buffer::new();
+        p.data = rest[..len].to_vec();
+        packets.push(p);
+        rest = &rest[len..];
+    }
+    Ok(packets)
+}
+
+/// Decrypts captured packets in place, in order, with the constant rc4 keys. 'incoming' is server to client
+pub fn decrypt_packets(packets: &mut [buffer::Buffer], incoming: bool) {
+    let mut keys = rc4::CipherPair::new_const();
+    for p in packets.iter_mut() {
+        let mut copy = p.data.clone();
+        let cipher = if incoming {
+            &mut keys.incoming
+        } else {
+            &mut keys.outgoing
+        };
+        cipher.xor_key_stream(&mut p.data[5..], &mut copy[5..]);
+    }
+}
+
+/// Parses a decrypted server packet and pretty prints it. Returns None for ids we don't have a parser for.
+/// Parsers read past the end of truncated packets, so those come back as an error instead of a panic.
+pub fn describe_server_packet(mut p: buffer::Buffer) -> Option<Result<String, String>> {
+    if p.data.len() < 5 {
+        return Some(Err(String::from("packet is shorter than its header")));
+    }
+    let id = p.data[4];
+    p.index = 5;
+    let described = panic::catch_unwind(panic::AssertUnwindSafe(move || {
+        let text = match id {
+            FAILURE => format!("{:#?}", server_packets::Failure::new(p)),
+            MAPINFO => format!("{:#?}", server_packets::MapInfo::new(p)),
+            CREATESUCCESS => format!("{:#?}", server_packets::CreateSuccess::new(p)),
+            UPDATE => format!("{:#?}", server_packets::Update::new(p)),
+            NEWTICK => format!("{:#?}", server_packets::NewTick::new(p)),
+            PING => format!("{:#?}", server_packets::Ping::new(p)),
+            RECONNECT => format!("{:#?}", server_packets::Reconnect::new(p)),
+            ALLYSHOOT => format!("{:#?}", server_packets::AllyShoot::new(p)),
+            ENEMYSHOOT => format!("{:#?}", server_packets::EnemyShoot::new(p)),
+            INVRESULT => format!("{:#?}", server_packets::InvResult::new(p)),
+            TRADECHANGED => format!("{:#?}", server_packets::TradeChanged::new(p)),
+            TRADESTART => format!("{:#?}", server_packets::TradeStart::new(p)),
+            TRADEACCEPTED => format!("{:#?}", server_packets::TradeAccepted::new(p)),
+            TRADEDONE => format!("{:#?}", server_packets::TradeDone::new(p)),
+            TRADEREQUESTED => format!("{:#?}", server_packets::TradeRequested::new(p)),
+            GOTO => format!("{:#?}", server_packets::Goto::new(p)),
+            QUESTOBJID => format!("{:#?}", server_packets::QuestObjID::new(p)),
+            AOE => format!("{:#?}", server_packets::AoE::new(p)),
+            SERVERPLAYERSHOOT => format!("{:#?}", server_packets::ServerPlayerShoot::new(p)),
+            TEXT => format!("{:#?}", server_packets::Text::new(p)),
+            NOTIFICATION => format!("{:#?}", server_packets::Notification::new(p)),
+            GLOBALNOTIFICATION => format!("{:#?}", server_packets::GlobalNotification::new(p)),
+            ACCOUNTLIST => format!("{:#?}", server_packets::AccountList::new(p)),
+            DAMAGE => format!("{:#?}", server_packets::Damage::new(p)),
+            SHOWEFFECT => format!("{:#?}", server_packets::ShowEffect::new(p)),
+            DEATH => format!("{:#?}", server_packets::Death::new(p)),
+            BUYRESULT => format!("{:#?}", server_packets::BuyResult::new(p)),
+            NAMERESULT => format!("{:#?}", server_packets::NameResult::new(p)),
+            CREATEGUILDRESULT => format!("{:#?}", server_packets::CreateGuildResult::new(p)),
+            CLIENTSTAT => format!("{:#?}", server_packets::ClientStat::new(p)),
+            INVITEDTOGUILD => format!("{:#?}", server_packets::InvitedToGuild::new(p)),
+            PLAYSOUND => format!("{:#?}", server_packets::PlaySound::new(p)),
+            ACTIVEPETUPDATERECV => format!("{:#?}", server_packets::ActivePetUpdateRecv::new(p)),
+            NEWABILITY => format!("{:#?}", server_packets::NewAbility::new(p)),
+            PETYARDUPDATE => format!("{:#?}", server_packets::PetYardUpdate::new(p)),
+            EVOLVEPET => format!("{:#?}", server_packets::EvolvePet::new(p)),
+            DELETEPET => format!("{:#?}", server_packets::DeletePet::new(p)),
+            HATCHPET => format!("{:#?}", server_packets::HatchPet::new(p)),
+            IMMINENTARENAWAVE => format!("{:#?}", server_packets::ImminentArenaWave::new(p)),
+            ARENADEATH => format!("{:#?}", server_packets::ArenaDeath::new(p)),
+            VERIFYEMAIL => format!("{:#?}", server_packets::VerifyEmail::new(p)),
+            RESKINUNLOCK => format!("{:#?}", server_packets::ReSkinUnlock::new(p)),
+            PASSWORDPROMPT => format!("{:#?}", server_packets::PasswordPrompt::new(p)),
+            QUESTFETCHRESPONSE => format!("{:#?}", server_packets::QuestFetchResponse::new(p)),
+            QUESTREDEEMRESPONSE => format!("{:#?}", server_packets::QuestRedeemResponse::new(p)),
+            KEYINFORESPONSE => format!("{:#?}", server_packets::KeyInfoResponse::new(p)),
+            LOGINREWARDRECV => format!("{:#?}", server_packets::LoginRewardRecv::new(p)),
+            REALMHEROLEFT => format!("{:#?}", server_packets::RealmHeroLeft::new(p)),
+            FILE => format!("{:#?}", server_packets::File::new(p)),
+            PIC => format!("{:#?}", server_packets::Pic::new(p)),
+            _ => return None,
+        };
+        Some(text)
+    }));
+    match described {
+        Ok(Some(text)) => Some(Ok(text)),
+        Ok(None) => None,
+        Err(_) => Some(Err(String::from("packet is truncated or malformed"))),
+    }
+}
+
 pub const FAILURE: u8 = 0;
 pub const CREATESUCCESS: u8 = 101;
 pub const CREATE: u8 = 61;
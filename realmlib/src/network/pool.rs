@@ -0,0 +1,277 @@
+use std::fs;
+use std::io;
+use std::net;
+use std::net::ToSocketAddrs;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time;
+
+use serde::{Deserialize, Serialize};
+
+use tracing::{error, info};
+
+use crate::network::proxy;
+
+/* pool.rs - Shared proxy pool that spreads bots over many proxies */
+
+/// How the pool picks a proxy for a client
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolStrategy {
+    RoundRobin,
+    LeastUsed,
+}
+
+/// Pool settings, under "proxy_pool" in the settings file. The pool is disabled while ``file`` is empty.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PoolSettings {
+    pub file: String, //one proxy url per line, blank lines and lines starting with # are skipped
+    pub strategy: PoolStrategy,
+    pub max_clients_per_proxy: usize, //0 means unlimited
+    pub max_failures: u32,            //consecutive connect failures before a proxy is marked dead
+    pub revive_after_secs: u64, //dead proxies get another chance after this long, 0 means never
+    pub health_check_secs: u64, //how often dead proxies are probed with a tcp connect, 0 disables it
+}
+
+impl Default for PoolSettings {
+    fn default() -> PoolSettings {
+        PoolSettings {
+            file: String::new(),
+            strategy: PoolStrategy::RoundRobin,
+            max_clients_per_proxy: 3,
+            max_failures: 3,
+            revive_after_secs: 600,
+            health_check_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PoolEntry {
+    proxy: proxy::Proxy,
+    clients: usize,
+    failures: u32,
+    dead_since: Option<time::Instant>,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    entries: Vec<PoolEntry>,
+    next: usize, //round robin cursor
+}
+
+/// A proxy pool shared between every client. All methods lock internally, so hand it out in an ``Arc``.
+#[derive(Debug)]
+pub struct ProxyPool {
+    state: Mutex<PoolState>,
+    settings: PoolSettings,
+}
+
+impl ProxyPool {
+    pub fn new(proxies: Vec<proxy::Proxy>, settings: PoolSettings) -> ProxyPool {
+        let entries = proxies
+            .into_iter()
+            .map(|p| PoolEntry {
+                proxy: p,
+                clients: 0,
+                failures: 0,
+                dead_since: None,
+            })
+            .collect();
+        ProxyPool {
+            state: Mutex::new(PoolState { entries, next: 0 }),
+            settings,
+        }
+    }
+    /// Reads the proxies file named in the settings. Bare ``host:port`` lines are socks5 proxies.
+    pub fn load(settings: PoolSettings) -> io::Result<ProxyPool> {
+        let text = fs::read_to_string(&settings.file)?;
+        let mut proxies = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match proxy::Proxy::parse(line) {
+                Ok(p) => proxies.push(p),
+                Err(e) => {
+                    let msg = format!("{} line {}: {}", settings.file, i + 1, e);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                }
+            }
+        }
+        Ok(ProxyPool::new(proxies, settings))
+    }
+    /// Loads the pool if one is configured. Returns None if the pool is disabled or couldn't be loaded.
+    pub fn from_settings(settings: &PoolSettings) -> Option<Arc<ProxyPool>> {
+        if settings.file.is_empty() {
+            return None;
+        }
+        match ProxyPool::load(settings.clone()) {
+            Ok(p) => {
+                let pool = Arc::new(p);
+                if settings.health_check_secs > 0 {
+                    ProxyPool::spawn_health_checker(pool.clone());
+                }
+                Some(pool)
+            }
+            Err(e) => {
+                error!("Couldn't load proxy pool: {}", e);
+                None
+            }
+        }
+    }
+    /// Hands out a healthy proxy with a free slot, preferring one other than ``avoid``. The slot is held until ``release``.
+    pub fn acquire(&self, avoid: Option<&proxy::Proxy>) -> Option<proxy::Proxy> {
+        let mut state = self.state.lock().unwrap();
+        let len = state.entries.len();
+        if len == 0 {
+            return None;
+        }
+        self.revive_expired(&mut state);
+        let mut candidates: Vec<usize> = (0..len)
+            .map(|i| (state.next + i) % len)
+            .filter(|&i| self.is_usable(&state.entries[i]))
+            .collect();
+        //only fall back to the proxy we're moving away from if nothing else is left
+        if candidates.len() > 1 {
+            if let Some(a) = avoid {
+                candidates.retain(|&i| &state.entries[i].proxy != a);
+            }
+        }
+        let pick = match self.settings.strategy {
+            PoolStrategy::RoundRobin => candidates.first().copied(),
+            PoolStrategy::LeastUsed => candidates
+                .iter()
+                .copied()
+                .min_by_key(|&i| state.entries[i].clients),
+        }?;
+        state.next = (pick + 1) % len;
+        state.entries[pick].clients += 1;
+        Some(state.entries[pick].proxy.clone())
+    }
+    /// Gives back the slot taken by ``acquire``
+    pub fn release(&self, p: &proxy::Proxy) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(e) = state.entries.iter_mut().find(|e| &e.proxy == p) {
+            e.clients = e.clients.saturating_sub(1);
+        }
+    }
+    /// Releases the current proxy (if any) and acquires a different healthy one
+    pub fn rotate(&self, current: Option<&proxy::Proxy>) -> Option<proxy::Proxy> {
+        if let Some(c) = current {
+            self.release(c);
+        }
+        self.acquire(current)
+    }
+    /// Clears the failure count after a successful connect
+    pub fn report_success(&self, p: &proxy::Proxy) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(e) = state.entries.iter_mut().find(|e| &e.proxy == p) {
+            e.failures = 0;
+            e.dead_since = None;
+        }
+    }
+    /// Counts a connect failure against the proxy. Returns true if the proxy is now dead.
+    pub fn report_failure(&self, p: &proxy::Proxy) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.entries.iter_mut().find(|e| &e.proxy == p) {
+            Some(e) => {
+                e.failures += 1;
+                if e.failures >= self.settings.max_failures.max(1) && e.dead_since.is_none() {
+                    e.dead_since = Some(time::Instant::now());
+                }
+                e.dead_since.is_some()
+            }
+            None => false,
+        }
+    }
+    /// Tries a plain tcp connect to every dead proxy and revives the ones that answer
+    pub fn health_check(&self, timeout: time::Duration) -> usize {
+        let dead: Vec<proxy::Proxy> = {
+            let state = self.state.lock().unwrap();
+            state
+                .entries
+                .iter()
+                .filter(|e| e.dead_since.is_some())
+                .map(|e| e.proxy.clone())
+                .collect()
+        };
+        let mut revived = 0;
+        for p in dead.iter() {
+            if is_reachable(&p.address, timeout) {
+                self.report_success(p);
+                revived += 1;
+            }
+        }
+        revived
+    }
+    /// Runs ``health_check`` in the background. The thread exits once every client dropped the pool.
+    pub fn spawn_health_checker(pool: Arc<ProxyPool>) {
+        let weak = Arc::downgrade(&pool);
+        let interval = time::Duration::from_secs(pool.settings.health_check_secs.max(1));
+        drop(pool);
+        thread::Builder::new()
+            .name(String::from("ProxyHealth"))
+            .spawn(move || loop {
+                thread::sleep(interval);
+                match weak.upgrade() {
+                    Some(p) => {
+                        let revived = p.health_check(time::Duration::from_secs(5));
+                        if revived > 0 {
+                            info!("Proxy health check revived {} proxies", revived);
+                        }
+                    }
+                    None => return,
+                }
+            })
+            .unwrap();
+    }
+    pub fn is_dead(&self, p: &proxy::Proxy) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .iter()
+            .any(|e| &e.proxy == p && e.dead_since.is_some())
+    }
+    /// Returns the number of proxies not marked dead
+    pub fn healthy_count(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .iter()
+            .filter(|e| e.dead_since.is_none())
+            .count()
+    }
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn is_usable(&self, e: &PoolEntry) -> bool {
+        let max = self.settings.max_clients_per_proxy;
+        e.dead_since.is_none() && (max == 0 || e.clients < max)
+    }
+    /// Gives dead proxies one more try once their time is up. A single failure kills them again.
+    fn revive_expired(&self, state: &mut PoolState) {
+        if self.settings.revive_after_secs == 0 {
+            return;
+        }
+        let wait = time::Duration::from_secs(self.settings.revive_after_secs);
+        for e in state.entries.iter_mut() {
+            if e.dead_since.is_some_and(|d| d.elapsed() >= wait) {
+                e.dead_since = None;
+                e.failures = self.settings.max_failures.saturating_sub(1);
+            }
+        }
+    }
+}
+
+fn is_reachable(address: &str, timeout: time::Duration) -> bool {
+    match address.to_socket_addrs() {
+        Ok(mut addrs) => addrs.any(|a| net::TcpStream::connect_timeout(&a, timeout).is_ok()),
+        Err(_) => false,
+    }
+}
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 // extern crate fnv;
 
-#[derive(Debug, Clone, Copy, Hash, Eq)]
+#[derive(Debug, Clone, Copy, Eq)]
 pub struct GroundTile {
     pub x: i16,
     pub y: i16,
@@ -15,6 +15,14 @@ impl std::cmp::PartialEq for GroundTile {
     }
 }
 
+//same fields as eq, so a tile can be looked up by its position alone
+impl std::hash::Hash for GroundTile {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.x.hash(state);
+        self.y.hash(state);
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct WorldPosition {
     pub x: f32,
@@ -56,11 +64,6 @@ impl StatData {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct PositionRecords {
-    pub time: i32,
-}
-
 #[derive(Debug, Copy, Clone)]
 pub struct SlotObjectData {
     pub object_id: i32,
@@ -475,7 +478,8 @@ impl Stats {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
 pub enum Effects {
     NOTHING = 0,
     DEAD = 1,
@@ -528,6 +532,7 @@ pub enum Effects {
     SILENCED = 48,
     EXPOSED = 49,
     ENERGIZED = 50,
+    UNKNOWN(u8), //an id the bitfields hold past ENERGIZED, up to conditions::MAX_EFFECT_ID
     GROUNDDAMAGE = 99,
 }
 
@@ -585,7 +590,66 @@ impl Effects {
             Effects::SILENCED => 48,
             Effects::EXPOSED => 49,
             Effects::ENERGIZED => 50,
+            Effects::UNKNOWN(v) => *v,
             Effects::GROUNDDAMAGE => 99,
         }
     }
+    /// The effect with that id, ``UNKNOWN`` for the unnamed ones the bitfields hold. None for 0 and anything higher
+    pub fn from_byte(v: u8) -> Option<Effects> {
+        match v {
+            1 => Some(Effects::DEAD),
+            2 => Some(Effects::QUIET),
+            3 => Some(Effects::WEAK),
+            4 => Some(Effects::SLOWED),
+            5 => Some(Effects::SICK),
+            6 => Some(Effects::DAZED),
+            7 => Some(Effects::STUNNED),
+            8 => Some(Effects::BLIND),
+            9 => Some(Effects::HALLUCINATING),
+            10 => Some(Effects::DRUNK),
+            11 => Some(Effects::CONFUSED),
+            12 => Some(Effects::STUNIMMUNE),
+            13 => Some(Effects::INVISIBLE),
+            14 => Some(Effects::PARALYZED),
+            15 => Some(Effects::SPEEDY),
+            16 => Some(Effects::BLEEDING),
+            17 => Some(Effects::ARMORBROKENIMMUNE),
+            18 => Some(Effects::HEALING),
+            19 => Some(Effects::DAMAGING),
+            20 => Some(Effects::BERSERK),
+            21 => Some(Effects::PAUSED),
+            22 => Some(Effects::STASIS),
+            23 => Some(Effects::STASISIMMUNE),
+            24 => Some(Effects::INVINCIBLE),
+            25 => Some(Effects::INVULNERABLE),
+            26 => Some(Effects::ARMORED),
+            27 => Some(Effects::ARMORBROKEN),
+            28 => Some(Effects::HEXED),
+            29 => Some(Effects::NINJASPEEDY),
+            30 => Some(Effects::UNSTABLE),
+            31 => Some(Effects::DARKNESS),
+            32 => Some(Effects::SLOWIMMUNE),
+            33 => Some(Effects::DAZEIMMUNE),
+            34 => Some(Effects::PARALYZEIMMUNE),
+            35 => Some(Effects::PETRIFIED),
+            36 => Some(Effects::PETRIFIEDIMMUNE),
+            37 => Some(Effects::PETSTASIS),
+            38 => Some(Effects::CURSE),
+            39 => Some(Effects::CURSEIMMUNE),
+            40 => Some(Effects::HPBOOST),
+            41 => Some(Effects::MPBOOST),
+            42 => Some(Effects::ATKBOOST),
+            43 => Some(Effects::DEFBOOST),
+            44 => Some(Effects::SPDBOOST),
+            45 => Some(Effects::VITBOOST),
+            46 => Some(Effects::WISBOOST),
+            47 => Some(Effects::DEXBOOST),
+            48 => Some(Effects::SILENCED),
+            49 => Some(Effects::EXPOSED),
+            50 => Some(Effects::ENERGIZED),
+            51..=crate::conditions::MAX_EFFECT_ID => Some(Effects::UNKNOWN(v)),
+            99 => Some(Effects::GROUNDDAMAGE),
+            _ => None,
+        }
+    }
 }
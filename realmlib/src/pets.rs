@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::client;
+use super::network::packets::client_packets;
+use super::network::packets::client_packets::ClientPacket;
+use super::network::packets::client_packets::ClientPackets;
+use super::network::packets::server_packets;
+use super::network::types;
+use super::network::types::Stats;
+
+/* pets.rs - The active pet: read it from the PET* stats, follow, switch or release it, feed, fuse and reskin */
+
+/// ``PetUpgradeRequest.pet_trans_type``
+pub const UPGRADE_PET_YARD: u8 = 1;
+pub const FEED_PET: u8 = 2;
+pub const FUSE_PET: u8 = 3;
+
+/// ``ActivePetUpdateSend.command_type``
+pub const FOLLOW_PET: u8 = 1;
+pub const UNFOLLOW_PET: u8 = 2;
+pub const RELEASE_PET: u8 = 3;
+
+/// What the pet yard charges
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Payment {
+    #[default]
+    Gold,
+    Fame,
+}
+
+impl Payment {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Payment::Gold => 0,
+            Payment::Fame => 1,
+        }
+    }
+}
+
+/// A pet, read from the PET* stats
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Pet {
+    pub instance_id: i32,
+    pub name: String,
+    pub pet_type: i32,
+    pub rarity: i32,
+    pub max_level: i32,
+    pub family: i32,
+    pub levels: [i32; 3], //per ability
+    pub points: [i32; 3],
+    pub abilities: [i32; 3],
+}
+
+impl Pet {
+    /// None without a PETINSTANCEID
+    pub fn from_stats(stats: &HashMap<u8, types::StatData>) -> Option<Pet> {
+        let num = |s: Stats| stats.get(&s.stat_to_u8()).map_or(0, |d| d.stat_value);
+        let instance_id = stats.get(&Stats::PETINSTANCEID.stat_to_u8())?.stat_value;
+        Some(Pet {
+            instance_id,
+            name: stats
+                .get(&Stats::PETNAME.stat_to_u8())
+                .map_or_else(String::new, |d| d.str_stat_value.clone()),
+            pet_type: num(Stats::PETTYPE),
+            rarity: num(Stats::PETRARITY),
+            max_level: num(Stats::PETMAXIMUMLEVEL),
+            family: num(Stats::PETFAMILY),
+            levels: [
+                num(Stats::PETLEVEL0),
+                num(Stats::PETLEVEL1),
+                num(Stats::PETLEVEL2),
+            ],
+            points: [
+                num(Stats::PETPOINTS0),
+                num(Stats::PETPOINTS1),
+                num(Stats::PETPOINTS2),
+            ],
+            abilities: [
+                num(Stats::PETABILITYTYPE0),
+                num(Stats::PETABILITYTYPE1),
+                num(Stats::PETABILITYTYPE2),
+            ],
+        })
+    }
+}
+
+/// A HatchPet, the egg that was hatched and what came out of it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Hatched {
+    pub pet_name: String,
+    pub pet_skin: i32,
+    pub item_type: i32,
+}
+
+/// An EvolvePet
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Evolved {
+    pub pet_id: i32,
+    pub initial_skin: i32,
+    pub final_skin: i32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PetTracker {
+    pub instance_id: Option<i32>, //the active pet, from ActivePetUpdateRecv or our stats
+    pub yard_type: Option<i32>,
+    pub last_hatch: Option<Hatched>,
+    pub last_evolve: Option<Evolved>,
+}
+
+impl PetTracker {
+    pub fn new() -> PetTracker {
+        PetTracker::default()
+    }
+}
+
+impl client::Client {
+    /// The active pet, from our own stats or else the pet object in sight with its instance id
+    pub fn active_pet(&self) -> Option<Pet> {
+        if let Some(pet) = Pet::from_stats(&self.stats.stat_map) {
+            return Some(pet);
+        }
+        let id = self.pets.instance_id?;
+        self.objects
+            .entities
+            .values()
+            .filter_map(|o| Pet::from_stats(&o.status.stats))
+            .find(|p| p.instance_id == id)
+    }
+    fn pet_command(&mut self, command_type: u8, instance_id: i32) {
+        let mut apu = client_packets::ActivePetUpdateSend::new();
+        apu.command_type = command_type;
+        apu.instance_id = instance_id;
+        self.send(ClientPackets::ActivePetUpdateSendPacket(apu).write());
+    }
+    /// Makes the pet follow us, switching from the active one if it's another
+    pub fn follow_pet(&mut self, instance_id: i32) {
+        self.pet_command(FOLLOW_PET, instance_id);
+    }
+    pub fn unfollow_pet(&mut self) {
+        if let Some(id) = self.active_pet().map(|p| p.instance_id) {
+            self.pet_command(UNFOLLOW_PET, id);
+        }
+    }
+    /// Releases the pet for good
+    pub fn release_pet(&mut self, instance_id: i32) {
+        warn!("releasing pet {}", instance_id);
+        self.pet_command(RELEASE_PET, instance_id);
+    }
+    /// Feeds the item in the inventory slot to the active pet. False without a pet or an item there
+    pub fn feed_pet(&mut self, slot: u8, payment: Payment) -> bool {
+        let (pet, item) = match (self.active_pet(), self.inventory_item(slot)) {
+            (Some(pet), Some(item)) => (pet, item),
+            _ => return false,
+        };
+        let mut pur = client_packets::PetUpgradeRequest::new();
+        pur.pet_trans_type = FEED_PET;
+        pur.pet_id_one = pet.instance_id;
+        pur.object_id = self.object_id;
+        pur.slot = types::SlotObjectData {
+            object_id: self.object_id,
+            slot_id: slot,
+            object_type: item,
+        };
+        pur.payment_type = payment.to_byte();
+        self.send(ClientPackets::PetUpgradeRequestPacket(pur).write());
+        true
+    }
+    /// Fuses the second pet into the first
+    pub fn fuse_pets(&mut self, pet_one: i32, pet_two: i32, payment: Payment) {
+        let mut pur = client_packets::PetUpgradeRequest::new();
+        pur.pet_trans_type = FUSE_PET;
+        pur.pet_id_one = pet_one;
+        pur.pet_id_two = pet_two;
+        pur.object_id = self.object_id;
+        pur.payment_type = payment.to_byte();
+        self.send(ClientPackets::PetUpgradeRequestPacket(pur).write());
+    }
+    pub fn change_pet_skin(&mut self, pet_id: i32, skin_type: i32, payment: Payment) {
+        let mut pcs = client_packets::PetChangeSkin::new();
+        pcs.pet_id = pet_id;
+        pcs.skin_type = skin_type;
+        pcs.currency = i32::from(payment.to_byte());
+        self.send(ClientPackets::PetChangeSkinPacket(pcs).write());
+    }
+    pub fn pet_hatched(&mut self, hp: &server_packets::HatchPet) {
+        info!(
+            "hatched {} (skin {}) from item {}",
+            hp.pet_name, hp.pet_skin, hp.item_type
+        );
+        self.pets.last_hatch = Some(Hatched {
+            pet_name: hp.pet_name.clone(),
+            pet_skin: hp.pet_skin,
+            item_type: hp.item_type,
+        });
+    }
+    pub fn pet_evolved(&mut self, ep: &server_packets::EvolvePet) {
+        info!(
+            "pet {} evolved, skin {} to {}",
+            ep.pet_id, ep.initial_skin, ep.final_skin
+        );
+        self.pets.last_evolve = Some(Evolved {
+            pet_id: ep.pet_id,
+            initial_skin: ep.initial_skin,
+            final_skin: ep.final_skin,
+        });
+    }
+    pub fn pet_deleted(&mut self, pet_id: i32) {
+        info!("pet {} is gone", pet_id);
+        if self.pets.instance_id == Some(pet_id) {
+            self.pets.instance_id = None;
+        }
+    }
+}
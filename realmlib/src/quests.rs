@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+use super::client;
+use super::network::types;
+
+/* quests.rs - Keeps track of the quest the server points us at, and hunts it down: walk there, circle it and shoot */
+
+/// Quest hunting settings, under "hunt" in the settings file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HuntSettings {
+    pub enabled: bool, //hunt quests as soon as the bot joins, the control api can turn it on later
+    pub shoot_range: f32, //tiles, shoots the quest once it's this close
+}
+
+impl Default for HuntSettings {
+    fn default() -> HuntSettings {
+        HuntSettings {
+            enabled: false,
+            shoot_range: 8.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QuestTracker {
+    pub object_id: Option<i32>, //the last QuestObjID, None until one comes in on this map
+    pub hunting: bool,
+}
+
+impl QuestTracker {
+    pub fn new() -> QuestTracker {
+        QuestTracker {
+            object_id: None,
+            hunting: false,
+        }
+    }
+}
+
+impl Default for QuestTracker {
+    fn default() -> QuestTracker {
+        QuestTracker::new()
+    }
+}
+
+impl client::Client {
+    /// The quest object, if we know the quest and can see it
+    pub fn quest_object(&self) -> Option<&types::ObjectData> {
+        self.objects.entities.get(&self.quest.object_id?)
+    }
+    pub fn quest_position(&self) -> Option<types::WorldPosition> {
+        self.quest_object().map(|o| o.status.position)
+    }
+    /// Tiles between us and the quest
+    pub fn quest_distance(&self) -> Option<f32> {
+        self.quest_position()
+            .map(|p| self.movement.current_position.distance_to(&p))
+    }
+    /// Angle from us to the quest in radians, the one ``shoot`` takes
+    pub fn quest_angle(&self) -> Option<f32> {
+        self.quest_position()
+            .map(|p| self.movement.current_position.angle_to(&p))
+    }
+    pub fn start_hunting(&mut self) {
+        //a followed object would overwrite target_position every tick
+        self.objects.target_object = types::ObjectStatusData::new();
+        self.quest.hunting = true;
+    }
+    pub fn stop_hunting(&mut self) {
+        self.quest.hunting = false;
+        self.movement.target_position = self.movement.current_position;
+    }
+    /// One tick of hunting: head for the quest, circle it at ``Movement.radius`` once there
+    /// and shoot it when it's in range. Does nothing in the nexus or while the quest is out of sight
+    pub fn hunt_quest(&mut self) {
+        if self.current_map == "Nexus" {
+            return;
+        }
+        let quest = match self.quest_position() {
+            Some(p) => p,
+            None => return,
+        };
+        self.engage(quest, self.config.hunt.shoot_range);
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+use std::time;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use super::client;
+use super::network::packets::client_packets;
+use super::network::packets::client_packets::ClientPacket;
+use super::network::packets::client_packets::ClientPackets;
+use super::network::packets::server_packets;
+use super::web;
+
+/* rewards.rs - The login rewards calendar: fetch it, claim the unlocked days in the nexus and keep what came back */
+
+/// Login rewards settings, under "login_rewards" in the settings file
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct LoginRewardSettings {
+    pub enabled: bool,
+    pub api_base: String, //where the calendar comes from, "api_base_url" when empty
+}
+
+/// What a LoginRewardRecv gave the account
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LoginReward {
+    pub item_id: i32,
+    pub quantity: i32,
+    pub gold: i32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoginRewards {
+    pub calendar: Vec<web::CalendarDay>, //empty until it's fetched
+    pub sent: HashSet<String>,           //claim keys already handed in
+    pub received: Vec<LoginReward>,
+}
+
+impl LoginRewards {
+    pub fn new() -> LoginRewards {
+        LoginRewards::default()
+    }
+    /// Unlocked days we haven't claimed or sent a claim for yet
+    pub fn unclaimed(&self) -> Vec<&web::CalendarDay> {
+        self.calendar
+            .iter()
+            .filter(|d| d.claimable() && !self.sent.contains(&d.key))
+            .collect()
+    }
+}
+
+impl client::Client {
+    /// Where the calendar comes from, ``login_rewards.api_base`` or the main api
+    pub fn rewards_api_base(&self) -> &str {
+        if self.config.login_rewards.api_base.is_empty() {
+            &self.config.api_base_url
+        } else {
+            &self.config.login_rewards.api_base
+        }
+    }
+    /// Fetches the account's calendar from the web api
+    pub fn fetch_login_calendar(&mut self) -> Result<(), web::WebError> {
+        let timeout = time::Duration::from_millis(self.config.connect_timeout_ms.max(1));
+        let calendar = web::fetch_calendar(
+            self.rewards_api_base(),
+            &self.base.email,
+            &self.base.password,
+            timeout,
+        )?;
+        self.rewards.calendar = calendar;
+        debug!("{} login rewards to claim", self.rewards.unclaimed().len());
+        Ok(())
+    }
+    /// Sends a LoginRewardSend for every unlocked day nobody claimed yet. The server only takes them in the nexus
+    pub fn claim_login_rewards(&mut self) {
+        if self.current_map != "Nexus" {
+            return;
+        }
+        let claims: Vec<(String, String)> = self
+            .rewards
+            .unclaimed()
+            .into_iter()
+            .map(|d| (d.key.clone(), d.claim_type.clone()))
+            .collect();
+        for (key, claim_type) in claims {
+            info!("claiming the {} login reward", claim_type);
+            let mut lrs = client_packets::LoginRewardSend::new();
+            lrs.claim_key = key.clone();
+            lrs.claim_type = claim_type;
+            self.send(ClientPackets::LoginRewardSendPacket(lrs).write());
+            self.rewards.sent.insert(key);
+        }
+    }
+    pub fn got_login_reward(&mut self, r: &server_packets::LoginRewardRecv) {
+        info!(
+            "login reward: item {} x{}, {} gold",
+            r.item_id, r.quantity, r.gold
+        );
+        self.rewards.received.push(LoginReward {
+            item_id: r.item_id,
+            quantity: r.quantity,
+            gold: r.gold,
+        });
+    }
+}
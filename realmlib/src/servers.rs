@@ -0,0 +1,186 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use tracing::{info, warn};
+
+use super::client;
+use super::web;
+use super::web::ServerInfo;
+
+/* servers.rs - Maps server names like "USWest" to hosts and picks servers for accounts */
+
+/// Picks the least populated server instead of a named one
+pub const LEAST_POPULATED: &str = "least_populated";
+
+#[derive(Debug, Clone, Default)]
+pub struct ServerList {
+    pub servers: Vec<ServerInfo>,
+}
+
+impl ServerList {
+    pub fn new(servers: Vec<ServerInfo>) -> ServerList {
+        ServerList { servers }
+    }
+
+    /// Builds the list from a /char/list response
+    pub fn from_char_list(list: &web::CharList) -> ServerList {
+        ServerList::new(list.servers.clone())
+    }
+
+    /// Parses a json server list, either `[{"name": "USWest", "dns": "..."}]` or `{"USWest": "..."}`
+    pub fn from_json(json: &str) -> Result<ServerList, String> {
+        let value: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if let serde_json::Value::Object(map) = value {
+            let mut servers = Vec::with_capacity(map.len());
+            for (name, host) in map {
+                match host.as_str() {
+                    Some(h) => servers.push(ServerInfo {
+                        name,
+                        dns: h.to_owned(),
+                        ..ServerInfo::default()
+                    }),
+                    None => return Err(format!("the host for {} isn't a string", name)),
+                }
+            }
+            return Ok(ServerList::new(servers));
+        }
+        serde_json::from_value(value)
+            .map(ServerList::new)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Parses a <Servers> xml document or a saved /char/list response
+    pub fn from_xml(xml: &str) -> Result<ServerList, String> {
+        web::parse_servers(xml)
+            .map(ServerList::new)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Loads a server list file, json or xml depending on its contents
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ServerList> {
+        let text = fs::read_to_string(path)?;
+        let parsed = if text.trim_start().starts_with('<') {
+            ServerList::from_xml(&text)
+        } else {
+            ServerList::from_json(&text)
+        };
+        parsed.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.servers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+
+    /// Finds a server by name, ignoring case
+    pub fn find(&self, name: &str) -> Option<&ServerInfo> {
+        self.servers
+            .iter()
+            .find(|s| s.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Finds the server a host belongs to
+    pub fn find_host(&self, host: &str) -> Option<&ServerInfo> {
+        self.servers.iter().find(|s| s.dns == host)
+    }
+
+    /// Returns the server with the lowest usage, skipping admin only servers and the hosts in 'avoid'
+    pub fn least_populated(&self, avoid: &[&str]) -> Option<&ServerInfo> {
+        self.servers
+            .iter()
+            .filter(|s| !s.admin_only && !avoid.contains(&s.dns.as_str()))
+            .min_by(|a, b| {
+                a.usage
+                    .partial_cmp(&b.usage)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    /// Picks a server for a setting that is either a name, "least_populated" or empty (also least populated)
+    pub fn select(&self, choice: &str) -> Option<&ServerInfo> {
+        if choice.is_empty() || choice.eq_ignore_ascii_case(LEAST_POPULATED) {
+            self.least_populated(&[])
+        } else {
+            self.find(choice)
+        }
+    }
+
+    /// Turns a server name into its host. Anything that isn't a known name is assumed to be a host already
+    pub fn resolve(&self, name_or_host: &str) -> String {
+        match self.select(name_or_host) {
+            Some(s) => s.dns.clone(),
+            None => name_or_host.to_owned(),
+        }
+    }
+
+    /// Returns the server after 'current' in list order, wrapping around and skipping admin only servers
+    pub fn next_after(&self, current: &str) -> Option<&ServerInfo> {
+        let usable: Vec<&ServerInfo> = self.servers.iter().filter(|s| !s.admin_only).collect();
+        if usable.is_empty() {
+            return None;
+        }
+        let next = match usable
+            .iter()
+            .position(|s| s.dns == current || s.name.eq_ignore_ascii_case(current))
+        {
+            Some(i) => (i + 1) % usable.len(),
+            None => 0,
+        };
+        Some(usable[next])
+    }
+}
+
+/// Loads the shared server list file, printing (not failing) when it can't be read
+pub fn load_server_list(path: &str) -> Option<Arc<ServerList>> {
+    if path.is_empty() {
+        return None;
+    }
+    match ServerList::load(path) {
+        Ok(list) => Some(Arc::new(list)),
+        Err(e) => {
+            warn!("Couldn't load the server list {}: {}", path, e);
+            None
+        }
+    }
+}
+
+impl client::Client {
+    /// Resolves the accounts 'server' (or a server name in 'server_ip') to a host and stores it in 'server_ip'
+    pub fn select_server(&mut self) {
+        let choice = if self.base.server.is_empty() {
+            self.base.server_ip.clone()
+        } else {
+            self.base.server.clone()
+        };
+        let picked = match &self.servers {
+            Some(list) => list.select(&choice).map(|s| s.dns.clone()),
+            None => None,
+        };
+        match picked {
+            Some(host) => self.base.server_ip = host,
+            None if !self.base.server.is_empty() => warn!(
+                "no known server called {}, staying on {}",
+                self.base.server, self.base.server_ip
+            ),
+            None => (),
+        }
+    }
+
+    /// Moves the client on to the next server in the list
+    pub fn rotate_server(&mut self) {
+        let next = match &self.servers {
+            Some(list) => list.next_after(&self.recon.current_server).cloned(),
+            None => None,
+        };
+        if let Some(s) = next {
+            info!("rotating to {}", s.name);
+            self.recon.previous_server = self.recon.current_server.clone();
+            self.recon.current_server = s.dns;
+        }
+    }
+}
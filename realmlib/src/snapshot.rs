@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::client;
+use super::network::types::Stats;
+
+/* snapshot.rs - Saves each bot's state to a json file now and then, and picks it back up at startup */
+
+/// Reconnects kept in a snapshot, oldest ones are dropped first
+pub const MAX_RECONNECT_HISTORY: usize = 100;
+
+/// Snapshot settings, under "snapshots" in the settings file. How often to save is ``save_delay_secs``
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SnapshotSettings {
+    pub dir: String,         //one json file per account, empty (the default) disables snapshots
+    pub resume_server: bool, //reconnect to the last server instead of the account's
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReconnectRecord {
+    pub at: u64, //unix seconds
+    pub reason: String,
+    pub server: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Snapshot {
+    pub email: String,
+    pub saved_at: u64, //unix seconds
+    pub char_id: i32,
+    pub ign: String,
+    pub map: String,
+    pub server: String,
+    pub inventory: Vec<i32>,
+    pub stats: BTreeMap<u8, i32>, //every number stat by stat id
+    pub fame: i32,
+    pub account_fame: i32,
+    pub deaths: u32,
+    pub reconnects: VecDeque<ReconnectRecord>,
+}
+
+/// What the client tracks for its snapshots between saves
+#[derive(Debug, Clone)]
+pub struct History {
+    pub deaths: u32,
+    pub reconnects: VecDeque<ReconnectRecord>,
+    pub resumed: bool, //the saved snapshot was loaded already
+    pub last_save: time::Instant,
+    pub last: Option<Snapshot>, //the last snapshot saved or resumed
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            deaths: 0,
+            reconnects: VecDeque::new(),
+            resumed: false,
+            last_save: time::Instant::now(),
+            last: None,
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new()
+    }
+}
+
+pub fn unix_now() -> u64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// A directory of snapshots, named after the account emails
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    pub dir: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn new<P: AsRef<Path>>(dir: P) -> SnapshotStore {
+        SnapshotStore {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// None when snapshots are disabled
+    pub fn from_settings(settings: &SnapshotSettings) -> Option<SnapshotStore> {
+        if settings.dir.is_empty() {
+            None
+        } else {
+            Some(SnapshotStore::new(&settings.dir))
+        }
+    }
+
+    pub fn path_for(&self, email: &str) -> PathBuf {
+        let name: String = email
+            .to_ascii_lowercase()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '@' || c == '.' || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(format!("{}.json", name))
+    }
+
+    /// Ok(None) if the account has no snapshot yet
+    pub fn load(&self, email: &str) -> io::Result<Option<Snapshot>> {
+        let text = match fs::read_to_string(self.path_for(email)) {
+            Ok(t) => t,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes to a temporary file first, so a crash mid-save doesn't leave half a snapshot
+    pub fn save(&self, s: &Snapshot) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path_for(&s.email);
+        let tmp = path.with_extension("json.tmp");
+        let text = serde_json::to_string_pretty(s)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, &path)
+    }
+
+    /// Every snapshot in the directory, sorted by email. Files that don't parse are skipped
+    pub fn load_all(&self) -> io::Result<Vec<Snapshot>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(e) => e,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut all = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            let parsed = fs::read_to_string(&path)
+                .ok()
+                .and_then(|t| serde_json::from_str::<Snapshot>(&t).ok());
+            if let Some(s) = parsed {
+                all.push(s);
+            }
+        }
+        all.sort_by(|a, b| a.email.cmp(&b.email));
+        Ok(all)
+    }
+}
+
+impl client::Client {
+    /// The client's current state as a snapshot. The stats are cleared while reconnecting,
+    /// the last snapshot's stats are kept until new ones come in
+    pub fn snapshot(&self) -> Snapshot {
+        let stat = |s: Stats| {
+            self.stats
+                .stat_map
+                .get(&s.stat_to_u8())
+                .map_or(0, |v| v.stat_value)
+        };
+        let inventory = (Stats::INVENTORY0.stat_to_u8()..=Stats::INVENTORY11.stat_to_u8())
+            .map(|i| self.stats.stat_map.get(&i).map_or(-1, |s| s.stat_value))
+            .collect();
+        let stats = self
+            .stats
+            .stat_map
+            .values()
+            .filter(|s| !s.is_string_stat())
+            .map(|s| (s.stat_type, s.stat_value))
+            .collect();
+        let mut s = Snapshot {
+            email: self.base.email.clone(),
+            saved_at: unix_now(),
+            char_id: self.base.char_id,
+            ign: self.ign.clone(),
+            map: self.current_map.clone(),
+            server: self.recon.current_server.clone(),
+            inventory,
+            stats,
+            fame: stat(Stats::CHARACTERFAME),
+            account_fame: stat(Stats::ACCOUNTFAME),
+            deaths: self.history.deaths,
+            reconnects: self.history.reconnects.clone(),
+        };
+        if let (true, Some(last)) = (self.stats.stat_map.is_empty(), &self.history.last) {
+            s.inventory = last.inventory.clone();
+            s.stats = last.stats.clone();
+            s.fame = last.fame;
+            s.account_fame = last.account_fame;
+        }
+        s
+    }
+
+    /// Picks up the counters, ign and char id from the saved snapshot. Only runs once per client
+    pub fn resume_snapshot(&mut self) {
+        if self.history.resumed {
+            return;
+        }
+        self.history.resumed = true;
+        let store = match &self.snapshots {
+            Some(s) => s.clone(),
+            None => return,
+        };
+        let s = match store.load(&self.base.email) {
+            Ok(Some(s)) => s,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("couldn't load the snapshot: {}", e);
+                return;
+            }
+        };
+        self.history.deaths = s.deaths;
+        self.history.reconnects = s.reconnects.clone();
+        if self.ign.is_empty() {
+            self.ign = s.ign.clone();
+        }
+        if self.base.char_id == 0 {
+            self.base.char_id = s.char_id;
+        }
+        if self.config.snapshots.resume_server && !s.server.is_empty() {
+            self.base.server.clear();
+            self.base.server_ip = s.server.clone();
+        }
+        info!("resumed the snapshot from {}", s.saved_at);
+        self.history.last = Some(s);
+    }
+
+    pub fn save_snapshot(&mut self) {
+        self.history.last_save = time::Instant::now();
+        let store = match &self.snapshots {
+            Some(s) => s.clone(),
+            None => return,
+        };
+        let s = self.snapshot();
+        match store.save(&s) {
+            Ok(()) => self.history.last = Some(s),
+            Err(e) => warn!("couldn't save the snapshot: {}", e),
+        }
+    }
+
+    /// Saves once 'save_delay_secs' have passed since the last save. 0 only saves when the client stops
+    pub fn save_snapshot_if_due(&mut self) {
+        let delay = self.config.save_delay_secs;
+        if delay > 0 && self.history.last_save.elapsed().as_secs() >= delay as u64 {
+            self.save_snapshot();
+        }
+    }
+
+    /// Adds the reconnect to the history
+    pub fn record_reconnect(&mut self, reason: client::ReconnectReason) {
+        self.history.reconnects.push_back(ReconnectRecord {
+            at: unix_now(),
+            reason: format!("{:?}", reason),
+            server: self.recon.current_server.clone(),
+        });
+        while self.history.reconnects.len() > MAX_RECONNECT_HISTORY {
+            self.history.reconnects.pop_front();
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use super::client;
+use super::network::packets::client_packets;
+use super::network::packets::client_packets::ClientPacket;
+use super::network::packets::client_packets::ClientPackets;
+use super::network::types;
+use super::network::types::Effects;
+use super::web;
+
+/* tiles.rs - Ground tile definitions: how fast the client walks on a tile and whether it hurts */
+
+/// How often the official client reports damage from the tile it stands on, in ms
+pub const GROUND_DAMAGE_INTERVAL_MS: i32 = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct TileDef {
+    pub tile_type: u16,
+    pub id: String,
+    pub speed: f32, //move speed multiplier, below 1 on water and other slow tiles
+    pub min_damage: i32,
+    pub max_damage: i32, //0 for tiles that don't hurt
+}
+
+impl Default for TileDef {
+    fn default() -> TileDef {
+        TileDef {
+            tile_type: 0,
+            id: String::new(),
+            speed: 1.0,
+            min_damage: 0,
+            max_damage: 0,
+        }
+    }
+}
+
+impl TileDef {
+    pub fn is_damaging(&self) -> bool {
+        self.max_damage > 0
+    }
+}
+
+/// Tile definitions keyed by ``GroundTile.tile_type``
+#[derive(Debug, Clone, Default)]
+pub struct TileDefs {
+    pub defs: HashMap<u16, TileDef>,
+}
+
+impl TileDefs {
+    pub fn new(defs: Vec<TileDef>) -> TileDefs {
+        TileDefs {
+            defs: defs.into_iter().map(|d| (d.tile_type, d)).collect(),
+        }
+    }
+
+    /// Parses a json list, `[{"tile_type": 70, "id": "Lava", "speed": 1.0, "max_damage": 40}]`
+    pub fn from_json(json: &str) -> Result<TileDefs, String> {
+        serde_json::from_str(json)
+            .map(TileDefs::new)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Parses the game's <GroundTypes> xml. A tile without <MinDamage> always does its <MaxDamage>
+    pub fn from_xml(xml: &str) -> Result<TileDefs, String> {
+        let doc = roxmltree::Document::parse(xml).map_err(|e| e.to_string())?;
+        let mut defs = Vec::new();
+        for node in doc.descendants().filter(|n| n.has_tag_name("Ground")) {
+            let tile_type = match node.attribute("type").and_then(parse_type) {
+                Some(t) => t,
+                None => return Err(String::from("a <Ground> has no valid type")),
+            };
+            let num = |name: &str| web::child_text(node, name).parse::<i32>().ok();
+            let max_damage = num("MaxDamage").unwrap_or(0);
+            defs.push(TileDef {
+                tile_type,
+                id: node.attribute("id").unwrap_or("").to_owned(),
+                speed: web::child_text(node, "Speed").parse().unwrap_or(1.0),
+                min_damage: num("MinDamage").unwrap_or(max_damage),
+                max_damage,
+            });
+        }
+        Ok(TileDefs::new(defs))
+    }
+
+    /// Loads a tile definitions file, json or xml depending on its contents
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<TileDefs> {
+        let text = fs::read_to_string(path)?;
+        let parsed = if text.trim_start().starts_with('<') {
+            TileDefs::from_xml(&text)
+        } else {
+            TileDefs::from_json(&text)
+        };
+        parsed.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn get(&self, tile_type: u16) -> Option<&TileDef> {
+        self.defs.get(&tile_type)
+    }
+
+    pub fn len(&self) -> usize {
+        self.defs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defs.is_empty()
+    }
+}
+
+/// "0x00be" or "190"
+fn parse_type(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Loads the tile definitions file, None if there isn't one or it doesn't load
+pub fn load_tile_defs(path: &str) -> Option<Arc<TileDefs>> {
+    if path.is_empty() {
+        return None;
+    }
+    match TileDefs::load(path) {
+        Ok(defs) => Some(Arc::new(defs)),
+        Err(e) => {
+            warn!("Couldn't load the tile definitions {}: {}", path, e);
+            None
+        }
+    }
+}
+
+impl client::Client {
+    /// The definition of the tile under 'pos', if both the tile and its type are known
+    pub fn tile_def_at(&self, pos: &types::WorldPosition) -> Option<&TileDef> {
+        let tile_type = self.movement.tile_at(pos)?;
+        self.tiles.as_ref()?.get(tile_type)
+    }
+    /// Speed multiplier of the tile the client stands on, 1 if it isn't known
+    pub fn tile_speed(&self) -> f32 {
+        self.tile_def_at(&self.movement.current_position)
+            .map_or(1.0, |d| d.speed)
+    }
+    /// Sends a GroundDamage for the sampled positions on damaging tiles, at most once every
+    /// GROUND_DAMAGE_INTERVAL_MS. The server disconnects clients that take lava damage without reporting it
+    pub fn report_ground_damage(&mut self, records: &[types::MoveRecord]) {
+        if self.tiles.is_none() || self.movement.reset_pos {
+            return;
+        }
+        let samples: Vec<(i32, types::WorldPosition)> = if records.is_empty() {
+            vec![(
+                self.time_keeper.current_tick_time,
+                self.movement.current_position,
+            )]
+        } else {
+            records
+                .iter()
+                .map(|r| (r.time, types::WorldPosition::new_fill(r.x, r.y)))
+                .collect()
+        };
+        for (time, position) in samples {
+            if !self.tile_def_at(&position).is_some_and(|d| d.is_damaging()) {
+                continue;
+            }
+            let last = self.time_keeper.last_ground_damage;
+            if last >= 0 && time - last < GROUND_DAMAGE_INTERVAL_MS {
+                continue;
+            }
+            if self.has_effect(Effects::INVINCIBLE) {
+                return; //the server doesn't hurt us either
+            }
+            let mut gd = client_packets::GroundDamage::new();
+            gd.time = time;
+            gd.position = position;
+            debug!("Ground damage at {:?}", position);
+            self.send(ClientPackets::GroundDamagePacket(gd).write());
+            self.time_keeper.last_ground_damage = time;
+        }
+    }
+}
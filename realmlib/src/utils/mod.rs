@@ -43,3 +43,23 @@ pub fn rand_i32() -> i32 {
         return rand_i32();
     }
 }
+
+/// Decodes a hex dump such as "00 00 00 09 08" or "0x0000000908". Whitespace, ':' and ',' between bytes are ignored
+pub fn decode_hex(input: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = input
+        .split_whitespace()
+        .map(|w| w.trim_start_matches("0x").trim_start_matches("0X"))
+        .flat_map(|w| w.chars())
+        .filter(|c| *c != ':' && *c != ',')
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(String::from("hex dump has an odd number of digits"));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let byte: String = pair.iter().collect();
+            u8::from_str_radix(&byte, 16).map_err(|_| format!("{} isn't a hex byte", byte))
+        })
+        .collect()
+}
@@ -0,0 +1,522 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::net;
+use std::net::ToSocketAddrs;
+use std::time;
+
+extern crate openssl;
+extern crate roxmltree;
+extern crate serde;
+
+use openssl::ssl::{SslConnector, SslMethod};
+use serde::{Deserialize, Serialize};
+
+/* web.rs - Small http(s) client for the game's web app api (account verify, char list, login calendar) */
+
+pub const DEFAULT_API_BASE: &str = "https://realmofthemadgodhrd.appspot.com";
+
+/// Everything that can go wrong talking to the web app
+#[derive(Debug)]
+pub enum WebError {
+    /// The url couldn't be parsed
+    BadUrl(String),
+    /// Socket level error
+    Io(io::Error),
+    /// The tls handshake failed
+    Tls(String),
+    /// The server answered with a non 2xx status code
+    Http(u16),
+    /// The api answered with an <Error> document, such as a wrong password
+    Api(String),
+    /// The response wasn't the xml we expected
+    Parse(String),
+}
+
+impl fmt::Display for WebError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebError::BadUrl(u) => write!(f, "bad url: {}", u),
+            WebError::Io(e) => write!(f, "io error: {}", e),
+            WebError::Tls(e) => write!(f, "tls error: {}", e),
+            WebError::Http(code) => write!(f, "http status {}", code),
+            WebError::Api(e) => write!(f, "api error: {}", e),
+            WebError::Parse(e) => write!(f, "couldn't parse response: {}", e),
+        }
+    }
+}
+
+impl error::Error for WebError {}
+
+impl From<io::Error> for WebError {
+    fn from(e: io::Error) -> WebError {
+        WebError::Io(e)
+    }
+}
+
+/// The parts of an http(s) url we care about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    pub https: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Result<Url, WebError> {
+        let (https, rest) = if let Some(r) = url.strip_prefix("https://") {
+            (true, r)
+        } else if let Some(r) = url.strip_prefix("http://") {
+            (false, r)
+        } else {
+            return Err(WebError::BadUrl(url.to_owned()));
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let default_port = if https { 443 } else { 80 };
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority.ends_with(']') => match authority[i + 1..].parse::<u16>() {
+                Ok(p) => (&authority[..i], p),
+                Err(_) => return Err(WebError::BadUrl(url.to_owned())),
+            },
+            _ => (authority, default_port),
+        };
+        if host.is_empty() {
+            return Err(WebError::BadUrl(url.to_owned()));
+        }
+        Ok(Url {
+            https,
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        })
+    }
+}
+
+/// Joins the api base url and an endpoint path
+pub fn api_url(base: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+/// Percent encodes a form value
+pub fn url_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// POSTs the form parameters to the url and returns the response body
+pub fn post_form(
+    url: &str,
+    params: &[(&str, &str)],
+    timeout: time::Duration,
+) -> Result<String, WebError> {
+    let u = Url::parse(url)?;
+    let body = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", url_encode(k), url_encode(v)))
+        .collect::<Vec<String>>()
+        .join("&");
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: realmlib\r\nAccept: */*\r\nConnection: close\r\n\
+         Content-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+        u.path,
+        u.host,
+        body.len(),
+        body
+    );
+    let addr = match (u.host.as_str(), u.port).to_socket_addrs()?.next() {
+        Some(a) => a,
+        None => return Err(WebError::BadUrl(url.to_owned())),
+    };
+    let stream = net::TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let raw = if u.https {
+        let connector = SslConnector::builder(SslMethod::tls())
+            .map_err(|e| WebError::Tls(e.to_string()))?
+            .build();
+        let mut tls = connector
+            .connect(&u.host, stream)
+            .map_err(|e| WebError::Tls(e.to_string()))?;
+        exchange(&mut tls, &request)?
+    } else {
+        let mut stream = stream;
+        exchange(&mut stream, &request)?
+    };
+    parse_response(&raw)
+}
+
+/// Writes the request and reads until the server closes the connection
+fn exchange<S: Read + Write>(s: &mut S, request: &str) -> Result<Vec<u8>, WebError> {
+    s.write_all(request.as_bytes())?;
+    let mut raw = Vec::new();
+    match s.read_to_end(&mut raw) {
+        Ok(_) => Ok(raw),
+        //some servers skip the tls close_notify, we already have everything by then
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof && !raw.is_empty() => Ok(raw),
+        Err(e) => Err(WebError::Io(e)),
+    }
+}
+
+/// Splits off the headers, checks the status and undoes chunked encoding
+fn parse_response(raw: &[u8]) -> Result<String, WebError> {
+    let split = match raw.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(i) => i,
+        None => return Err(WebError::Parse(String::from("no http headers"))),
+    };
+    let head = String::from_utf8_lossy(&raw[..split]);
+    let body = &raw[split + 4..];
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|l| l.split_whitespace().nth(1))
+        .and_then(|c| c.parse::<u16>().ok())
+        .ok_or_else(|| WebError::Parse(String::from("bad status line")))?;
+    if !(200..300).contains(&status) {
+        return Err(WebError::Http(status));
+    }
+    let chunked = lines.any(|l| {
+        let l = l.to_ascii_lowercase();
+        l.starts_with("transfer-encoding:") && l.contains("chunked")
+    });
+    let body = if chunked {
+        decode_chunked(body)?
+    } else {
+        body.to_vec()
+    };
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+fn decode_chunked(mut data: &[u8]) -> Result<Vec<u8>, WebError> {
+    let mut out = Vec::with_capacity(data.len());
+    loop {
+        let line_end = match data.windows(2).position(|w| w == b"\r\n") {
+            Some(i) => i,
+            None => return Err(WebError::Parse(String::from("truncated chunk"))),
+        };
+        let size_str = String::from_utf8_lossy(&data[..line_end]);
+        let size_str = size_str.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_str, 16)
+            .map_err(|_| WebError::Parse(String::from("bad chunk size")))?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if data.len() < size {
+            return Err(WebError::Parse(String::from("truncated chunk")));
+        }
+        out.extend_from_slice(&data[..size]);
+        data = &data[(size + 2).min(data.len())..];
+    }
+}
+
+/// Account details from /account/verify or the <Account> node of /char/list
+#[derive(Debug, Clone, Default)]
+pub struct AccountInfo {
+    pub account_id: String,
+    pub name: String,
+    pub name_chosen: bool,
+    pub credits: i32,
+    pub fame: i32,
+    pub total_fame: i32,
+    pub vault: Vec<Vec<i32>>, //one entry per chest, -1 is an empty slot
+}
+
+/// A character from /char/list
+#[derive(Debug, Clone, Default)]
+pub struct CharInfo {
+    pub id: i32,
+    pub class: u16, //the class' object type
+    pub level: i32,
+    pub exp: i32,
+    pub current_fame: i32,
+    pub equipment: Vec<i32>,
+    pub dead: bool,
+}
+
+/// A game server from /char/list
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerInfo {
+    pub name: String,
+    pub dns: String,
+    pub lat: f32,
+    pub long: f32,
+    pub usage: f32,
+    pub admin_only: bool,
+}
+
+/// A day of the login rewards calendar from /dailyLogin/fetchCalendar
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CalendarDay {
+    pub claim_type: String, //"consecutive" or "nonconsecutive", the calendar it's in
+    pub days: i32,          //logins it takes to unlock
+    pub item_id: i32,       //-1 for gold only rewards
+    pub quantity: i32,
+    pub gold: i32,
+    pub key: String, //only sent for unlocked days, LoginRewardSend hands it back
+    pub claimed: bool,
+}
+
+impl CalendarDay {
+    pub fn claimable(&self) -> bool {
+        !self.claimed && !self.key.is_empty()
+    }
+}
+
+/// The parsed /char/list response
+#[derive(Debug, Clone, Default)]
+pub struct CharList {
+    pub next_char_id: i32,
+    pub max_num_chars: i32,
+    pub chars: Vec<CharInfo>,
+    pub account: AccountInfo,
+    pub servers: Vec<ServerInfo>,
+}
+
+impl CharList {
+    /// Returns the id of the first living character, if the account has one
+    pub fn first_char_id(&self) -> Option<i32> {
+        self.chars.iter().find(|c| !c.dead).map(|c| c.id)
+    }
+}
+
+/// Calls /account/verify with the accounts credentials
+pub fn verify_account(
+    base: &str,
+    email: &str,
+    password: &str,
+    timeout: time::Duration,
+) -> Result<AccountInfo, WebError> {
+    let body = post_form(
+        &api_url(base, "account/verify"),
+        &[("guid", email), ("password", password)],
+        timeout,
+    )?;
+    parse_account(&body)
+}
+
+/// Calls /char/list with the accounts credentials
+pub fn fetch_char_list(
+    base: &str,
+    email: &str,
+    password: &str,
+    timeout: time::Duration,
+) -> Result<CharList, WebError> {
+    let body = post_form(
+        &api_url(base, "char/list"),
+        &[("guid", email), ("password", password)],
+        timeout,
+    )?;
+    parse_char_list(&body)
+}
+
+/// Calls /dailyLogin/fetchCalendar with the accounts credentials
+pub fn fetch_calendar(
+    base: &str,
+    email: &str,
+    password: &str,
+    timeout: time::Duration,
+) -> Result<Vec<CalendarDay>, WebError> {
+    let body = post_form(
+        &api_url(base, "dailyLogin/fetchCalendar"),
+        &[("guid", email), ("password", password)],
+        timeout,
+    )?;
+    parse_calendar(&body)
+}
+
+/// Parses the document, turning an <Error> root into ``WebError::Api``
+fn parse_document(xml: &str) -> Result<roxmltree::Document<'_>, WebError> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| WebError::Parse(e.to_string()))?;
+    let root = doc.root_element();
+    if root.has_tag_name("Error") {
+        return Err(WebError::Api(root.text().unwrap_or("").trim().to_owned()));
+    }
+    Ok(doc)
+}
+
+pub(crate) fn child<'a, 'i>(
+    node: roxmltree::Node<'a, 'i>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'i>> {
+    node.children().find(|c| c.has_tag_name(name))
+}
+
+pub(crate) fn child_text(node: roxmltree::Node, name: &str) -> String {
+    child(node, name)
+        .and_then(|c| c.text())
+        .unwrap_or("")
+        .trim()
+        .to_owned()
+}
+
+fn child_num<T: std::str::FromStr + Default>(node: roxmltree::Node, name: &str) -> T {
+    child_text(node, name).parse().unwrap_or_default()
+}
+
+/// Parses a comma separated item list, "2500,-1,-1"
+fn parse_items(s: &str) -> Vec<i32> {
+    s.split(',')
+        .filter_map(|i| i.trim().parse::<i32>().ok())
+        .collect()
+}
+
+fn read_account(node: roxmltree::Node) -> AccountInfo {
+    let mut total_fame = 0;
+    if let Some(stats) = child(node, "Stats") {
+        total_fame = child_num(stats, "TotalFame");
+    }
+    let vault = match child(node, "Vault") {
+        Some(v) => v
+            .children()
+            .filter(|c| c.has_tag_name("Chest"))
+            .map(|c| parse_items(c.text().unwrap_or("")))
+            .collect(),
+        None => Vec::new(),
+    };
+    AccountInfo {
+        account_id: child_text(node, "AccountId"),
+        name: child_text(node, "Name"),
+        name_chosen: child(node, "NameChosen").is_some(),
+        credits: child_num(node, "Credits"),
+        fame: child_num(node, "Fame"),
+        total_fame,
+        vault,
+    }
+}
+
+/// Parses the /account/verify response
+pub fn parse_account(xml: &str) -> Result<AccountInfo, WebError> {
+    let doc = parse_document(xml)?;
+    let root = doc.root_element();
+    if !root.has_tag_name("Account") {
+        return Err(WebError::Parse(format!(
+            "expected <Account>, got <{}>",
+            root.tag_name().name()
+        )));
+    }
+    Ok(read_account(root))
+}
+
+/// Parses the /char/list response
+pub fn parse_char_list(xml: &str) -> Result<CharList, WebError> {
+    let doc = parse_document(xml)?;
+    let root = doc.root_element();
+    if !root.has_tag_name("Chars") {
+        return Err(WebError::Parse(format!(
+            "expected <Chars>, got <{}>",
+            root.tag_name().name()
+        )));
+    }
+    let attr = |name: &str| -> i32 {
+        root.attribute(name)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0)
+    };
+    let mut list = CharList {
+        next_char_id: attr("nextCharId"),
+        max_num_chars: attr("maxNumChars"),
+        ..CharList::default()
+    };
+    for node in root.children().filter(|c| c.is_element()) {
+        match node.tag_name().name() {
+            "Char" => list.chars.push(CharInfo {
+                id: node
+                    .attribute("id")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0),
+                class: child_num(node, "ObjectType"),
+                level: child_num(node, "Level"),
+                exp: child_num(node, "Exp"),
+                current_fame: child_num(node, "CurrentFame"),
+                equipment: parse_items(&child_text(node, "Equipment")),
+                dead: child_text(node, "Dead") == "True",
+            }),
+            "Account" => list.account = read_account(node),
+            "Servers" => list.servers = read_servers(node),
+            _ => (),
+        }
+    }
+    Ok(list)
+}
+
+fn read_servers(node: roxmltree::Node) -> Vec<ServerInfo> {
+    node.children()
+        .filter(|c| c.has_tag_name("Server"))
+        .map(|s| ServerInfo {
+            name: child_text(s, "Name"),
+            dns: child_text(s, "DNS"),
+            lat: child_num(s, "Lat"),
+            long: child_num(s, "Long"),
+            usage: child_num(s, "Usage"),
+            admin_only: child(s, "AdminOnly").is_some(),
+        })
+        .collect()
+}
+
+/// Parses a <Servers> document, or takes the server list out of a full /char/list response
+pub fn parse_servers(xml: &str) -> Result<Vec<ServerInfo>, WebError> {
+    let doc = parse_document(xml)?;
+    let root = doc.root_element();
+    if root.has_tag_name("Servers") {
+        return Ok(read_servers(root));
+    }
+    match child(root, "Servers") {
+        Some(node) => Ok(read_servers(node)),
+        None => Err(WebError::Parse(String::from(
+            "no <Servers> in the document",
+        ))),
+    }
+}
+
+/// Parses the /dailyLogin/fetchCalendar response, both calendars' days in one list
+pub fn parse_calendar(xml: &str) -> Result<Vec<CalendarDay>, WebError> {
+    let doc = parse_document(xml)?;
+    let root = doc.root_element();
+    if !root.has_tag_name("LoginRewards") {
+        return Err(WebError::Parse(format!(
+            "expected <LoginRewards>, got <{}>",
+            root.tag_name().name()
+        )));
+    }
+    let mut days = Vec::new();
+    for calendar in root
+        .children()
+        .filter(|c| c.has_tag_name("Consecutive") || c.has_tag_name("NonConsecutive"))
+    {
+        let claim_type = calendar.tag_name().name().to_ascii_lowercase();
+        for login in calendar.children().filter(|c| c.has_tag_name("Login")) {
+            let item = child(login, "ItemId");
+            days.push(CalendarDay {
+                claim_type: claim_type.clone(),
+                days: child_num(login, "Days"),
+                item_id: child_text(login, "ItemId").parse().unwrap_or(-1),
+                quantity: item
+                    .and_then(|i| i.attribute("quantity"))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1),
+                gold: child_num(login, "Gold"),
+                key: child_text(login, "key"),
+                claimed: child(login, "Claimed").is_some(),
+            });
+        }
+    }
+    Ok(days)
+}
@@ -1,6 +1,30 @@
 #[cfg(test)]
 mod tests {
+    use std::io::{Read, Write};
+    use std::net;
+    use std::sync::Arc;
+    use std::thread;
+
+    use realmlib::account::IAccount;
+    use realmlib::bootstrap;
+    use realmlib::client;
+    use realmlib::conditions;
+    use realmlib::config;
+    use realmlib::control;
+    use realmlib::daily;
+    use realmlib::logging;
+    use realmlib::manager;
+    use realmlib::metrics;
     use realmlib::network;
+    use realmlib::network::packets;
+    use realmlib::network::pool;
+    use realmlib::network::proxy;
+    use realmlib::pets;
+    use realmlib::servers;
+    use realmlib::snapshot;
+    use realmlib::tiles;
+    use realmlib::utils;
+    use realmlib::web;
 
     #[test]
     fn it_works() {
@@ -20,4 +44,2111 @@ mod tests {
         let y = network::types::StatData::new();
         assert!(y.is_string_stat() == false);
     }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        //delays should double per failed attempt and stop growing at the max delay
+        let mut r = client::ReconnectBase::new();
+        r.policy.base_delay_ms = 100;
+        r.policy.max_delay_ms = 1000;
+        r.policy.jitter = 0.0;
+        let mut delays = Vec::new();
+        for _ in 0..6 {
+            r.increment(client::ReconnectReason::Dropped);
+            delays.push(r.next_delay().as_millis() as u64);
+        }
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+        //requested reconnects never back off
+        r.reset();
+        r.increment(client::ReconnectReason::Requested);
+        r.increment(client::ReconnectReason::Requested);
+        assert_eq!(r.backoff_delay_ms(), 100);
+        assert!(!r.check());
+    }
+
+    #[test]
+    fn test_reconnect_limits() {
+        let mut r = client::ReconnectBase::new();
+        r.policy.max_failure_attempts = 3;
+        r.policy.jitter = 0.5;
+        for _ in 0..2 {
+            r.increment(client::ReconnectReason::Failure);
+            assert!(!r.check());
+            let d = r.next_delay().as_millis() as u64;
+            assert!(d <= r.policy.max_delay_ms);
+        }
+        //the limit is the number of attempts, the third one hits it
+        r.increment(client::ReconnectReason::Failure);
+        assert_eq!(r.attempts_for(client::ReconnectReason::Failure), 3);
+        assert!(r.check());
+        //cooling down only clears the reason that hit the limit
+        r.increment(client::ReconnectReason::Dropped);
+        r.increment(client::ReconnectReason::Failure);
+        assert!(r.check());
+        assert_eq!(r.cool_down().as_millis() as u64, r.policy.cooldown_ms);
+        assert!(!r.check());
+        assert_eq!(r.attempts_for(client::ReconnectReason::Dropped), 1);
+    }
+
+    #[test]
+    fn test_connect_errors() {
+        let timeouts = network::factory::Timeouts {
+            connect_ms: 1000,
+            read_ms: 1000,
+            write_ms: 1000,
+        };
+        //grab a free port, then close it so nothing is listening
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        match network::factory::begin_networking(
+            format!("127.0.0.1:{}", port),
+            None,
+            2,
+            timeouts,
+            32,
+        ) {
+            Err(network::factory::ConnectError::Connect(_)) => (),
+            other => panic!("expected a connect error, got {:?}", other.err()),
+        }
+        match network::factory::begin_networking(
+            String::from("not an address"),
+            None,
+            2,
+            timeouts,
+            32,
+        ) {
+            Err(network::factory::ConnectError::BadAddress(_)) => (),
+            other => panic!("expected a bad address error, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_stalled_packet_body() {
+        let timeouts = network::factory::Timeouts {
+            connect_ms: 1000,
+            read_ms: 200,
+            write_ms: 1000,
+        };
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (release, stalled) = std::sync::mpsc::channel::<()>();
+        //a server that sends a header for 20 bytes, then only 3 of them
+        let server = thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            let mut hello = [0u8; 9];
+            s.read_exact(&mut hello).unwrap();
+            s.write_all(&[0, 0, 0, 20, 1, 1, 2, 3]).unwrap();
+            stalled.recv().ok(); //hold the socket open until the client is done
+        });
+        let (tx, rx, handle) =
+            network::factory::begin_networking(addr.to_string(), None, 2, timeouts, 32).unwrap();
+        let mut hello = network::buffer::new();
+        hello.data = vec![0, 0, 0, 9, 1, 1, 2, 3, 4];
+        tx.send(hello).unwrap();
+        //the factory gives up on the connection without handing the half packet on
+        handle.join().unwrap();
+        assert!(rx.recv().is_err());
+        release.send(()).unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_proxy_parse() {
+        let p = proxy::Proxy::parse("socks5://user:p@ss@10.0.0.1:1080").unwrap();
+        assert_eq!(p.kind, proxy::ProxyKind::Socks5);
+        assert_eq!(p.address, "10.0.0.1:1080");
+        assert_eq!(p.username.as_deref(), Some("user"));
+        assert_eq!(p.password.as_deref(), Some("p@ss"));
+        assert_eq!(p.to_string(), "socks5://user@10.0.0.1:1080");
+        let p = proxy::Proxy::parse("socks4://bot@proxy.local:1080").unwrap();
+        assert_eq!(p.kind, proxy::ProxyKind::Socks4);
+        assert_eq!(p.password, None);
+        //bare addresses are still unauthenticated socks5, as in old account files
+        let p = proxy::Proxy::parse("127.0.0.1:1085").unwrap();
+        assert_eq!(p.kind, proxy::ProxyKind::Socks5);
+        assert_eq!(p.username, None);
+        assert!(proxy::Proxy::parse("ftp://127.0.0.1:21").is_err());
+        assert!(proxy::Proxy::parse("socks5://127.0.0.1").is_err());
+    }
+
+    /// A bare bones socks5 server that only speaks username/password auth. Returns what it was sent.
+    fn socks5_stand_in(listener: net::TcpListener) -> (String, String, String, u16, Vec<u8>) {
+        let (mut s, _) = listener.accept().unwrap();
+        let mut head = [0u8; 2];
+        s.read_exact(&mut head).unwrap();
+        let mut methods = vec![0u8; head[1] as usize];
+        s.read_exact(&mut methods).unwrap();
+        assert!(methods.contains(&2));
+        s.write_all(&[5, 2]).unwrap();
+        let read_field = |s: &mut net::TcpStream| {
+            let mut len = [0u8; 1];
+            s.read_exact(&mut len).unwrap();
+            let mut field = vec![0u8; len[0] as usize];
+            s.read_exact(&mut field).unwrap();
+            String::from_utf8(field).unwrap()
+        };
+        let mut ver = [0u8; 1];
+        s.read_exact(&mut ver).unwrap();
+        let user = read_field(&mut s);
+        let pass = read_field(&mut s);
+        s.write_all(&[1, 0]).unwrap();
+        let mut req = [0u8; 4];
+        s.read_exact(&mut req).unwrap();
+        assert_eq!(req, [5, 1, 0, 3]); //connect, domain target
+        let domain = read_field(&mut s);
+        let mut port = [0u8; 2];
+        s.read_exact(&mut port).unwrap();
+        s.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).unwrap();
+        let mut hello = vec![0u8; 9];
+        s.read_exact(&mut hello).unwrap();
+        s.write_all(&[255, 0, 0, 0, 0]).unwrap(); //kill byte, shuts the factory down
+        (user, pass, domain, u16::from_be_bytes(port), hello)
+    }
+
+    #[test]
+    fn test_socks5_auth_and_domain() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || socks5_stand_in(listener));
+        let p = proxy::Proxy::parse(&format!("socks5://bot:hunter2@{}", addr)).unwrap();
+        let timeouts = network::factory::Timeouts {
+            connect_ms: 1000,
+            read_ms: 5000,
+            write_ms: 5000,
+        };
+        let (tx, _rx, handle) = network::factory::begin_networking(
+            String::from("usw.realm.local:2050"),
+            Some(p),
+            2,
+            timeouts,
+            32,
+        )
+        .unwrap();
+        let mut hello = network::buffer::new();
+        hello.data = vec![0, 0, 0, 9, 1, 1, 2, 3, 4];
+        tx.send(hello).unwrap();
+        let (user, pass, domain, port, sent) = server.join().unwrap();
+        assert_eq!(user, "bot");
+        assert_eq!(pass, "hunter2");
+        assert_eq!(domain, "usw.realm.local");
+        assert_eq!(port, 2050);
+        assert_eq!(sent, vec![0, 0, 0, 9, 1, 1, 2, 3, 4]);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_socks4a_domain() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            let mut head = [0u8; 8];
+            s.read_exact(&mut head).unwrap();
+            let mut read_str = || {
+                let mut field = Vec::new();
+                let mut b = [0u8; 1];
+                while s.read_exact(&mut b).is_ok() && b[0] != 0 {
+                    field.push(b[0]);
+                }
+                String::from_utf8(field).unwrap()
+            };
+            let (userid, domain) = (read_str(), read_str());
+            s.write_all(&[0, 90, 0, 0, 0, 0, 0, 0]).unwrap();
+            let mut hello = vec![0u8; 9];
+            s.read_exact(&mut hello).unwrap();
+            s.write_all(&[255, 0, 0, 0, 0]).unwrap();
+            (head, userid, domain, hello)
+        });
+        let p = proxy::Proxy::parse(&format!("socks4a://bot@{}", addr)).unwrap();
+        let timeouts = network::factory::Timeouts {
+            connect_ms: 1000,
+            read_ms: 5000,
+            write_ms: 5000,
+        };
+        let (tx, _rx, handle) = network::factory::begin_networking(
+            String::from("usw.realm.local:2050"),
+            Some(p),
+            2,
+            timeouts,
+            32,
+        )
+        .unwrap();
+        let mut hello = network::buffer::new();
+        hello.data = vec![0, 0, 0, 9, 1, 1, 2, 3, 4];
+        tx.send(hello).unwrap();
+        let (head, userid, domain, sent) = server.join().unwrap();
+        //connect, port 2050, and the 0.0.0.x address that asks the proxy to resolve the name
+        assert_eq!(head, [4, 1, 8, 2, 0, 0, 0, 1]);
+        assert_eq!(userid, "bot");
+        assert_eq!(domain, "usw.realm.local");
+        assert_eq!(sent, vec![0, 0, 0, 9, 1, 1, 2, 3, 4]);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_socks_proxy_timeout() {
+        let timeouts = network::factory::Timeouts {
+            connect_ms: 1000,
+            read_ms: 300,
+            write_ms: 300,
+        };
+        //a proxy that takes the connection and never answers the handshake
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        for url in ["socks5://{}", "socks5://bot:hunter2@{}", "socks4://{}"] {
+            let p = proxy::Proxy::parse(&url.replace("{}", &addr.to_string())).unwrap();
+            let started = std::time::Instant::now();
+            match network::factory::begin_networking(
+                String::from("18.218.255.91:2050"),
+                Some(p),
+                2,
+                timeouts,
+                32,
+            ) {
+                Err(network::factory::ConnectError::Proxy(_)) => (),
+                other => panic!("expected a proxy error, got {:?}", other.err()),
+            }
+            assert!(started.elapsed() < std::time::Duration::from_secs(3));
+        }
+        drop(listener);
+    }
+
+    /// Answers a single HTTP CONNECT with the given status line and returns the request headers it got
+    fn http_proxy_stand_in(listener: net::TcpListener, status: &str) -> (String, Vec<u8>) {
+        let (mut s, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut byte = [0u8; 1];
+        while !request.ends_with(b"\r\n\r\n") {
+            s.read_exact(&mut byte).unwrap();
+            request.push(byte[0]);
+        }
+        s.write_all(format!("{}\r\n\r\n", status).as_bytes())
+            .unwrap();
+        let mut hello = Vec::new();
+        if status.contains(" 200 ") {
+            hello = vec![0u8; 9];
+            s.read_exact(&mut hello).unwrap();
+            s.write_all(&[255, 0, 0, 0, 0]).unwrap();
+        }
+        (String::from_utf8(request).unwrap(), hello)
+    }
+
+    #[test]
+    fn test_http_connect_proxy() {
+        let timeouts = network::factory::Timeouts {
+            connect_ms: 1000,
+            read_ms: 5000,
+            write_ms: 5000,
+        };
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            http_proxy_stand_in(listener, "HTTP/1.1 200 Connection established")
+        });
+        let p = proxy::Proxy::parse(&format!("http://bot:hunter2@{}", addr)).unwrap();
+        assert_eq!(p.kind, proxy::ProxyKind::Http);
+        let (tx, _rx, handle) = network::factory::begin_networking(
+            String::from("18.218.255.91:2050"),
+            Some(p),
+            2,
+            timeouts,
+            32,
+        )
+        .unwrap();
+        let mut hello = network::buffer::new();
+        hello.data = vec![0, 0, 0, 9, 1, 1, 2, 3, 4];
+        tx.send(hello).unwrap();
+        let (request, sent) = server.join().unwrap();
+        assert!(request.starts_with("CONNECT 18.218.255.91:2050 HTTP/1.1\r\n"));
+        //base64 of bot:hunter2
+        assert!(request.contains("Proxy-Authorization: Basic Ym90Omh1bnRlcjI=\r\n"));
+        assert_eq!(sent, vec![0, 0, 0, 9, 1, 1, 2, 3, 4]);
+        handle.join().unwrap();
+
+        //a proxy that wants credentials we don't have
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            http_proxy_stand_in(listener, "HTTP/1.1 407 Proxy Authentication Required")
+        });
+        let p = proxy::Proxy::parse_or(&addr.to_string(), proxy::ProxyKind::Http).unwrap();
+        match network::factory::begin_networking(
+            String::from("18.218.255.91:2050"),
+            Some(p),
+            2,
+            timeouts,
+            32,
+        ) {
+            Err(network::factory::ConnectError::Proxy(_)) => (),
+            other => panic!("expected a proxy error, got {:?}", other.err()),
+        }
+        assert!(!server.join().unwrap().0.contains("Proxy-Authorization"));
+    }
+
+    fn test_pool(strategy: pool::PoolStrategy) -> pool::ProxyPool {
+        let proxies = vec!["10.0.0.1:1080", "10.0.0.2:1080", "10.0.0.3:1080"]
+            .into_iter()
+            .map(|p| proxy::Proxy::parse(p).unwrap())
+            .collect();
+        let mut settings = pool::PoolSettings::default();
+        settings.strategy = strategy;
+        settings.max_clients_per_proxy = 2;
+        settings.max_failures = 2;
+        settings.revive_after_secs = 0;
+        pool::ProxyPool::new(proxies, settings)
+    }
+
+    #[test]
+    fn test_proxy_pool() {
+        let pool = test_pool(pool::PoolStrategy::RoundRobin);
+        let picks: Vec<String> = (0..6)
+            .map(|_| pool.acquire(None).unwrap().address)
+            .collect();
+        assert_eq!(
+            picks,
+            vec![
+                "10.0.0.1:1080",
+                "10.0.0.2:1080",
+                "10.0.0.3:1080",
+                "10.0.0.1:1080",
+                "10.0.0.2:1080",
+                "10.0.0.3:1080"
+            ]
+        );
+        //every proxy is at its limit
+        assert!(pool.acquire(None).is_none());
+
+        let pool = test_pool(pool::PoolStrategy::LeastUsed);
+        let first = pool.acquire(None).unwrap();
+        //two failures in a row kill the proxy, and rotating moves the client off it
+        assert!(!pool.report_failure(&first));
+        assert!(pool.report_failure(&first));
+        assert!(pool.is_dead(&first));
+        assert_eq!(pool.healthy_count(), 2);
+        let second = pool.rotate(Some(&first)).unwrap();
+        assert_ne!(second, first);
+        let third = pool.acquire(None).unwrap();
+        assert_ne!(third, second); //least used picks the idle proxy
+        assert_ne!(third, first);
+        pool.report_success(&first);
+        assert!(!pool.is_dead(&first));
+    }
+
+    const CHAR_LIST_XML: &str = r#"<Chars nextCharId="4" maxNumChars="2">
+<Char id="2"><ObjectType>782</ObjectType><Level>20</Level><Exp>84500</Exp><CurrentFame>152</CurrentFame><Equipment>2711,2726,2725,2767,-1,-1,2594,-1</Equipment><Dead>False</Dead></Char>
+<Char id="3"><ObjectType>775</ObjectType><Level>1</Level><Exp>0</Exp><CurrentFame>0</CurrentFame><Equipment>2594,-1,-1,-1</Equipment><Dead>False</Dead></Char>
+<Account><AccountId>1234</AccountId><Name>Botty</Name><NameChosen/><Credits>50</Credits><Fame>310</Fame>
+<Vault><Chest>2594,-1,-1,-1,-1,-1,-1,-1</Chest><Chest>-1,-1,-1,-1,-1,-1,-1,2711</Chest></Vault>
+<Stats><TotalFame>4100</TotalFame></Stats></Account>
+<Servers>
+<Server><Name>USWest</Name><DNS>54.86.47.176</DNS><Lat>37.77</Lat><Long>-122.42</Long><Usage>0.53</Usage></Server>
+<Server><Name>EUNorth</Name><DNS>18.195.167.79</DNS><Lat>59.33</Lat><Long>18.06</Long><Usage>0.2</Usage></Server>
+</Servers>
+</Chars>"#;
+
+    /// Answers `count` web requests, replying to each path with the matching body. Returns the request bodies
+    fn web_stand_in(
+        listener: net::TcpListener,
+        count: usize,
+        replies: Vec<(&'static str, &'static str)>,
+    ) -> Vec<String> {
+        let mut bodies = Vec::new();
+        for _ in 0..count {
+            let (mut s, _) = listener.accept().unwrap();
+            let mut head = Vec::new();
+            let mut byte = [0u8; 1];
+            while !head.ends_with(b"\r\n\r\n") {
+                s.read_exact(&mut byte).unwrap();
+                head.push(byte[0]);
+            }
+            let head = String::from_utf8(head).unwrap();
+            let len: usize = head
+                .lines()
+                .find(|l| l.to_ascii_lowercase().starts_with("content-length:"))
+                .map(|l| l[15..].trim().parse().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0u8; len];
+            s.read_exact(&mut body).unwrap();
+            bodies.push(String::from_utf8(body).unwrap());
+            let path = head.split_whitespace().nth(1).unwrap().to_owned();
+            let reply = replies.iter().find(|(p, _)| *p == path).map(|(_, r)| *r);
+            match reply {
+                //send the char list chunked to exercise the decoder
+                Some(r) => {
+                    let (a, b) = r.split_at(r.len() / 2);
+                    write!(
+                        s,
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                        a.len(),
+                        a,
+                        b.len(),
+                        b
+                    )
+                    .unwrap();
+                }
+                None => s
+                    .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                    .unwrap(),
+            }
+        }
+        bodies
+    }
+
+    fn test_config(api_base_url: &str) -> realmlib::Config {
+        serde_json::from_value(serde_json::json!({
+            "amount": 1,
+            "index": 0,
+            "conn_limit": 1,
+            "game_version": "X31.2.3",
+            "thread_delay_ms": 100,
+            "factory_delay_ms": 10,
+            "factory_stack_size_kb": 64,
+            "client_thread_stack_size_kb": 512,
+            "save_delay_secs": 60,
+            "connect_timeout_ms": 2000,
+            "api_base_url": api_base_url
+        }))
+        .unwrap()
+    }
+
+    fn test_account(server_ip: &str) -> realmlib::account::Account {
+        realmlib::account::Account {
+            email: String::from("bot@example.com"),
+            password: String::from("p&ss word"),
+            server_ip: String::from(server_ip),
+            fetch_new_data: true,
+            char_id: 0,
+            module: String::new(),
+            use_socks: false,
+            socks_proxy: String::new(),
+            use_http: false,
+            http_proxy: String::new(),
+            server: String::new(),
+            rotate_servers: false,
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_web_parse() {
+        let list = web::parse_char_list(CHAR_LIST_XML).unwrap();
+        assert_eq!(list.next_char_id, 4);
+        assert_eq!(list.max_num_chars, 2);
+        assert_eq!(list.chars.len(), 2);
+        assert_eq!(list.chars[0].id, 2);
+        assert_eq!(list.chars[0].class, 782);
+        assert_eq!(list.chars[0].current_fame, 152);
+        assert_eq!(list.chars[0].equipment[..4], [2711, 2726, 2725, 2767]);
+        assert_eq!(list.account.name, "Botty");
+        assert!(list.account.name_chosen);
+        assert_eq!(list.account.fame, 310);
+        assert_eq!(list.account.total_fame, 4100);
+        assert_eq!(list.account.vault.len(), 2);
+        assert_eq!(list.account.vault[1][7], 2711);
+        assert_eq!(list.servers[1].name, "EUNorth");
+        assert_eq!(list.servers[1].dns, "18.195.167.79");
+
+        match web::parse_char_list("<Error>WebChangePasswordDialog.passwordError</Error>") {
+            Err(web::WebError::Api(e)) => assert_eq!(e, "WebChangePasswordDialog.passwordError"),
+            other => panic!("expected an api error, got {:?}", other),
+        }
+        assert_eq!(
+            web::api_url("http://127.0.0.1:8080/", "/char/list"),
+            "http://127.0.0.1:8080/char/list"
+        );
+    }
+
+    #[test]
+    fn test_web_fetch() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            web_stand_in(
+                listener,
+                4,
+                vec![
+                    ("/char/list", CHAR_LIST_XML),
+                    ("/account/verify", "<Account><AccountId>1234</AccountId><Name>Botty</Name><Credits>50</Credits></Account>"),
+                ],
+            )
+        });
+        let timeout = std::time::Duration::from_secs(2);
+        let info = web::verify_account(&base, "bot@example.com", "p&ss word", timeout).unwrap();
+        assert_eq!(info.account_id, "1234");
+        assert_eq!(info.credits, 50);
+        assert!(!info.name_chosen);
+
+        //a server name gets swapped for its address
+        let mut client = test_account("eunorth").create_client(test_config(&base));
+        client.get_new_char_id().unwrap();
+        assert_eq!(client.base.char_id, 2);
+        assert_eq!(client.base.server_ip, "18.195.167.79");
+        assert_eq!(client.account_data.as_ref().unwrap().chars.len(), 2);
+        assert_eq!(client.get_url("char/list"), format!("{}/char/list", base));
+
+        //an empty server_ip falls back to the least populated server
+        let mut client = test_account("").create_client(test_config(&base));
+        client.get_new_char_id().unwrap();
+        assert_eq!(client.base.server_ip, "18.195.167.79");
+
+        //a bad base url surfaces the http status
+        match web::fetch_char_list(&format!("{}/missing", base), "a", "b", timeout) {
+            Err(web::WebError::Http(404)) => (),
+            other => panic!("expected a 404, got {:?}", other),
+        }
+        let bodies = server.join().unwrap();
+        assert_eq!(bodies[0], "guid=bot%40example.com&password=p%26ss%20word");
+    }
+
+    #[test]
+    fn test_server_list() {
+        let list = servers::ServerList::from_xml(CHAR_LIST_XML).unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.resolve("uswest"), "54.86.47.176");
+        assert_eq!(list.resolve("10.1.2.3"), "10.1.2.3"); //not a name, used as is
+        assert_eq!(list.select("least_populated").unwrap().name, "EUNorth");
+        assert_eq!(
+            list.least_populated(&["18.195.167.79"]).unwrap().name,
+            "USWest"
+        );
+        assert_eq!(list.next_after("54.86.47.176").unwrap().name, "EUNorth");
+        assert_eq!(list.next_after("EUNorth").unwrap().name, "USWest");
+
+        let list = servers::ServerList::from_json(
+            r#"[{"name": "USEast", "dns": "52.23.232.42", "usage": 0.9},
+                {"name": "USEast2", "dns": "3.88.196.105", "usage": 0.1, "admin_only": true}]"#,
+        )
+        .unwrap();
+        //admin only servers are never picked
+        assert_eq!(list.select("").unwrap().name, "USEast");
+        let list = servers::ServerList::from_json(r#"{"Australia": "13.236.87.251"}"#).unwrap();
+        assert_eq!(list.find("australia").unwrap().dns, "13.236.87.251");
+
+        //an account naming a server gets its host, and connect failures rotate through the list
+        let mut client =
+            test_account("18.218.255.91").create_client(test_config("http://127.0.0.1:1"));
+        client.base.server = String::from("USWest");
+        client.servers = Some(std::sync::Arc::new(
+            servers::ServerList::from_xml(CHAR_LIST_XML).unwrap(),
+        ));
+        client.select_server();
+        assert_eq!(client.base.server_ip, "54.86.47.176");
+        client.recon.current_server = client.base.server_ip.clone();
+        client.rotate_server();
+        assert_eq!(client.recon.current_server, "18.195.167.79");
+        assert_eq!(client.recon.previous_server, "54.86.47.176");
+    }
+
+    /// Writes 'text' to a file in the temp dir that is unique to this test run
+    fn temp_file(name: &str, text: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("realmlib-{}-{}", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    const SETTINGS_TOML: &str = r#"
+amount = 2
+index = 1
+conn_limit = 100
+game_version = "X32.4.1"
+thread_delay_ms = 1000
+factory_delay_ms = 2
+factory_stack_size_kb = 32
+client_thread_stack_size_kb = 64
+save_delay_secs = 180
+
+[reconnect]
+base_delay_ms = 250
+"#;
+
+    #[test]
+    fn test_config_formats() {
+        //the shipped files have to stay valid
+        let c = realmlib::Config::load_with_env("../config/settings.json", Vec::new()).unwrap();
+        let accounts = config::load_accounts("../config/accounts.json").unwrap();
+        assert!(c.validate_accounts(&accounts).is_ok());
+
+        let c =
+            realmlib::Config::load_with_env(temp_file("settings.toml", SETTINGS_TOML), Vec::new())
+                .unwrap();
+        assert_eq!(c.amount, 2);
+        assert_eq!(c.reconnect.base_delay_ms, 250);
+        assert_eq!(c.reconnect.max_delay_ms, 60_000); //missing nested fields keep their defaults
+        let yaml = "amount: 1\nindex: 0\nconn_limit: 5\ngame_version: X32.4.1\nthread_delay_ms: 10\n\
+                    factory_delay_ms: 2\nfactory_stack_size_kb: 32\nclient_thread_stack_size_kb: 64\n\
+                    save_delay_secs: 0\nserver_list_file: servers.xml\n";
+        let c =
+            realmlib::Config::load_with_env(temp_file("settings.yml", yaml), Vec::new()).unwrap();
+        assert_eq!(c.server_list_file, "servers.xml");
+
+        let toml_accounts = "[[accounts]]\nemail = \"a@b.c\"\npassword = \"pw\"\nserver_ip = \"\"\n\
+                             fetch_new_data = true\nchar_id = 0\nmodule = \"nil\"\nuse_socks = false\n\
+                             socks_proxy = \"\"\nuse_http = false\nhttp_proxy = \"\"\nserver = \"USWest\"\n";
+        let accounts = config::load_accounts(temp_file("accounts.toml", toml_accounts)).unwrap();
+        assert_eq!(accounts[0].server, "USWest");
+
+        match realmlib::Config::load_with_env(
+            temp_file("broken.json", "{\"amount\": 1,"),
+            Vec::new(),
+        ) {
+            Err(config::ConfigError::Parse(..)) => (),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        match realmlib::Config::load_with_env(
+            std::env::temp_dir().join("realmlib-missing.json"),
+            Vec::new(),
+        ) {
+            Err(config::ConfigError::Io(..)) => (),
+            other => panic!("expected an io error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_config_field_errors() {
+        let fields = |text: &str, name: &str| -> Vec<String> {
+            let e = realmlib::Config::load_with_env(temp_file(name, text), Vec::new()).unwrap_err();
+            e.fields().iter().map(|f| f.field.clone()).collect()
+        };
+        let bad = SETTINGS_TOML
+            .replace("amount = 2", "amount = 0")
+            .replace(
+                "client_thread_stack_size_kb = 64",
+                "client_thread_stack_size_kb = 1",
+            )
+            .replace("base_delay_ms = 250", "base_delay_ms = 250\njitter = 3.0");
+        assert_eq!(
+            fields(&bad, "range.toml"),
+            vec![
+                "amount",
+                "index",
+                "client_thread_stack_size_kb",
+                "reconnect.jitter"
+            ]
+        );
+        let wrong_type = SETTINGS_TOML.replace("amount = 2", "amount = \"two\"");
+        assert_eq!(fields(&wrong_type, "type.toml"), vec!["amount"]);
+        let missing = SETTINGS_TOML.replace("game_version = \"X32.4.1\"", "");
+        assert_eq!(fields(&missing, "missing.toml"), vec!["game_version"]);
+
+        let e = config::load_accounts(temp_file(
+            "accounts.json",
+            r#"[{"email": "", "password": "pw", "server_ip": "", "fetch_new_data": false, "char_id": 1,
+                "module": "nil", "use_socks": true, "socks_proxy": "ftp://nope", "use_http": false, "http_proxy": ""}]"#,
+        ))
+        .unwrap_err();
+        let names: Vec<&str> = e.fields().iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(names, vec!["[0].email", "[0].socks_proxy"]);
+    }
+
+    #[test]
+    fn test_config_env_overrides() {
+        let path = temp_file("env.toml", SETTINGS_TOML);
+        let env = |vars: &[(&str, &str)]| -> Vec<(String, String)> {
+            vars.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let c = realmlib::Config::load_with_env(
+            &path,
+            env(&[
+                ("REALMLIB_AMOUNT", "5"),
+                ("REALMLIB_GAME_VERSION", "X33.0.0"),
+                ("REALMLIB_RECONNECT__GIVE_UP", "true"),
+                ("REALMLIB_PROXY_POOL__STRATEGY", "least_used"),
+                ("PATH", "/usr/bin"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(c.amount, 5);
+        assert_eq!(c.game_version, "X33.0.0");
+        assert!(c.reconnect.give_up);
+        assert_eq!(c.proxy_pool.strategy, pool::PoolStrategy::LeastUsed);
+
+        let e = realmlib::Config::load_with_env(
+            &path,
+            env(&[("REALMLIB_AMOUNT", "lots"), ("REALMLIB_NOT_A_SETTING", "1")]),
+        )
+        .unwrap_err();
+        let names: Vec<&str> = e.fields().iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(names, vec!["amount", "not_a_setting"]);
+        //overrides are validated like the file
+        let e =
+            realmlib::Config::load_with_env(&path, env(&[("REALMLIB_INDEX", "2")])).unwrap_err();
+        assert_eq!(e.fields()[0].field, "index");
+    }
+
+    #[test]
+    fn test_select_accounts() {
+        let mut accounts: Vec<realmlib::account::Account> = (0..6)
+            .map(|i| {
+                let mut a = test_account("");
+                a.email = format!("bot{}@example.com", i);
+                a
+            })
+            .collect();
+        accounts[1].tags = vec![String::from("farm")];
+        accounts[4].tags = vec![String::from("Farm"), String::from("mule")];
+        let select = |s: &[&str]| {
+            let sel: Vec<String> = s.iter().map(|x| x.to_string()).collect();
+            realmlib::account::select_accounts(&accounts, &sel)
+        };
+        assert_eq!(select(&["3"]).unwrap(), vec![3]);
+        assert_eq!(select(&["2-4", "3"]).unwrap(), vec![2, 3, 4]);
+        assert_eq!(select(&["tag:farm"]).unwrap(), vec![1, 4]);
+        assert_eq!(select(&["BOT5@example.com", "0"]).unwrap(), vec![5, 0]);
+        assert!(select(&["6"]).is_err());
+        assert!(select(&["4-9"]).is_err());
+        assert!(select(&["tag:nope"]).is_err());
+        assert!(select(&["nobody@example.com"]).is_err());
+
+        assert_eq!(
+            config::override_var("reconnect.jitter", "0.1"),
+            (
+                String::from("REALMLIB_RECONNECT__JITTER"),
+                String::from("0.1")
+            )
+        );
+    }
+
+    #[test]
+    fn test_decode_dump() {
+        assert_eq!(
+            utils::decode_hex("0x00 0a:FF,10\n7f").unwrap(),
+            vec![0, 0x0a, 0xff, 0x10, 0x7f]
+        );
+        assert!(utils::decode_hex("abc").is_err());
+        assert!(utils::decode_hex("zz").is_err());
+
+        //a ping followed by a reconnect with an empty host, as the server would send them
+        let mut dump = vec![0, 0, 0, 9, packets::PING, 0, 0, 0, 42];
+        let mut reconnect = network::buffer::new();
+        reconnect.write_string(&String::from("Nexus"));
+        reconnect.write_string(&String::new());
+        reconnect.write_string(&String::new());
+        reconnect.write_i32(2050);
+        reconnect.write_i32(-2);
+        reconnect.write_i32(0);
+        reconnect.write_bool(false);
+        reconnect.write_i16(0);
+        dump.extend(reconnect.finalize(packets::RECONNECT).data);
+        let mut split = packets::split_packets(&dump).unwrap();
+        assert_eq!(split.len(), 2);
+        assert_eq!(packets::packet_name(split[1].data[4]), Some("RECONNECT"));
+        assert_eq!(packets::packet_name(2), None);
+        let ping = packets::describe_server_packet(split[0].clone())
+            .unwrap()
+            .unwrap();
+        assert!(ping.contains("serial: 42"));
+        let text = packets::describe_server_packet(split[1].clone())
+            .unwrap()
+            .unwrap();
+        assert!(text.contains("\"Nexus\""));
+
+        //encrypting with the same key stream and decrypting again gives the plain packets back
+        let plain = split.clone();
+        packets::decrypt_packets(&mut split, true);
+        assert_ne!(split[1].data, plain[1].data);
+        packets::decrypt_packets(&mut split, true);
+        assert_eq!(split[1].data, plain[1].data);
+
+        //truncated packets are reported instead of panicking
+        let short = packets::split_packets(&[0, 0, 0, 6, packets::PING, 0]).unwrap();
+        assert!(packets::describe_server_packet(short[0].clone())
+            .unwrap()
+            .is_err());
+        assert!(packets::split_packets(&[0, 0, 0, 20, packets::PING]).is_err());
+    }
+
+    /// A client that can never connect, it keeps reconnecting to a closed local port
+    fn unreachable_client(give_up: bool) -> client::Client {
+        let mut c = test_config("http://127.0.0.1:1");
+        c.reconnect.base_delay_ms = 1;
+        c.reconnect.jitter = 0.0;
+        c.reconnect.max_connect_attempts = 1;
+        c.reconnect.give_up = give_up;
+        c.connect_timeout_ms = 200;
+        let mut a = test_account("127.0.0.1");
+        a.fetch_new_data = false;
+        a.create_client(c)
+    }
+
+    fn wait_for_state(bots: &manager::BotManager, id: usize, state: manager::BotState) -> bool {
+        for _ in 0..100 {
+            if bots.state(id) == Some(state) {
+                return true;
+            }
+            thread::sleep(std::time::Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    fn test_bot_manager() {
+        let mut settings = manager::SupervisorSettings::default();
+        settings.restart = manager::RestartPolicy::Always;
+        settings.max_restarts = 1;
+        settings.restart_delay_ms = 0;
+        let mut bots = manager::BotManager::new(settings);
+        let looping = bots.add(unreachable_client(false));
+        assert_eq!(bots.state(looping), Some(manager::BotState::Idle));
+        assert_eq!(bots.find("BOT@example.com"), Some(looping));
+        bots.start_all().unwrap();
+        assert_eq!(bots.state(looping), Some(manager::BotState::Running));
+
+        //stopping interrupts the reconnect cooldown and hands the client back
+        thread::sleep(std::time::Duration::from_millis(100));
+        let asked = std::time::Instant::now();
+        assert!(bots.stop(looping));
+        assert!(asked.elapsed() < std::time::Duration::from_secs(2));
+        assert_eq!(bots.state(looping), Some(manager::BotState::Stopped));
+        assert!(!bots.stop(looping));
+        bots.restart(looping).unwrap();
+        assert_eq!(bots.state(looping), Some(manager::BotState::Running));
+
+        //a client that gives up returns on its own, gets restarted once and then runs out of restarts
+        let quitter = bots.add(unreachable_client(true));
+        bots.relaunch(quitter).unwrap();
+        let mut restarted = false;
+        for _ in 0..200 {
+            bots.supervise();
+            if bots.status()[quitter].restarts == 1 {
+                restarted = true;
+            }
+            if bots.state(quitter) == Some(manager::BotState::Failed) {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert!(restarted);
+        assert_eq!(bots.state(quitter), Some(manager::BotState::Failed));
+
+        bots.shutdown();
+        assert!(wait_for_state(&bots, looping, manager::BotState::Stopped));
+        let status = bots.status();
+        assert_eq!(status.len(), 2);
+        assert_eq!(status[looping].restarts, 0);
+    }
+
+    fn control_request(addr: net::SocketAddr, request: &str) -> (u16, serde_json::Value) {
+        let mut s = net::TcpStream::connect(addr).unwrap();
+        s.write_all(request.as_bytes()).unwrap();
+        let mut raw = String::new();
+        s.read_to_string(&mut raw).unwrap();
+        let status = raw.split_whitespace().nth(1).unwrap().parse().unwrap();
+        let body = &raw[raw.find("\r\n\r\n").unwrap() + 4..];
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_control_unix_socket() {
+        let path =
+            std::env::temp_dir().join(format!("realmlib-{}-control.sock", std::process::id()));
+        let bind = format!("unix:{}", path.display());
+        let api = control::Api {
+            targets: Vec::new(),
+            metrics: Arc::new(metrics::Metrics::new()),
+        };
+        //a mistyped path to some other file is left alone
+        std::fs::write(&path, "not a socket").unwrap();
+        match control::ControlServer::start(&bind, api.clone()) {
+            Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::AlreadyExists),
+            Ok(_) => panic!("bound over a regular file"),
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+        std::fs::remove_file(&path).unwrap();
+
+        //a socket left behind by a previous run is replaced
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let mut server = control::ControlServer::start(&bind, api).unwrap();
+        let mut s = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        s.write_all(b"GET /bots HTTP/1.1\r\n\r\n").unwrap();
+        let mut raw = String::new();
+        s.read_to_string(&mut raw).unwrap();
+        assert!(raw.starts_with("HTTP/1.1 200"));
+        server.stop();
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_control() {
+        assert!(control::Bind::parse("127.0.0.1:7700").is_ok());
+        assert!(control::Bind::parse("0.0.0.0:7700").is_err());
+        assert!(control::Bind::parse("unix:").is_err());
+
+        //the manager's mailboxes are what the server routes commands through
+        let mut bots = manager::BotManager::new(manager::SupervisorSettings::default());
+        let id = bots.add(unreachable_client(false));
+        let mut c = unreachable_client(false);
+        c.base.email = String::from("other@example.com");
+        let other = bots.add(c);
+        let targets = bots.control_targets();
+        assert_eq!(targets.len(), 2);
+        let api = control::Api {
+            targets: targets.clone(),
+            metrics: Arc::new(metrics::Metrics::new()),
+        };
+
+        let r = control::handle_request(&api, "POST", "/bots/0/move_to", r#"{"x":12.5,"y":3}"#);
+        assert_eq!(r.status, 202);
+        let r = control::handle_request(
+            &api,
+            "POST",
+            "/bots/OTHER@example.com/say",
+            r#"{"text":"hi"}"#,
+        );
+        assert_eq!(r.status, 202);
+        let r = control::handle_request(&api, "POST", "/bots/all/reconnect", "");
+        assert_eq!(r.status, 202);
+        assert_eq!(
+            control::handle_request(&api, "POST", "/bots/0/dance", "").status,
+            400
+        );
+        assert_eq!(
+            control::handle_request(&api, "POST", "/bots/0/say", "{}").status,
+            400
+        );
+        assert_eq!(
+            control::handle_request(&api, "POST", "/bots/7/nexus", "").status,
+            404
+        );
+        assert_eq!(
+            control::handle_request(&api, "DELETE", "/bots/0", "").status,
+            405
+        );
+        assert_eq!(
+            targets[id].mailbox.take(),
+            vec![
+                control::ClientCommand::MoveTo { x: 12.5, y: 3.0 },
+                control::ClientCommand::Reconnect
+            ]
+        );
+        assert_eq!(
+            targets[other].mailbox.take(),
+            vec![
+                control::ClientCommand::Say {
+                    text: String::from("hi")
+                },
+                control::ClientCommand::Reconnect
+            ]
+        );
+
+        //commands run on the client, which publishes its state back
+        let mut c = unreachable_client(false);
+        c.mailbox = targets[id].mailbox.clone();
+        c.mailbox
+            .push(control::ClientCommand::MoveTo { x: 4.0, y: 5.0 });
+        c.current_map = String::from("Nexus");
+        c.handle_commands();
+        assert_eq!(c.movement.target_position.x, 4.0);
+        assert_eq!(c.movement.target_position.y, 5.0);
+        c.publish_state();
+        let state = targets[id].mailbox.state();
+        assert_eq!(state.map, "Nexus");
+        assert!(!state.connected);
+        assert_eq!(state.inventory, vec![-1; 12]);
+        //the game loop only publishes with the control server on, and not on every packet
+        c.current_map = String::from("Realm");
+        c.publish_state_if_due();
+        assert_eq!(c.mailbox.state().map, "Nexus");
+        c.config.control.enabled = true;
+        c.publish_state_if_due();
+        assert_eq!(c.mailbox.state().map, "Realm");
+        c.current_map = String::from("Nexus");
+        c.publish_state_if_due();
+        assert_eq!(c.mailbox.state().map, "Realm");
+        c.last_publish = Some(std::time::Instant::now() - control::PUBLISH_INTERVAL);
+        c.publish_state_if_due();
+        assert_eq!(c.mailbox.state().map, "Nexus");
+
+        let mut server = control::ControlServer::start("127.0.0.1:0", api.clone()).unwrap();
+        let addr = server.local_addr().unwrap();
+        //a client that connects and says nothing doesn't hold up the others
+        let idle = net::TcpStream::connect(addr).unwrap();
+        let asked = std::time::Instant::now();
+        let (status, _) = control_request(addr, "GET /bots HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 200);
+        assert!(asked.elapsed() < std::time::Duration::from_secs(2));
+        drop(idle);
+        let (status, body) = control_request(addr, "GET /bots HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 2);
+        assert_eq!(body[0]["map"], "Nexus");
+        assert_eq!(body[0]["bot"], "idle");
+        assert_eq!(body[1]["email"], "other@example.com");
+        let (status, body) = control_request(
+            addr,
+            "POST /bots/1/trade HTTP/1.1\r\nContent-Length: 16\r\n\r\n{\"name\":\"Alice\"}",
+        );
+        assert_eq!(status, 202);
+        assert_eq!(body["queued"], 1);
+        assert_eq!(
+            targets[other].mailbox.take(),
+            vec![control::ClientCommand::Trade {
+                name: String::from("Alice")
+            }]
+        );
+        let (status, _) = control_request(addr, "GET /nope HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 404);
+        server.stop();
+    }
+
+    #[test]
+    fn test_metrics() {
+        let m = Arc::new(metrics::Metrics::new());
+        let mut c = unreachable_client(false);
+        c.metrics = m.clone();
+        c.set_connection(metrics::Connection::Connected);
+        assert_eq!(m.bots(metrics::Connection::Connected), 1);
+        c.queue_recon(client::ReconnectReason::Dropped, -2, Vec::new(), 0);
+        c.queue_recon(client::ReconnectReason::Dropped, -2, Vec::new(), 0);
+        assert_eq!(m.reconnects(client::ReconnectReason::Dropped), 2);
+        assert_eq!(m.reconnects(client::ReconnectReason::Failure), 0);
+        c.set_connection(metrics::Connection::Reconnecting);
+        assert_eq!(m.bots(metrics::Connection::Connected), 0);
+        assert_eq!(
+            m.connection("bot@example.com"),
+            Some(metrics::Connection::Reconnecting)
+        );
+
+        m.packet(metrics::Direction::In, packets::NEWTICK, 30);
+        m.packet(metrics::Direction::In, packets::NEWTICK, 20);
+        m.packet(metrics::Direction::Out, packets::MOVE, 15);
+        assert_eq!(
+            m.packets(metrics::Direction::In, packets::NEWTICK),
+            metrics::PacketCount {
+                packets: 2,
+                bytes: 50
+            }
+        );
+        //bots count from their own threads without losing any
+        let counters: Vec<_> = (0..4)
+            .map(|_| {
+                let m = m.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        m.packet(metrics::Direction::Out, packets::PONG, 2);
+                    }
+                })
+            })
+            .collect();
+        for t in counters {
+            t.join().unwrap();
+        }
+        assert_eq!(
+            m.packets(metrics::Direction::Out, packets::PONG),
+            metrics::PacketCount {
+                packets: 4000,
+                bytes: 8000
+            }
+        );
+        m.failure(4);
+        m.death();
+        m.tick_latency(std::time::Duration::from_millis(3));
+        m.tick_latency(std::time::Duration::from_secs(2));
+        assert_eq!(m.tick_latency_count(), 2);
+
+        let text = m.render();
+        for line in [
+            "realmlib_bots{state=\"reconnecting\"} 1",
+            "realmlib_bots{state=\"connected\"} 0",
+            "realmlib_packets_total{direction=\"in\",type=\"NEWTICK\"} 2",
+            "realmlib_packet_bytes_total{direction=\"in\",type=\"NEWTICK\"} 50",
+            "realmlib_packets_total{direction=\"out\",type=\"MOVE\"} 1",
+            "realmlib_reconnects_total{reason=\"Dropped\"} 2",
+            "realmlib_deaths_total 1",
+            "realmlib_failures_total{code=\"4\"} 1",
+            "realmlib_tick_latency_seconds_bucket{le=\"0.001\"} 0",
+            "realmlib_tick_latency_seconds_bucket{le=\"0.005\"} 1",
+            "realmlib_tick_latency_seconds_bucket{le=\"1\"} 1",
+            "realmlib_tick_latency_seconds_bucket{le=\"+Inf\"} 2",
+            "realmlib_tick_latency_seconds_sum 2.003",
+            "realmlib_tick_latency_seconds_count 2",
+        ]
+        .iter()
+        {
+            assert!(text.lines().any(|l| l == *line), "missing {}", line);
+        }
+
+        let api = control::Api {
+            targets: Vec::new(),
+            metrics: m,
+        };
+        let r = control::handle_request(&api, "GET", "/metrics", "");
+        assert_eq!(r.status, 200);
+        assert!(r.content_type.starts_with("text/plain"));
+        assert_eq!(r.body, text);
+    }
+
+    #[test]
+    fn test_governor() {
+        use realmlib::network::governor;
+        let unlimited = governor::GovernorSettings {
+            connects_per_sec: 0.0,
+            server_connects_per_sec: 0.0,
+            max_per_server: 1,
+            ..governor::GovernorSettings::default()
+        };
+        let g = Arc::new(governor::Governor::new(2, unlimited));
+        let a = g.try_acquire("1.1.1.1").unwrap();
+        //one connection per server
+        assert!(g.try_acquire("1.1.1.1").is_err());
+        let b = g.try_acquire("2.2.2.2").unwrap();
+        assert_eq!(g.active(), 2);
+        //fleet cap
+        assert_eq!(g.try_acquire("3.3.3.3").unwrap_err(), governor::CAP_RETRY);
+        drop(a);
+        assert_eq!(g.active(), 1);
+        assert_eq!(g.active_on("1.1.1.1"), 0);
+        let _a = g.try_acquire("1.1.1.1").unwrap();
+        drop(b);
+        assert_eq!(g.active_on("2.2.2.2"), 0);
+
+        //the burst goes through, then the rate limit asks us to wait about 1 / rate
+        let limited = governor::GovernorSettings {
+            connects_per_sec: 100.0,
+            burst: 10,
+            server_connects_per_sec: 2.0,
+            server_burst: 2,
+            max_per_server: 0,
+        };
+        let g = Arc::new(governor::Governor::new(0, limited));
+        let mut held = vec![
+            g.try_acquire("1.1.1.1").unwrap(),
+            g.try_acquire("1.1.1.1").unwrap(),
+        ];
+        let wait = g.try_acquire("1.1.1.1").unwrap_err();
+        assert!(wait > std::time::Duration::from_millis(400));
+        assert!(wait <= std::time::Duration::from_millis(500));
+        //other servers only share the fleet bucket
+        held.push(g.try_acquire("2.2.2.2").unwrap());
+        assert_eq!(g.active(), 3);
+
+        //a client waiting on the governor gives up when it's asked to stop
+        let full = Arc::new(governor::Governor::new(
+            1,
+            governor::GovernorSettings::default(),
+        ));
+        let _slot = full.try_acquire("127.0.0.1").unwrap();
+        let mut c = unreachable_client(false);
+        c.game_connection.governor = Some(full.clone());
+        let stop = c.stop_signal.clone();
+        let waiter = thread::spawn(move || c.acquire_connect_slot("127.0.0.1"));
+        thread::sleep(std::time::Duration::from_millis(200));
+        stop.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(!waiter.join().unwrap());
+        assert_eq!(full.active(), 1);
+
+        let mut c = test_config("http://127.0.0.1:1");
+        c.governor.server_connects_per_sec = -1.0;
+        let fields: Vec<String> = c
+            .validate()
+            .unwrap_err()
+            .into_iter()
+            .map(|f| f.field)
+            .collect();
+        assert_eq!(fields, vec!["governor.server_connects_per_sec"]);
+    }
+
+    #[test]
+    fn test_logging() {
+        use tracing_subscriber::layer::SubscriberExt;
+        let path = temp_file("log.jsonl", "");
+        let settings = logging::LogSettings {
+            filter: String::from("info"),
+            stderr: false,
+            file: path.to_string_lossy().into_owned(),
+            file_filter: String::from("warn,realmlib::network=trace"),
+            ..logging::LogSettings::default()
+        };
+        let layers = logging::layers(&settings).unwrap();
+        //the factory threads log too, on their small stacks
+        thread::Builder::new()
+            .stack_size(32 * 1024)
+            .spawn(move || {
+                let subscriber = tracing_subscriber::registry().with(layers);
+                tracing::subscriber::with_default(subscriber, || {
+                    let span = tracing::error_span!(
+                        "client",
+                        email = "bot@example.com",
+                        ign = tracing::field::Empty,
+                        map = tracing::field::Empty
+                    );
+                    let _entered = span.enter();
+                    span.record("map", "Nexus");
+                    tracing::info!("filtered out");
+                    tracing::warn!(failure_id = 4, "Failure! bad login");
+                    tracing::trace!(target: "realmlib::network", packet = "NEWTICK", "packet");
+                })
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["level"], "WARN");
+        assert_eq!(lines[0]["fields"]["message"], "Failure! bad login");
+        assert_eq!(lines[0]["fields"]["failure_id"], 4);
+        assert_eq!(lines[0]["span"]["email"], "bot@example.com");
+        assert_eq!(lines[0]["span"]["map"], "Nexus");
+        assert_eq!(lines[1]["fields"]["packet"], "NEWTICK");
+
+        assert!(logging::parse_filter("info,realmlib::network=trace").is_ok());
+        let mut c = test_config("http://127.0.0.1:1");
+        c.logging.filter = String::from("info,realmlib=loud");
+        let fields: Vec<String> = c
+            .validate()
+            .unwrap_err()
+            .into_iter()
+            .map(|f| f.field)
+            .collect();
+        assert_eq!(fields, vec!["logging.filter"]);
+    }
+
+    #[test]
+    fn test_snapshots() {
+        //nothing gets written unless a dir is set
+        assert!(
+            snapshot::SnapshotStore::from_settings(&snapshot::SnapshotSettings::default())
+                .is_none()
+        );
+        let dir = std::env::temp_dir().join(format!("realmlib-{}-snapshots", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = Arc::new(snapshot::SnapshotStore::new(&dir));
+        assert_eq!(store.load("bot@example.com").unwrap(), None);
+        assert!(store.load_all().unwrap().is_empty());
+        assert!(store
+            .path_for("Bot+1@Example.com")
+            .ends_with("bot_1@example.com.json"));
+
+        let mut c = unreachable_client(false);
+        c.snapshots = Some(store.clone());
+        c.ign = String::from("Botty");
+        c.current_map = String::from("Nexus");
+        c.recon.current_server = String::from("1.2.3.4");
+        c.base.char_id = 7;
+        //reconnecting clears the stats, so it goes first
+        c.queue_recon(client::ReconnectReason::Dropped, -2, Vec::new(), 0);
+        let mut fame = network::types::StatData::new();
+        fame.stat_type = network::types::Stats::CHARACTERFAME.stat_to_u8();
+        fame.stat_value = 120;
+        c.stats.stat_map.insert(fame.stat_type, fame);
+        c.history.deaths = 2;
+        c.save_snapshot();
+
+        let saved = store.load("BOT@example.com").unwrap().unwrap();
+        assert_eq!(saved.ign, "Botty");
+        assert_eq!(saved.map, "Nexus");
+        assert_eq!(saved.server, "1.2.3.4");
+        assert_eq!(saved.char_id, 7);
+        assert_eq!(saved.fame, 120);
+        assert_eq!(saved.stats.get(&57), Some(&120));
+        assert_eq!(saved.inventory, vec![-1; 12]);
+        assert_eq!(saved.deaths, 2);
+        assert_eq!(saved.reconnects.len(), 1);
+        assert_eq!(saved.reconnects[0].reason, "Dropped");
+        assert_eq!(store.load_all().unwrap(), vec![saved.clone()]);
+
+        //a new client for the account picks up where the old one left off
+        let mut fresh = unreachable_client(false);
+        fresh.snapshots = Some(store.clone());
+        fresh.base.char_id = 0;
+        fresh.config.snapshots.resume_server = true;
+        fresh.resume_snapshot();
+        assert_eq!(fresh.history.deaths, 2);
+        assert_eq!(fresh.history.reconnects, saved.reconnects);
+        assert_eq!(fresh.ign, "Botty");
+        assert_eq!(fresh.base.char_id, 7);
+        assert_eq!(fresh.base.server_ip, "1.2.3.4");
+        //only once
+        fresh.history.deaths = 5;
+        fresh.resume_snapshot();
+        assert_eq!(fresh.history.deaths, 5);
+
+        //periodic saves wait for save_delay_secs
+        fresh.config.save_delay_secs = 60;
+        fresh.save_snapshot_if_due();
+        assert_eq!(store.load("bot@example.com").unwrap().unwrap().deaths, 2);
+        fresh.history.last_save -= std::time::Duration::from_secs(61);
+        fresh.save_snapshot_if_due();
+        assert_eq!(store.load("bot@example.com").unwrap().unwrap().deaths, 5);
+        //a reconnect clears the stats, the saved ones stay until new ones come in
+        fresh.queue_recon(client::ReconnectReason::Failure, -2, Vec::new(), 0);
+        fresh.save_snapshot();
+        let kept = store.load("bot@example.com").unwrap().unwrap();
+        assert_eq!(kept.fame, 120);
+        assert_eq!(kept.reconnects.len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Sets our stats the way an update from the server would
+    fn set_stats(c: &mut client::Client, stats: &[(network::types::Stats, i32)]) {
+        for (stat, value) in stats {
+            let mut s = network::types::StatData::new();
+            s.stat_type = stat.stat_to_u8();
+            s.stat_value = *value;
+            c.stats.stat_map.insert(s.stat_type, s);
+        }
+    }
+
+    /// An unreachable client with object id 1, these stats and metrics of its own
+    fn client_with_stats(
+        stats: &[(network::types::Stats, i32)],
+    ) -> (client::Client, Arc<metrics::Metrics>) {
+        let mut c = unreachable_client(false);
+        let m = Arc::new(metrics::Metrics::new());
+        c.metrics = m.clone();
+        c.object_id = 1;
+        set_stats(&mut c, stats);
+        (c, m)
+    }
+
+    /// Packets of that type the client sent
+    fn sent(m: &metrics::Metrics, id: u8) -> u64 {
+        m.packets(metrics::Direction::Out, id).packets
+    }
+
+    fn moving_client(speed: i32) -> (client::Client, Arc<metrics::Metrics>) {
+        let (mut c, m) = client_with_stats(&[
+            (network::types::Stats::SPEED, speed),
+            (network::types::Stats::EFFECTS, 0),
+        ]);
+        c.movement.move_multiplier = 1.0; //full speed, the default holds back a bit
+        c.movement.map_width = 100;
+        c.movement.current_position = network::types::WorldPosition::new_fill(10.0, 10.0);
+        (c, m)
+    }
+
+    #[test]
+    fn test_move_timing() {
+        use realmlib::network::packets::client_packets::ClientPacket;
+        let target = network::types::WorldPosition::new_fill(20.0, 10.0);
+
+        //a 200ms tick at the slowest and the fastest speed
+        for (speed, step) in [(0, 0.82), (75, 1.922)] {
+            let (mut c, _) = moving_client(speed);
+            c.time_keeper.last_tick_time = 1000;
+            c.time_keeper.current_tick_time = 1200;
+            assert_eq!(c.time_keeper.tick_elapsed(), 200);
+            c.move_to(target);
+            assert!((c.movement.current_position.x - (10.0 + step)).abs() < 1e-4);
+            assert_eq!(c.movement.current_position.y, 10.0);
+        }
+
+        //a late tick moves further, a close target is reached exactly
+        let (mut c, _) = moving_client(0);
+        c.time_keeper.last_tick_time = 1000;
+        c.time_keeper.current_tick_time = 1500;
+        c.move_to(target);
+        assert!((c.movement.current_position.x - 12.05).abs() < 1e-4);
+        c.move_to(network::types::WorldPosition::new_fill(12.5, 10.0));
+        assert_eq!(c.movement.current_position.x, 12.5);
+
+        //records every 100ms between the last two ticks
+        let (mut c, _) = moving_client(0);
+        c.time_keeper.last_tick_time = 1000;
+        c.time_keeper.current_tick_time = 1200;
+        c.move_to(target);
+        let records = c.move_records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].time, 1100);
+        assert!((records[0].x - 10.41).abs() < 1e-4);
+        assert_eq!(records[1].time, 1200);
+        assert_eq!(records[1].x, c.movement.current_position.x);
+
+        //the first tick goes by the server's tick_time, and a long gap caps the records
+        let (mut c, _) = moving_client(0);
+        c.time_keeper.on_tick(200);
+        assert_eq!(c.time_keeper.last_tick_time, -1);
+        assert_eq!(c.time_keeper.tick_elapsed(), 200);
+        c.time_keeper.last_tick_time = 0;
+        c.time_keeper.current_tick_time = 5000;
+        let times = c.time_keeper.record_times();
+        assert_eq!(times.len(), client::MAX_MOVE_RECORDS);
+        assert_eq!(times, (41..=50).map(|i| i * 100).collect::<Vec<i32>>());
+        //the records line up with the tick we're answering, not the one before it
+        c.time_keeper.last_tick_time = 1000;
+        c.time_keeper.current_tick_time = 1250;
+        assert_eq!(c.time_keeper.record_times(), vec![1050, 1150, 1250]);
+
+        //a Move is tick id, time, position, then the records as time, x, y
+        let mut mov = packets::client_packets::Move::new();
+        mov.records = records;
+        assert_eq!(mov.write().data.len(), 4 + 4 + 8 + 2 + 2 * 12);
+
+        //reconnecting starts the clock over
+        c.queue_recon(client::ReconnectReason::Dropped, -2, Vec::new(), 0);
+        assert_eq!(c.time_keeper.current_tick_time, -1);
+        assert_eq!(c.time_keeper.tick_elapsed(), client::TICK_MS);
+    }
+
+    #[test]
+    fn test_tiles() {
+        let xml = r#"<GroundTypes>
+            <Ground type="0x0024" id="Shallow Water"><Speed>0.5</Speed></Ground>
+            <Ground type="0x00be" id="Lava"><MinDamage>20</MinDamage><MaxDamage>40</MaxDamage></Ground>
+            <Ground type="12" id="Hot Rocks"><MaxDamage>10</MaxDamage></Ground>
+        </GroundTypes>"#;
+        let defs = tiles::TileDefs::from_xml(xml).unwrap();
+        assert_eq!(defs.len(), 3);
+        assert_eq!(defs.get(0x24).unwrap().speed, 0.5);
+        let lava = defs.get(0xbe).unwrap();
+        assert_eq!(
+            (lava.speed, lava.min_damage, lava.max_damage),
+            (1.0, 20, 40)
+        );
+        assert_eq!(defs.get(12).unwrap().min_damage, 10);
+        assert!(!defs.get(0x24).unwrap().is_damaging());
+        let json = tiles::TileDefs::from_json(r#"[{"tile_type": 36, "speed": 0.5}]"#).unwrap();
+        assert_eq!(json.get(36).unwrap().max_damage, 0);
+        assert!(tiles::TileDefs::from_xml("<GroundTypes><Ground/></GroundTypes>").is_err());
+
+        //water halves the step, unknown tiles walk at normal speed
+        let tile = |x, y, tile_type| network::types::GroundTile { x, y, tile_type };
+        let target = network::types::WorldPosition::new_fill(20.0, 10.0);
+        let (mut c, m) = moving_client(0);
+        c.tiles = Some(Arc::new(defs));
+        c.movement.tiles.insert(tile(10, 10, 0x24), 0x24);
+        c.movement.tiles.insert(tile(11, 10, 0x1), 0x1);
+        c.time_keeper.last_tick_time = 1000;
+        c.time_keeper.current_tick_time = 1200;
+        c.move_to(target);
+        assert!((c.movement.current_position.x - 10.41).abs() < 1e-4);
+        c.movement.current_position.x = 11.0;
+        c.move_to(target);
+        assert!((c.movement.current_position.x - 11.82).abs() < 1e-4);
+        //a tile that changes type is replaced, not added twice
+        c.movement.tiles.insert(tile(11, 10, 0xbe), 0xbe);
+        assert_eq!(c.movement.tiles.len(), 2);
+
+        //standing on lava reports damage every 500ms, and not while invincible
+        let damage = || sent(&m, packets::GROUNDDAMAGE);
+        let on_lava = |time| network::types::MoveRecord {
+            time,
+            x: 11.5,
+            y: 10.5,
+        };
+        let records: Vec<_> = (1..=10).map(|i| on_lava(1000 + i * 100)).collect();
+        c.report_ground_damage(&records);
+        assert_eq!(damage(), 2);
+        assert_eq!(c.time_keeper.last_ground_damage, 1600);
+        c.report_ground_damage(&[on_lava(2000)]);
+        assert_eq!(damage(), 2);
+        c.report_ground_damage(&[network::types::MoveRecord {
+            time: 2200,
+            x: 10.5,
+            y: 10.5,
+        }]);
+        assert_eq!(damage(), 2);
+        c.stats
+            .stat_map
+            .get_mut(&network::types::Stats::EFFECTS.stat_to_u8())
+            .unwrap()
+            .stat_value = 1 << (network::types::Effects::INVINCIBLE.to_byte() - 1);
+        c.report_ground_damage(&[on_lava(2500)]);
+        assert_eq!(damage(), 2);
+        c.stats
+            .stat_map
+            .get_mut(&network::types::Stats::EFFECTS.stat_to_u8())
+            .unwrap()
+            .stat_value = 0;
+        c.report_ground_damage(&[on_lava(2600)]);
+        assert_eq!(damage(), 3);
+    }
+
+    #[test]
+    fn test_aoe() {
+        use network::types::{Effects, Stats};
+        let (mut c, m) = moving_client(0);
+        set_stats(
+            &mut c,
+            &[
+                (Stats::HP, 500),
+                (Stats::MAXIMUMHP, 1000),
+                (Stats::DEFENSE, 20),
+                (Stats::EFFECTS2, 0),
+            ],
+        );
+        c.current_map = String::from("Realm of the Mad God");
+
+        //defense, armor pierce and the damage floor
+        assert_eq!(c.damage_with_defense(100, false), 80);
+        assert_eq!(c.damage_with_defense(100, true), 100);
+        assert_eq!(c.damage_with_defense(10, false), 1);
+        c.conditions.apply(Effects::ARMORED.to_byte(), 0, i32::MAX);
+        assert_eq!(c.damage_with_defense(100, false), 60);
+        c.conditions.clear();
+
+        let aoe = |x, damage, armor_pierce| packets::server_packets::AoE {
+            position: network::types::WorldPosition::new_fill(x, 10.0),
+            radius: 3.0,
+            damage,
+            effects: Effects::SLOWED.to_byte(),
+            effect_duration: 2.0,
+            origin_type: 0,
+            color: 0,
+            armor_pierce,
+        };
+        //out of range does nothing
+        c.on_aoe(aoe(20.0, 100, false));
+        assert_eq!(c.predicted_hp(), Some(500));
+        assert!(!c.has_effect(Effects::SLOWED));
+
+        c.on_aoe(aoe(11.0, 100, false));
+        assert_eq!(c.predicted_hp(), Some(420));
+        assert!(c.has_effect(Effects::SLOWED));
+        assert_eq!((c.health.aoe_hits, c.health.damage_taken), (1, 80));
+        assert_eq!((m.aoe_hits(), m.total_damage_taken()), (1, 80));
+
+        //immunities block the condition
+        c.conditions.clear();
+        c.conditions
+            .apply(Effects::SLOWIMMUNE.to_byte(), 0, i32::MAX);
+        c.apply_effect(Effects::SLOWED.to_byte(), 2000);
+        assert!(!c.has_effect(Effects::SLOWED));
+
+        //dropping to 30% escapes once
+        let escapes = || sent(&m, packets::ESCAPE);
+        c.on_aoe(aoe(11.0, 150, true));
+        assert_eq!(c.predicted_hp(), Some(270));
+        assert!(c.health.escaping);
+        assert_eq!((escapes(), m.auto_nexuses()), (1, 1));
+        c.on_aoe(aoe(11.0, 10, true));
+        assert_eq!(escapes(), 1);
+        assert!(m.render().contains("realmlib_auto_nexus_total 1"));
+
+        //a tick without our hp in it keeps the prediction
+        let predicted = c.predicted_hp();
+        assert_ne!(predicted, Some(500));
+        let mut def = network::types::StatData::new();
+        def.stat_type = Stats::DEFENSE.stat_to_u8();
+        def.stat_value = 20;
+        let mut status = network::types::ObjectStatusData::new();
+        status.object_id = c.object_id;
+        status.stats.insert(def.stat_type, def);
+        c.parse_newtick(&packets::server_packets::NewTick {
+            tick_id: 1,
+            tick_time: 200,
+            statuses: vec![status],
+        });
+        assert_eq!(c.predicted_hp(), predicted);
+
+        //the server's hp takes over once a tick carries it
+        let mut hp = network::types::StatData::new();
+        hp.stat_type = Stats::HP.stat_to_u8();
+        hp.stat_value = 900;
+        let mut status = network::types::ObjectStatusData::new();
+        status.object_id = c.object_id;
+        status.stats.insert(hp.stat_type, hp);
+        c.parse_newtick(&packets::server_packets::NewTick {
+            tick_id: 2,
+            tick_time: 400,
+            statuses: vec![status],
+        });
+        assert_eq!(c.predicted_hp(), Some(900));
+    }
+
+    #[test]
+    fn test_conditions() {
+        use network::types::{Effects, Stats};
+        //nothing from the server yet is no effects, not a panic
+        let (mut c, m) = client_with_stats(&[]);
+        assert_eq!(c.stats.get_item(Stats::EFFECTS), None);
+        assert_eq!(c.server_has_effect(Effects::SLOWED.to_byte()), None);
+        assert!(!c.has_effect(Effects::SLOWED));
+        assert_eq!(c.get_move_speed(), client::MIN_MOVE_SPEED * 0.8);
+
+        for id in 1..=50 {
+            assert_eq!(Effects::from_byte(id).unwrap().to_byte(), id);
+        }
+        assert_eq!(Effects::from_byte(0), None);
+        assert_eq!(Effects::from_byte(55), Some(Effects::UNKNOWN(55)));
+        assert_eq!(Effects::UNKNOWN(55).to_byte(), 55);
+        assert_eq!(Effects::from_byte(conditions::MAX_EFFECT_ID + 1), None);
+
+        //both bitfields, including an id past the ones Effects has a name for
+        set_stats(
+            &mut c,
+            &[
+                (Stats::EFFECTS, 1 << (Effects::SLOWED.to_byte() - 1)),
+                (Stats::EFFECTS2, 1 << (50 - 32) | 1 << (55 - 32)),
+            ],
+        );
+        assert!(c.has_effect(Effects::SLOWED));
+        assert!(c.has_effect(Effects::ENERGIZED));
+        assert!(c.has_effect_id(55));
+        assert!(c.has_effect(Effects::UNKNOWN(55)));
+        assert_eq!(c.server_has_effect(Effects::DAZED.to_byte()), Some(false));
+        assert_eq!(c.active_effect_ids(), vec![4, 50, 55]);
+        assert_eq!(
+            c.active_effects(),
+            vec![Effects::SLOWED, Effects::ENERGIZED, Effects::UNKNOWN(55)]
+        );
+
+        //local timers on top, a shorter one doesn't cut a longer one short
+        c.apply_effect(Effects::DAZED.to_byte(), 60_000);
+        c.apply_effect(Effects::DAZED.to_byte(), 1);
+        assert!(c.has_effect(Effects::DAZED));
+        c.conditions.apply(Effects::WEAK.to_byte(), -5000, 1000);
+        assert!(!c.has_effect(Effects::WEAK));
+        c.apply_effects(&[conditions::ConditionEffect {
+            effect: Effects::BLIND.to_byte(),
+            duration_ms: 60_000,
+        }]);
+        assert!(c.has_effect(Effects::BLIND));
+
+        c.set_condition(Effects::SPEEDY, 5.0);
+        assert!(c.has_effect(Effects::SPEEDY));
+        assert_eq!(sent(&m, packets::SETCONDITION), 1);
+
+        //a reconnect forgets everything
+        c.queue_recon(client::ReconnectReason::Dropped, -2, Vec::new(), 0);
+        assert!(c.active_effect_ids().is_empty());
+    }
+
+    #[test]
+    fn test_quest_hunter() {
+        let (mut c, m) = moving_client(0);
+        c.current_map = String::from("Realm of the Mad God");
+        c.time_keeper.startup_time -= std::time::Duration::from_secs(10);
+        let shots = || sent(&m, packets::PLAYERSHOOT);
+
+        //the quest isn't known, then isn't in sight
+        assert_eq!(c.quest_distance(), None);
+        c.on_questobjid(packets::server_packets::QuestObjID { object_id: 50 });
+        assert_eq!(c.quest.object_id, Some(50));
+        assert_eq!(c.quest_distance(), None);
+        let mut quest = network::types::ObjectData {
+            object_type: 0x0d5e,
+            status: network::types::ObjectStatusData::new(),
+        };
+        quest.status.object_id = 50;
+        quest.status.position = network::types::WorldPosition::new_fill(30.0, 10.0);
+        c.objects.entities.insert(50, quest);
+        assert_eq!(c.quest_distance(), Some(20.0));
+        assert_eq!(c.quest_angle(), Some(0.0));
+
+        //far off: head straight for it without shooting, ticking like the server does
+        let tick = |c: &mut client::Client, tick_id: i32| {
+            c.on_new_tick(packets::server_packets::NewTick {
+                tick_id,
+                tick_time: tick_id * client::TICK_MS,
+                statuses: Vec::new(),
+            });
+        };
+        c.start_hunting();
+        tick(&mut c, 1);
+        assert_eq!(c.movement.target_position.x, 30.0);
+        assert!(c.movement.current_position.x > 10.0);
+        assert_eq!(shots(), 0);
+
+        //close: circle it at the radius and shoot, once per attack period
+        c.movement.current_position = network::types::WorldPosition::new_fill(26.0, 10.0);
+        tick(&mut c, 2);
+        let orbit = c
+            .movement
+            .target_position
+            .distance_to(&network::types::WorldPosition::new_fill(30.0, 10.0));
+        assert!((orbit - c.movement.radius).abs() < 1e-3);
+        assert_eq!(shots(), 1);
+        tick(&mut c, 3);
+        assert_eq!(shots(), 1);
+        //a hunting bot stays in the realm
+        assert_eq!(sent(&m, packets::ESCAPE), 0);
+        assert_eq!(sent(&m, packets::MOVE), 3);
+
+        //not in the nexus, and the hunt ends with a stop
+        c.current_map = String::from("Nexus");
+        c.combat.last_attack_time = 0;
+        c.hunt_quest();
+        assert_eq!(shots(), 1);
+        c.run_command(control::ClientCommand::Stop);
+        assert!(!c.quest.hunting);
+        c.run_command(control::ClientCommand::Hunt);
+        assert!(c.quest.hunting);
+
+        //the quest is per map
+        c.queue_recon(client::ReconnectReason::Requested, -2, Vec::new(), 0);
+        assert_eq!(c.quest.object_id, None);
+        assert!(c.quest.hunting);
+    }
+
+    #[test]
+    fn test_daily_quests() {
+        let (mut c, m) = client_with_stats(&[
+            (network::types::Stats::INVENTORY0, 0xa22), //gear doesn't count
+            (network::types::Stats::INVENTORY5, 0xa23),
+            (network::types::Stats::INVENTORY6, -1),
+            (network::types::Stats::BACKPACK2, 0xa24),
+        ]);
+        assert_eq!(daily::parse_goal("0xa22, 2595,junk"), vec![0xa22, 2595]);
+        assert_eq!(c.inventory_item(5), Some(0xa23));
+        assert_eq!(c.inventory_item(6), None);
+        assert_eq!(c.find_item(0xa22), None);
+        assert_eq!(c.find_item(0xa23), Some(5));
+        assert_eq!(c.find_item(0xa24), None); //no backpack yet
+        let quest = |goal: &str| packets::server_packets::QuestFetchResponse {
+            tier: 2,
+            goal: goal.to_owned(),
+            description: String::from("Bring me a ring"),
+            image: String::new(),
+        };
+
+        //missing items: nothing goes out
+        c.run_command(control::ClientCommand::DailyQuest);
+        assert_eq!(sent(&m, packets::QUESTFETCHASK), 1);
+        c.on_questfetchresponse(quest("0xa22"));
+        assert_eq!(c.missing_daily_items(), vec![0xa22]);
+        assert_eq!(sent(&m, packets::QUESTREDEEM), 0);
+        assert!(c.daily.redeem_next);
+
+        //an item wanted twice takes two slots
+        c.on_questfetchresponse(quest("0xa23,0xa23"));
+        assert_eq!(c.missing_daily_items(), vec![0xa23]);
+        assert_eq!(sent(&m, packets::QUESTREDEEM), 0);
+        set_stats(&mut c, &[(network::types::Stats::INVENTORY7, 0xa23)]);
+        assert!(c.missing_daily_items().is_empty());
+        set_stats(&mut c, &[(network::types::Stats::INVENTORY7, -1)]);
+
+        //the backpack counts once we have one
+        set_stats(&mut c, &[(network::types::Stats::HASBACKPACK, 1)]);
+        c.on_questfetchresponse(quest("0xa23,0xa24"));
+        assert!(c.missing_daily_items().is_empty());
+        assert_eq!(sent(&m, packets::QUESTREDEEM), 1);
+        assert!(c.daily.redeeming);
+
+        //a failure keeps the quest, a success fetches the next tier
+        c.on_questredeemresponse(packets::server_packets::QuestRedeemResponse {
+            success: false,
+            message: String::from("nope"),
+        });
+        assert_eq!(c.daily.redeemed, 0);
+        assert_eq!(c.daily.quest.as_ref().map(|q| q.tier), Some(2));
+        c.on_questredeemresponse(packets::server_packets::QuestRedeemResponse {
+            success: true,
+            message: String::from("Quest complete"),
+        });
+        assert_eq!(c.daily.redeemed, 1);
+        assert_eq!(m.daily_quests(), 1);
+        assert_eq!(c.daily.quest, None);
+        assert_eq!(sent(&m, packets::QUESTFETCHASK), 2);
+
+        //an empty goal means nothing left to do
+        c.on_questfetchresponse(quest(""));
+        assert_eq!(c.daily.quest, None);
+        assert!(!c.daily.fetching);
+    }
+
+    const CALENDAR_XML: &str = r#"<LoginRewards serverTime="1515000000" conCurDay="2" nonconCurDay="2">
+        <NonConsecutive days="2">
+            <Login><Days>1</Days><ItemId quantity="2">2838</ItemId><Gold>0</Gold><key>bm9uMQ==</key><Claimed/></Login>
+            <Login><Days>2</Days><ItemId quantity="1">2591</ItemId><Gold>0</Gold><key>bm9uMg==</key></Login>
+            <Login><Days>3</Days><ItemId>-1</ItemId><Gold>50</Gold></Login>
+        </NonConsecutive>
+        <Consecutive days="2">
+            <Login><Days>2</Days><ItemId>-1</ItemId><Gold>10</Gold><key>Y29uMg==</key></Login>
+        </Consecutive>
+    </LoginRewards>"#;
+
+    #[test]
+    fn test_login_rewards() {
+        let days = web::parse_calendar(CALENDAR_XML).unwrap();
+        assert_eq!(days.len(), 4);
+        assert_eq!(days[0].claim_type, "nonconsecutive");
+        assert_eq!((days[0].item_id, days[0].quantity), (2838, 2));
+        assert!(days[0].claimed && !days[0].claimable());
+        assert!(days[1].claimable());
+        assert!(!days[2].claimable()); //not unlocked yet
+        assert_eq!(days[3].claim_type, "consecutive");
+        assert_eq!(days[3].gold, 10);
+
+        //the calendar comes from login_rewards.api_base, not the (unreachable) main api
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            web_stand_in(
+                listener,
+                1,
+                vec![("/dailyLogin/fetchCalendar", CALENDAR_XML)],
+            )
+        });
+        let (mut c, m) = client_with_stats(&[]);
+        c.config.login_rewards.api_base = base;
+        c.fetch_login_calendar().unwrap();
+        assert!(server.join().unwrap()[0].contains("guid=bot%40example.com"));
+        assert_eq!(c.rewards.unclaimed().len(), 2);
+
+        //claims go out in the nexus only, once each
+        let claims = || sent(&m, packets::LOGINREWARDSEND);
+        c.current_map = String::from("Realm of the Mad God");
+        c.claim_login_rewards();
+        assert_eq!(claims(), 0);
+        c.current_map = String::from("Nexus");
+        c.claim_login_rewards();
+        assert_eq!(claims(), 2);
+        assert!(c.rewards.unclaimed().is_empty());
+        c.claim_login_rewards();
+        assert_eq!(claims(), 2);
+
+        c.on_loginrewardrecv(packets::server_packets::LoginRewardRecv {
+            item_id: 2591,
+            quantity: 1,
+            gold: 0,
+        });
+        assert_eq!(c.rewards.received[0].item_id, 2591);
+        c.publish_state();
+        assert_eq!(c.mailbox.state().login_rewards.len(), 1);
+    }
+
+    #[test]
+    fn test_pets() {
+        let (mut c, m) = client_with_stats(&[]);
+        assert_eq!(c.active_pet(), None);
+        assert!(!c.feed_pet(4, pets::Payment::Gold));
+
+        //a pet in sight only counts once the server says it's ours
+        let mut pet = network::types::ObjectData {
+            object_type: 0x7e00,
+            status: network::types::ObjectStatusData::new(),
+        };
+        for (stat, value) in [
+            (network::types::Stats::PETINSTANCEID, 77),
+            (network::types::Stats::PETTYPE, 0x7e00),
+            (network::types::Stats::PETLEVEL0, 30),
+            (network::types::Stats::PETLEVEL1, 12),
+        ] {
+            let mut s = network::types::StatData::new();
+            s.stat_type = stat.stat_to_u8();
+            s.stat_value = value;
+            pet.status.stats.insert(s.stat_type, s);
+        }
+        c.objects.entities.insert(500, pet);
+        assert_eq!(c.active_pet(), None);
+        c.on_activepetupdaterecv(packets::server_packets::ActivePetUpdateRecv { instance_id: 77 });
+        let active = c.active_pet().unwrap();
+        assert_eq!(active.instance_id, 77);
+        assert_eq!(active.levels, [30, 12, 0]);
+
+        //feeding takes an item, the payment comes from the command
+        assert!(!c.feed_pet(4, pets::Payment::Gold));
+        set_stats(&mut c, &[(network::types::Stats::INVENTORY4, 0xa22)]);
+        let feed: control::ClientCommand = serde_json::from_value(
+            serde_json::json!({"command": "pet_feed", "slot": 4, "payment": "fame"}),
+        )
+        .unwrap();
+        assert_eq!(
+            feed,
+            control::ClientCommand::PetFeed {
+                slot: 4,
+                payment: pets::Payment::Fame
+            }
+        );
+        c.run_command(feed);
+        assert_eq!(sent(&m, packets::PETUPGRADEREQUEST), 1);
+        c.fuse_pets(77, 78, pets::Payment::Gold);
+        assert_eq!(sent(&m, packets::PETUPGRADEREQUEST), 2);
+        c.run_command(control::ClientCommand::PetFollow { instance_id: 78 });
+        c.run_command(control::ClientCommand::PetUnfollow);
+        assert_eq!(sent(&m, packets::ACTIVEPETUPDATESEND), 2);
+        c.change_pet_skin(77, 0x7e10, pets::Payment::Gold);
+        assert_eq!(sent(&m, packets::PETCHANGESKIN), 1);
+
+        //results show up in the state
+        c.on_hatchpet(packets::server_packets::HatchPet {
+            pet_name: String::from("Bob"),
+            pet_skin: 0x7e10,
+            item_type: 0xc86,
+        });
+        c.on_evolvepet(packets::server_packets::EvolvePet {
+            pet_id: 77,
+            initial_skin: 0x7e00,
+            final_skin: 0x7e10,
+        });
+        c.publish_state();
+        let state = c.mailbox.state();
+        assert_eq!(state.pet.map(|p| p.instance_id), Some(77));
+        assert_eq!(state.last_hatch.unwrap().pet_name, "Bob");
+        assert_eq!(state.last_evolve.unwrap().final_skin, 0x7e10);
+        c.on_deletepet(packets::server_packets::DeletePet { pet_id: 77 });
+        assert_eq!(c.active_pet(), None);
+    }
+
+    #[test]
+    fn test_arena() {
+        let (mut c, m) = moving_client(0);
+        c.time_keeper.startup_time -= std::time::Duration::from_secs(10);
+
+        //only from the nexus, and once until the server moves us
+        c.current_map = String::from("Realm of the Mad God");
+        c.run_command(control::ClientCommand::Arena);
+        assert_eq!(sent(&m, packets::ENTERARENA), 0);
+        c.current_map = String::from("Nexus");
+        c.run_command(control::ClientCommand::Arena);
+        c.enter_arena();
+        assert_eq!(sent(&m, packets::ENTERARENA), 1);
+        c.current_map = String::from(realmlib::arena::ARENA_MAP);
+        c.arena_joined();
+        assert!(c.arena.active && !c.arena.entering);
+        assert_eq!(c.arena.runs, 1);
+
+        //between waves hold the middle, then go for the closest enemy, not a player
+        c.on_imminentarenawave(packets::server_packets::ImminentArenaWave { current_runtime: 0 });
+        assert_eq!(c.arena.wave, 1);
+        assert_eq!(c.movement.target_position.x, 50.0);
+        let mut add = |id: i32, x: f32, stat: network::types::Stats| {
+            let mut o = network::types::ObjectData {
+                object_type: 0,
+                status: network::types::ObjectStatusData::new(),
+            };
+            o.status.object_id = id;
+            o.status.position = network::types::WorldPosition::new_fill(x, 10.0);
+            let mut s = network::types::StatData::new();
+            s.stat_type = stat.stat_to_u8();
+            o.status.stats.insert(s.stat_type, s);
+            let mut hp = network::types::StatData::new();
+            hp.stat_type = network::types::Stats::MAXIMUMHP.stat_to_u8();
+            o.status.stats.insert(hp.stat_type, hp);
+            c.objects.entities.insert(id, o);
+        };
+        add(20, 12.0, network::types::Stats::ACCOUNTID);
+        add(21, 15.0, network::types::Stats::HP);
+        assert_eq!(c.nearest_enemy().map(|p| p.x), Some(15.0));
+        c.fight_arena();
+        assert_eq!(sent(&m, packets::PLAYERSHOOT), 1);
+        c.publish_state();
+        assert_eq!(c.mailbox.state().arena_wave, Some(1));
+
+        //a cheap death is paid for, an expensive one sends us home
+        c.config.arena.max_death_cost = 100;
+        c.on_arenadeath(packets::server_packets::ArenaDeath { cost: 50 });
+        assert_eq!(sent(&m, packets::ACCEPTARENADEATH), 1);
+        assert!(c.arena.active);
+        c.on_arenadeath(packets::server_packets::ArenaDeath { cost: 500 });
+        assert_eq!(sent(&m, packets::ACCEPTARENADEATH), 1);
+        assert!(!c.arena.active);
+
+        //the server's reconnect out of the arena ends the run too
+        c.arena_joined();
+        assert!(c.arena.active);
+        c.on_reconnect(packets::server_packets::Reconnect {
+            name: String::from("Nexus"),
+            host: String::new(),
+            stats: String::new(),
+            port: 2050,
+            game_id: -2,
+            key_time: 0,
+            is_from_arena: true,
+            key: Vec::new(),
+        });
+        assert!(!c.arena.active);
+        assert_eq!(c.arena.best_wave, 1);
+
+        //back in the nexus, arena.enabled doesn't pay for more than arena.max_runs runs
+        c.config.arena.enabled = true;
+        c.current_map = String::from("Nexus");
+        c.arena_joined();
+        let joined = || packets::server_packets::CreateSuccess {
+            object_id: 1,
+            char_id: 1,
+        };
+        c.on_create_success(joined());
+        assert_eq!(sent(&m, packets::ENTERARENA), 1);
+        assert_eq!(c.arena.runs, 2);
+        c.config.arena.max_runs = 3;
+        c.on_create_success(joined());
+        assert_eq!(sent(&m, packets::ENTERARENA), 2);
+    }
+
+    #[test]
+    fn test_bootstrap() {
+        for _ in 0..20 {
+            assert!(bootstrap::valid_name(&bootstrap::generate_name()));
+        }
+        assert!(!bootstrap::valid_name("Bad Name"));
+        assert!(!bootstrap::valid_name("Waytoolongname"));
+
+        let (mut c, m) = client_with_stats(&[]);
+        c.config.bootstrap.choose_name = true;
+        c.config.bootstrap.max_name_attempts = 3;
+        c.config.bootstrap.names = vec![String::from("Bad Name"), String::from("Botty")];
+
+        //nothing happens until the server says the account has no name
+        c.check_name();
+        assert_eq!(sent(&m, packets::CHOOSENAME), 0);
+        let mut me = network::types::ObjectData {
+            object_type: 0x030e,
+            status: network::types::ObjectStatusData::new(),
+        };
+        me.status.object_id = 1;
+        let mut s = network::types::StatData::new();
+        s.stat_type = network::types::Stats::NAMECHOSEN.stat_to_u8();
+        me.status.stats.insert(s.stat_type, s);
+        c.on_update(packets::server_packets::Update {
+            tiles: Vec::new(),
+            new_objs: vec![me],
+            drops: Vec::new(),
+        });
+        assert_eq!(c.name_chosen(), Some(false));
+        assert_eq!(sent(&m, packets::CHOOSENAME), 1);
+        assert_eq!(c.bootstrap.pending_name.as_deref(), Some("Botty"));
+        c.check_name();
+        assert_eq!(sent(&m, packets::CHOOSENAME), 1);
+
+        //a taken name moves on to a generated one, until the attempts run out
+        let taken = || packets::server_packets::NameResult {
+            success: false,
+            error_message: String::from("Name is already taken"),
+        };
+        c.on_nameresult(taken());
+        assert_eq!(sent(&m, packets::CHOOSENAME), 2);
+        let generated = c.bootstrap.pending_name.clone().unwrap();
+        assert_ne!(generated, "Botty");
+        c.on_nameresult(taken());
+        c.on_nameresult(taken());
+        assert_eq!(sent(&m, packets::CHOOSENAME), 3);
+        assert_eq!(c.bootstrap.pending_name, None);
+
+        //the next connection tries again and takes the name
+        c.bootstrap.reset();
+        c.check_name();
+        let name = c.bootstrap.pending_name.clone().unwrap();
+        c.on_nameresult(packets::server_packets::NameResult {
+            success: true,
+            error_message: String::new(),
+        });
+        assert_eq!(c.ign, name);
+        //our stats still say NAMECHOSEN 0 until the server catches up, but we don't ask again
+        c.check_name();
+        c.on_update(packets::server_packets::Update {
+            tiles: Vec::new(),
+            new_objs: Vec::new(),
+            drops: Vec::new(),
+        });
+        assert_eq!(c.name_chosen(), Some(false));
+        assert_eq!(sent(&m, packets::CHOOSENAME), 4);
+        assert_eq!(c.bootstrap.pending_name, None);
+
+        //an account without a living character gets one created instead of loaded
+        c.config.bootstrap.create_character = true;
+        assert!(!c.needs_character()); //no char list, nothing to go by
+        c.account_data = Some(web::CharList::default());
+        assert!(c.needs_character());
+        c.create_character();
+        assert_eq!(sent(&m, packets::CREATE), 1);
+        c.on_create_success(packets::server_packets::CreateSuccess {
+            object_id: 1,
+            char_id: 7,
+        });
+        assert_eq!(c.base.char_id, 7);
+        assert!(!c.needs_character());
+    }
 }
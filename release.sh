@@ -1 +1 @@
-cargo build --release && cp ./target/release/bots ./botz && ./botz
+cargo build --release && cp ./target/release/bots ./botz && ./botz "$@"